@@ -0,0 +1,659 @@
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
+use crate::error::{CoapBlockError, CoapBuildError, CoapParseError};
+use crate::{Complete, Message, MessageBuilder, NeedsPayload, OptionNumber};
+
+/// Largest block number that can be encoded in a Block1/Block2 option (20 bits).
+pub(crate) const MAX_BLOCK_NUMBER: u32 = (1 << 20) - 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, IntoPrimitive, TryFromPrimitive)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+/// Block size, encoded as the size exponent (SZX) of a Block1/Block2 option.
+///
+/// Source: [RFC 7959 2.2](https://datatracker.ietf.org/doc/html/rfc7959#section-2.2)
+pub enum BlockSize {
+    /// 16 byte blocks (SZX 0)
+    S16 = 0,
+    /// 32 byte blocks (SZX 1)
+    S32 = 1,
+    /// 64 byte blocks (SZX 2)
+    S64 = 2,
+    /// 128 byte blocks (SZX 3)
+    S128 = 3,
+    /// 256 byte blocks (SZX 4)
+    S256 = 4,
+    /// 512 byte blocks (SZX 5)
+    S512 = 5,
+    /// 1024 byte blocks (SZX 6)
+    S1024 = 6,
+    /// Block-wise Extension for Reliable Transport (SZX 7). Each payload carries one or more
+    /// 1024 byte blocks, and is only valid over reliable transports.
+    ///
+    /// Source: [RFC 8323 6](https://datatracker.ietf.org/doc/html/rfc8323#section-6)
+    Bert = 7,
+}
+
+impl BlockSize {
+    /// Returns the block size in bytes. BERT blocks are counted in units of 1024 bytes.
+    pub fn size(self) -> usize {
+        match self {
+            BlockSize::Bert => 1024,
+            szx => 16 << u8::from(szx),
+        }
+    }
+
+    /// Returns the block size for an exact size in bytes, which must be a power of two between
+    /// 16 and 1024.
+    pub fn from_size(size: usize) -> Option<Self> {
+        if !(16..=1024).contains(&size) || !size.is_power_of_two() {
+            return None;
+        }
+
+        BlockSize::try_from((size.trailing_zeros() - 4) as u8).ok()
+    }
+
+    /// Returns the largest (non-BERT) block size that does not exceed `max` bytes.
+    pub fn fitting(max: usize) -> Option<Self> {
+        if max < 16 {
+            return None;
+        }
+
+        let exponent = (usize::BITS - 1 - max.leading_zeros()).min(10);
+        BlockSize::try_from((exponent - 4) as u8).ok()
+    }
+}
+
+/// Value of a Block1 or Block2 option.
+///
+/// Source: [RFC 7959 2.2](https://datatracker.ietf.org/doc/html/rfc7959#section-2.2)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BlockValue {
+    /// Relative number of the block within the sequence of blocks (NUM)
+    pub num: u32,
+    /// Whether more blocks follow this one (M)
+    pub more: bool,
+    /// Size of the block (SZX)
+    pub size: BlockSize,
+}
+
+impl BlockValue {
+    /// Decode a block option value. Size exponent 7 is only accepted if `allow_bert` is set.
+    pub(crate) fn decode(value: &[u8], allow_bert: bool) -> Result<Self, CoapParseError> {
+        if value.len() > 3 {
+            return Err(CoapParseError::InvalidBlockOption);
+        }
+
+        let raw = value
+            .iter()
+            .fold(0u32, |acc, &byte| (acc << 8) | byte as u32);
+
+        let size = BlockSize::try_from((raw & 0x07) as u8).unwrap_or(BlockSize::Bert);
+        if size == BlockSize::Bert && !allow_bert {
+            return Err(CoapParseError::ReservedBlockSize);
+        }
+
+        Ok(BlockValue {
+            num: raw >> 4,
+            more: raw & 0x08 != 0,
+            size,
+        })
+    }
+
+    /// Encode the block option value as an unsigned integer.
+    pub(crate) fn encode(&self) -> u32 {
+        (self.num << 4) | (u32::from(self.more) << 3) | u32::from(u8::from(self.size))
+    }
+
+    /// Returns the byte offset of this block within the full body.
+    pub fn offset(&self) -> usize {
+        self.num as usize * self.size.size()
+    }
+}
+
+/// Splits a body into blocks for a block-wise transfer.
+///
+/// Source: [RFC 7959 2](https://datatracker.ietf.org/doc/html/rfc7959#section-2)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BlockwiseSender<'a> {
+    body: &'a [u8],
+    size: BlockSize,
+}
+
+impl<'a> BlockwiseSender<'a> {
+    /// Create a sender for `body`, using `size` as the preferred block size.
+    pub fn new(body: &'a [u8], size: BlockSize) -> Self {
+        Self { body, size }
+    }
+
+    /// Returns the total size of the body in bytes.
+    pub fn total_size(&self) -> usize {
+        self.body.len()
+    }
+
+    /// Returns the number of blocks needed to transfer the body at the preferred block size.
+    pub fn block_count(&self) -> u32 {
+        self.body.len().div_ceil(self.size.size()).max(1) as u32
+    }
+
+    /// Returns the block with number `num` at the preferred block size.
+    pub fn block(&self, num: u32) -> Result<(BlockValue, &'a [u8]), CoapBlockError> {
+        self.block_with_size(num, self.size)
+    }
+
+    /// Returns the block requested by a Block2 option, using the smaller of the requested and the
+    /// preferred block size. Without a requested block, the first block is returned.
+    ///
+    /// Source: [RFC 7959 2.4](https://datatracker.ietf.org/doc/html/rfc7959#section-2.4)
+    pub fn negotiate(
+        &self,
+        requested: Option<BlockValue>,
+    ) -> Result<(BlockValue, &'a [u8]), CoapBlockError> {
+        let Some(requested) = requested else {
+            return self.block(0);
+        };
+
+        let size = requested.size.min(self.size);
+        let num = requested.offset() / size.size();
+
+        self.block_with_size(num as u32, size)
+    }
+
+    /// Add the block with number `num` to a message, together with the matching Size1/Size2
+    /// option on the first block.
+    ///
+    /// `option_number` must be [`OptionNumber::Block1`], [`OptionNumber::Block2`] or one of their
+    /// Q-Block counterparts, and only options numbered below both the block option and its size
+    /// option may be added to the builder beforehand.
+    pub fn write_block<'buf>(
+        &self,
+        builder: MessageBuilder<'buf, NeedsPayload>,
+        option_number: OptionNumber,
+        num: u32,
+    ) -> Result<MessageBuilder<'buf, Complete>, CoapBlockError> {
+        let (block, data) = self.block(num)?;
+        Self::write(builder, option_number, block, data, self.body.len())
+    }
+
+    /// Answer a request with the block of the body it asks for via its Block2 option, adding the
+    /// Block2 option, a Size2 option on the first block, and the block payload.
+    ///
+    /// Only options numbered below 23 (Block2) may be added to the builder beforehand.
+    pub fn write_block2_response<'buf>(
+        &self,
+        builder: MessageBuilder<'buf, NeedsPayload>,
+        request: &Message<'_>,
+    ) -> Result<MessageBuilder<'buf, Complete>, CoapBlockError> {
+        let requested = request
+            .options
+            .into_iter()
+            .find(|opt| opt.number == OptionNumber::Block2)
+            .map(|opt| opt.as_block())
+            .transpose()
+            .map_err(CoapBlockError::InvalidBlockOption)?;
+
+        let (block, data) = self.negotiate(requested)?;
+        Self::write(builder, OptionNumber::Block2, block, data, self.body.len())
+    }
+
+    fn block_with_size(
+        &self,
+        num: u32,
+        size: BlockSize,
+    ) -> Result<(BlockValue, &'a [u8]), CoapBlockError> {
+        if num > MAX_BLOCK_NUMBER {
+            return Err(CoapBlockError::BlockOutOfRange(num));
+        }
+
+        let start = num as usize * size.size();
+        if start >= self.body.len() && num != 0 {
+            return Err(CoapBlockError::BlockOutOfRange(num));
+        }
+
+        let end = (start + size.size()).min(self.body.len());
+        let block = BlockValue {
+            num,
+            more: end < self.body.len(),
+            size,
+        };
+
+        Ok((block, &self.body[start..end]))
+    }
+
+    fn write<'buf>(
+        builder: MessageBuilder<'buf, NeedsPayload>,
+        option_number: OptionNumber,
+        block: BlockValue,
+        data: &[u8],
+        total_size: usize,
+    ) -> Result<MessageBuilder<'buf, Complete>, CoapBlockError> {
+        let size_option = match option_number {
+            OptionNumber::Block1 | OptionNumber::QBlock1 => OptionNumber::Size1,
+            _ => OptionNumber::Size2,
+        };
+
+        // Q-Block2 is numbered after Size2, the other block options before their size option.
+        let size_first = u16::from(size_option) < u16::from(option_number);
+        let mut builder = builder;
+        if block.num == 0 && size_first {
+            builder = builder.option_uint(size_option, total_size as u64)?;
+        }
+        builder = builder.option_block(option_number, block)?;
+        if block.num == 0 && !size_first {
+            builder = builder.option_uint(size_option, total_size as u64)?;
+        }
+
+        if data.is_empty() {
+            Ok(builder.no_payload())
+        } else {
+            Ok(builder.payload(data)?)
+        }
+    }
+}
+
+/// Outcome of receiving a block with a [`BlockwiseReceiver`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BlockStatus {
+    /// More blocks are expected. Contains the Block1 value a server should echo in its 2.31
+    /// (Continue) response, carrying the receiver's preferred block size.
+    More(BlockValue),
+    /// The body is complete. Contains the total size of the body.
+    Complete(usize),
+}
+
+/// Reassembles the blocks of a block-wise transfer into a caller-supplied buffer.
+///
+/// Source: [RFC 7959 2](https://datatracker.ietf.org/doc/html/rfc7959#section-2)
+pub struct BlockwiseReceiver<'buf> {
+    buffer: &'buf mut [u8],
+    received: usize,
+    size: BlockSize,
+    preferred: BlockSize,
+}
+
+impl<'buf> BlockwiseReceiver<'buf> {
+    /// Create a receiver reassembling into `buffer`, asking the peer to use blocks no larger than
+    /// `preferred`.
+    pub fn new(buffer: &'buf mut [u8], preferred: BlockSize) -> Self {
+        Self {
+            buffer,
+            received: 0,
+            size: preferred,
+            preferred,
+        }
+    }
+
+    /// Returns the largest body the receiver can reassemble.
+    pub fn max_size(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Returns the part of the body received so far.
+    pub fn body(&self) -> &[u8] {
+        &self.buffer[..self.received]
+    }
+
+    /// Returns the Block2 value a client should request next.
+    pub fn next_block(&self) -> BlockValue {
+        let size = self.size.min(self.preferred);
+
+        BlockValue {
+            num: (self.received / size.size()) as u32,
+            more: false,
+            size,
+        }
+    }
+
+    /// Discard any partially received body.
+    pub fn reset(&mut self) {
+        self.received = 0;
+        self.size = self.preferred;
+    }
+
+    /// Receive a single block. A block with number 0 starts a new body.
+    pub fn receive(
+        &mut self,
+        block: BlockValue,
+        payload: &[u8],
+    ) -> Result<BlockStatus, CoapBlockError> {
+        if block.num == 0 {
+            self.reset();
+        }
+
+        if block.offset() != self.received {
+            return Err(CoapBlockError::Incomplete(self.received));
+        }
+
+        let valid_length = match (block.more, block.size) {
+            (true, BlockSize::Bert) => !payload.is_empty() && payload.len().is_multiple_of(1024),
+            (true, size) => payload.len() == size.size(),
+            (false, BlockSize::Bert) => true,
+            (false, size) => payload.len() <= size.size(),
+        };
+
+        if !valid_length {
+            return Err(CoapBlockError::UnexpectedBlockLength(payload.len()));
+        }
+
+        if self.received + payload.len() > self.buffer.len() {
+            return Err(CoapBlockError::TooLarge(self.buffer.len()));
+        }
+
+        self.buffer[self.received..self.received + payload.len()].copy_from_slice(payload);
+        self.received += payload.len();
+        self.size = block.size;
+
+        if !block.more {
+            return Ok(BlockStatus::Complete(self.received));
+        }
+
+        Ok(BlockStatus::More(BlockValue {
+            num: block.num,
+            more: true,
+            size: block.size.min(self.preferred),
+        }))
+    }
+
+    /// Receive the block carried by a parsed message in its Block1 or Block2 option (as given by
+    /// `option_number`). A message without the block option is treated as a complete body.
+    ///
+    /// A Size1/Size2 option announcing a body larger than the buffer is rejected up front.
+    pub fn receive_message(
+        &mut self,
+        message: &Message<'_>,
+        option_number: OptionNumber,
+    ) -> Result<BlockStatus, CoapBlockError> {
+        let size_option = match option_number {
+            OptionNumber::Block1 => OptionNumber::Size1,
+            _ => OptionNumber::Size2,
+        };
+
+        let mut block = None;
+        for option in message.options {
+            if option.number == option_number {
+                block = Some(
+                    option
+                        .as_block()
+                        .map_err(CoapBlockError::InvalidBlockOption)?,
+                );
+            } else if option.number == size_option
+                && option
+                    .as_uint()
+                    .is_some_and(|size| size > self.buffer.len() as u64)
+            {
+                return Err(CoapBlockError::TooLarge(self.buffer.len()));
+            }
+        }
+
+        let payload = message.payload.unwrap_or_default();
+        if let Some(block) = block {
+            return self.receive(block, payload);
+        }
+
+        self.reset();
+        let max_size = self.buffer.len();
+        let dst = self
+            .buffer
+            .get_mut(..payload.len())
+            .ok_or(CoapBlockError::TooLarge(max_size))?;
+        dst.copy_from_slice(payload);
+        self.received = payload.len();
+
+        Ok(BlockStatus::Complete(self.received))
+    }
+
+    /// Add the options required by the response to a failed transfer, i.e. the Size1 option
+    /// carried by a 4.13 (Request Entity Too Large) response. The response code itself is given
+    /// by [`CoapBlockError::response_code`].
+    ///
+    /// Source: [RFC 7959 2.9.3](https://datatracker.ietf.org/doc/html/rfc7959#section-2.9.3)
+    pub fn write_error_options<'b>(
+        builder: MessageBuilder<'b, NeedsPayload>,
+        error: CoapBlockError,
+    ) -> Result<MessageBuilder<'b, NeedsPayload>, CoapBuildError> {
+        match error {
+            CoapBlockError::TooLarge(max) => builder.option_uint(OptionNumber::Size1, max as u64),
+            _ => Ok(builder),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_size_conversions() {
+        assert_eq!(BlockSize::S16.size(), 16);
+        assert_eq!(BlockSize::S1024.size(), 1024);
+        assert_eq!(BlockSize::Bert.size(), 1024);
+
+        assert_eq!(BlockSize::from_size(64), Some(BlockSize::S64));
+        assert_eq!(BlockSize::from_size(100), None);
+        assert_eq!(BlockSize::from_size(2048), None);
+
+        assert_eq!(BlockSize::fitting(15), None);
+        assert_eq!(BlockSize::fitting(100), Some(BlockSize::S64));
+        assert_eq!(BlockSize::fitting(4096), Some(BlockSize::S1024));
+    }
+
+    #[test]
+    fn block_value_roundtrip() {
+        let block = BlockValue {
+            num: 1234,
+            more: true,
+            size: BlockSize::S256,
+        };
+
+        let encoded = block.encode();
+        let bytes = encoded.to_be_bytes();
+        assert_eq!(BlockValue::decode(&bytes[1..], false), Ok(block));
+        assert_eq!(block.offset(), 1234 * 256);
+    }
+
+    #[test]
+    fn block_value_reserved() {
+        assert_eq!(
+            BlockValue::decode(&[0x07], false),
+            Err(CoapParseError::ReservedBlockSize)
+        );
+        assert_eq!(
+            BlockValue::decode(&[0x07], true).map(|b| b.size),
+            Ok(BlockSize::Bert)
+        );
+        assert_eq!(
+            BlockValue::decode(&[0, 0, 0, 0], false),
+            Err(CoapParseError::InvalidBlockOption)
+        );
+        assert_eq!(
+            BlockValue::decode(&[], false),
+            Ok(BlockValue {
+                num: 0,
+                more: false,
+                size: BlockSize::S16
+            })
+        );
+    }
+
+    #[test]
+    fn sender_splits_body() {
+        let body = [0xAB; 100];
+        let sender = BlockwiseSender::new(&body, BlockSize::S32);
+
+        assert_eq!(sender.block_count(), 4);
+
+        let (block, data) = sender.block(3).unwrap();
+        assert_eq!(data.len(), 4);
+        assert!(!block.more);
+
+        let (block, data) = sender.block(0).unwrap();
+        assert_eq!(data.len(), 32);
+        assert!(block.more);
+
+        assert_eq!(sender.block(4), Err(CoapBlockError::BlockOutOfRange(4)));
+    }
+
+    #[test]
+    fn sender_negotiates_smaller_size() {
+        let body = [0; 256];
+        let sender = BlockwiseSender::new(&body, BlockSize::S64);
+
+        // Client asks for the second 32 byte block.
+        let requested = BlockValue {
+            num: 1,
+            more: false,
+            size: BlockSize::S32,
+        };
+        let (block, data) = sender.negotiate(Some(requested)).unwrap();
+        assert_eq!(block.size, BlockSize::S32);
+        assert_eq!(block.num, 1);
+        assert_eq!(data.len(), 32);
+
+        // Client asks for the second 128 byte block, which is served as the third 64 byte block.
+        let requested = BlockValue {
+            num: 1,
+            more: false,
+            size: BlockSize::S128,
+        };
+        let (block, _) = sender.negotiate(Some(requested)).unwrap();
+        assert_eq!(block.size, BlockSize::S64);
+        assert_eq!(block.num, 2);
+    }
+
+    #[test]
+    fn block2_transfer_roundtrip() {
+        use crate::{MessageType, RequestCode, ResponseCode};
+
+        let body: [u8; 70] = core::array::from_fn(|i| i as u8);
+        let sender = BlockwiseSender::new(&body, BlockSize::S32);
+
+        let mut reassembly = [0; 128];
+        let mut receiver = BlockwiseReceiver::new(&mut reassembly, BlockSize::S1024);
+
+        let mut status = BlockStatus::More(receiver.next_block());
+        let mut requests = 0;
+
+        while let BlockStatus::More(_) = status {
+            let mut request_buf = [0; 32];
+            let request = MessageBuilder::new(&mut request_buf)
+                .unwrap()
+                .request(MessageType::Confirmable, RequestCode::Get)
+                .message_id(requests)
+                .no_token()
+                .unwrap()
+                .option_block(OptionNumber::Block2, receiver.next_block())
+                .unwrap()
+                .no_payload()
+                .build();
+            let request = Message::parse(request).unwrap();
+
+            let mut response_buf = [0; 64];
+            let builder = MessageBuilder::new(&mut response_buf)
+                .unwrap()
+                .response(MessageType::Acknowledgement, ResponseCode::Content)
+                .message_id(requests)
+                .no_token()
+                .unwrap();
+            let response = sender
+                .write_block2_response(builder, &request)
+                .unwrap()
+                .build();
+            let response = Message::parse(response).unwrap();
+
+            let size2 = response
+                .options
+                .into_iter()
+                .find(|opt| opt.number == OptionNumber::Size2)
+                .and_then(|opt| opt.as_uint());
+            assert_eq!(size2.is_some(), requests == 0);
+
+            status = receiver
+                .receive_message(&response, OptionNumber::Block2)
+                .unwrap();
+            requests += 1;
+        }
+
+        assert_eq!(requests, 3);
+        assert_eq!(status, BlockStatus::Complete(70));
+        assert_eq!(receiver.body(), &body);
+
+        // A response without Block2 is the whole body, and keeps the preferred block size.
+        let mut response_buf = [0; 32];
+        let response = MessageBuilder::new(&mut response_buf)
+            .unwrap()
+            .response(MessageType::Acknowledgement, ResponseCode::Content)
+            .message_id(requests)
+            .no_token()
+            .unwrap()
+            .payload(b"plain")
+            .unwrap()
+            .build();
+        let response = Message::parse(response).unwrap();
+        assert_eq!(
+            receiver.receive_message(&response, OptionNumber::Block2),
+            Ok(BlockStatus::Complete(5))
+        );
+        assert_eq!(receiver.body(), b"plain");
+        assert_eq!(receiver.size, BlockSize::S1024);
+    }
+
+    #[test]
+    fn receiver_detects_gaps_and_overflow() {
+        let mut reassembly = [0; 40];
+        let mut receiver = BlockwiseReceiver::new(&mut reassembly, BlockSize::S16);
+
+        let first = BlockValue {
+            num: 0,
+            more: true,
+            size: BlockSize::S32,
+        };
+        // The server prefers 16 byte blocks and says so in its echo.
+        assert_eq!(
+            receiver.receive(first, &[0; 32]),
+            Ok(BlockStatus::More(BlockValue {
+                num: 0,
+                more: true,
+                size: BlockSize::S16
+            }))
+        );
+
+        let gap = BlockValue {
+            num: 3,
+            more: true,
+            size: BlockSize::S16,
+        };
+        let error = receiver.receive(gap, &[0; 16]).unwrap_err();
+        assert_eq!(error, CoapBlockError::Incomplete(32));
+        assert_eq!(
+            error.response_code(),
+            crate::ResponseCode::RequestEntityIncomplete
+        );
+
+        let overflow = BlockValue {
+            num: 2,
+            more: true,
+            size: BlockSize::S16,
+        };
+        let error = receiver.receive(overflow, &[0; 16]).unwrap_err();
+        assert_eq!(error, CoapBlockError::TooLarge(40));
+
+        let mut tx_buf = [0; 32];
+        let builder = MessageBuilder::new(&mut tx_buf)
+            .unwrap()
+            .response(crate::MessageType::Acknowledgement, error.response_code())
+            .message_id(1)
+            .no_token()
+            .unwrap();
+        let packet = BlockwiseReceiver::write_error_options(builder, error)
+            .unwrap()
+            .no_payload()
+            .build();
+        let message = Message::parse(packet).unwrap();
+        let size1 = message.options.into_iter().next().unwrap();
+        assert_eq!(size1.number, OptionNumber::Size1);
+        assert_eq!(size1.as_uint(), Some(40));
+    }
+}
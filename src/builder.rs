@@ -1,13 +1,22 @@
 use core::marker::PhantomData;
-
-use crate::{MessageType, RequestCode, ResponseCode, Version, coap_code, error::CoapBuildError};
+use core::time::Duration;
+
+use crate::block::{BlockValue, MAX_BLOCK_NUMBER};
+use crate::observe::{OBSERVE_DEREGISTER, OBSERVE_REGISTER, SEQUENCE_MASK};
+use crate::signal::{
+    ALTERNATIVE_ADDRESS, BAD_CSM_OPTION, BLOCK_WISE_TRANSFER, CUSTODY, HOLD_OFF, MAX_MESSAGE_SIZE,
+};
+use crate::{
+    Message, MessageType, OptionNumber, RequestCode, ResponseCode, SignalCode, Version, coap_code,
+    error::CoapBuildError,
+};
 
 type BuilderResult<'buf, T> = core::result::Result<MessageBuilder<'buf, T>, CoapBuildError>;
 
 /// Converts an unsigned integer to minimal byte representation (big-endian, no leading zeros).
 /// According to RFC 7252 Section 3.2, 0 is represented as an empty slice.
 /// Returns a tuple of (byte array, start index) where the meaningful bytes are from start..8.
-fn uint_to_minimal_bytes(value: u64) -> ([u8; 8], usize) {
+pub(crate) fn uint_to_minimal_bytes(value: u64) -> ([u8; 8], usize) {
     if value == 0 {
         return ([0; 8], 8); // Empty slice: start at end
     }
@@ -19,6 +28,45 @@ fn uint_to_minimal_bytes(value: u64) -> ([u8; 8], usize) {
     (bytes, leading_zeros)
 }
 
+/// Largest option value length that can be encoded in an option header.
+pub(crate) const MAX_OPTION_LENGTH: usize = 269 + u16::MAX as usize;
+
+/// Splits an option delta or length into its 4-bit field, writing any extended bytes to `ext`.
+/// Returns the field and the number of extended bytes.
+fn extended_field(value: usize, ext: &mut [u8]) -> (u8, usize) {
+    match value {
+        0..=12 => (value as u8, 0),
+        13..=268 => {
+            ext[0] = (value - 13) as u8;
+            (13, 1)
+        }
+        _ => {
+            ext[..2].copy_from_slice(&((value - 269) as u16).to_be_bytes());
+            (14, 2)
+        }
+    }
+}
+
+/// Encodes the header of an option with the given delta and value length into `header`,
+/// returning the number of bytes used.
+///
+/// Source: [RFC 7252 3.1](https://datatracker.ietf.org/doc/html/rfc7252#section-3.1)
+pub(crate) fn encode_option_header(
+    delta: u16,
+    len: usize,
+    header: &mut [u8; 5],
+) -> Result<usize, CoapBuildError> {
+    if len > MAX_OPTION_LENGTH {
+        return Err(CoapBuildError::OptionTooLong(len));
+    }
+
+    let (delta_field, delta_ext_len) = extended_field(delta as usize, &mut header[1..]);
+    let (length_field, length_ext_len) = extended_field(len, &mut header[1 + delta_ext_len..]);
+    header[0] = (delta_field << 4) | length_field;
+
+    Ok(1 + delta_ext_len + length_ext_len)
+}
+
 /// State for receiving the buffer.
 pub struct NeedsBuffer;
 /// State for constructing the header.
@@ -32,11 +80,42 @@ pub struct NeedsPayload;
 /// State for completing the packet.
 pub struct Complete;
 
+/// State for setting the code of a message without a type or message ID.
+pub struct NeedsCode;
+/// State for adding the token of a signaling message.
+pub struct NeedsSignalToken;
+/// State for adding signaling options and/or a diagnostic payload.
+pub struct NeedsSignalPayload;
+
+/// Header format of the message being built.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Framing {
+    /// Fixed 4-byte header with type and message ID (RFC 7252).
+    Udp,
+    /// Variable-length header with extended length (RFC 8323 3.2).
+    Tcp,
+    /// Header without length, which is given by the WebSocket frame (RFC 8323 4.2).
+    WebSocket,
+}
+
+impl Framing {
+    /// Space reserved in front of the token for the header. For TCP this fits the largest
+    /// header (length nibble, 4 extended length bytes and code), which is trimmed when building.
+    fn reserved_header_len(self) -> usize {
+        match self {
+            Framing::Udp => 4,
+            Framing::Tcp => 6,
+            Framing::WebSocket => 2,
+        }
+    }
+}
+
 /// Builder for CoAP messages.
 pub struct MessageBuilder<'buf, State> {
     buffer: &'buf mut [u8],
     offset: usize,
     last_option_number: u16,
+    framing: Framing,
     _state: PhantomData<State>,
 }
 
@@ -45,6 +124,16 @@ impl<'buf, State> MessageBuilder<'buf, State> {
     pub fn remaining_buffer(&self) -> usize {
         self.buffer.len() - self.offset
     }
+
+    fn into_state<Next>(self) -> MessageBuilder<'buf, Next> {
+        MessageBuilder {
+            buffer: self.buffer,
+            offset: self.offset,
+            last_option_number: self.last_option_number,
+            framing: self.framing,
+            _state: PhantomData,
+        }
+    }
 }
 
 impl<'buf> MessageBuilder<'buf, NeedsBuffer> {
@@ -58,11 +147,79 @@ impl<'buf> MessageBuilder<'buf, NeedsBuffer> {
             buffer,
             offset: 0,
             last_option_number: 0,
+            framing: Framing::Udp,
+            _state: PhantomData,
+        })
+    }
+
+    /// Create a new packet builder for CoAP over TCP or TLS, whose header carries the message
+    /// length but no type or message ID. The buffer must be at least 6 bytes long.
+    ///
+    /// Source: [RFC 8323 3.2](https://datatracker.ietf.org/doc/html/rfc8323#section-3.2)
+    pub fn new_tcp(buffer: &'buf mut [u8]) -> BuilderResult<'buf, NeedsCode> {
+        Self::new_framed(buffer, Framing::Tcp)
+    }
+
+    /// Create a new packet builder for CoAP over WebSockets, whose header carries neither the
+    /// message length nor a type or message ID. The buffer must be at least 2 bytes long.
+    ///
+    /// Source: [RFC 8323 4.2](https://datatracker.ietf.org/doc/html/rfc8323#section-4.2)
+    pub fn new_websocket(buffer: &'buf mut [u8]) -> BuilderResult<'buf, NeedsCode> {
+        Self::new_framed(buffer, Framing::WebSocket)
+    }
+
+    fn new_framed(buffer: &'buf mut [u8], framing: Framing) -> BuilderResult<'buf, NeedsCode> {
+        if buffer.len() < framing.reserved_header_len() {
+            return Err(CoapBuildError::BufferTooSmall);
+        }
+
+        Ok(MessageBuilder {
+            buffer,
+            offset: 0,
+            last_option_number: 0,
+            framing,
             _state: PhantomData,
         })
     }
 }
 
+impl<'buf> MessageBuilder<'buf, NeedsCode> {
+    /// Set the code of the message.
+    pub fn code(mut self, code: impl Into<u8>) -> MessageBuilder<'buf, NeedsToken> {
+        let header_len = self.framing.reserved_header_len();
+
+        // len 0..4 (set when building) | token_len 4..8 (set later)
+        self.buffer[0] = 0;
+        self.buffer[header_len - 1] = code.into();
+
+        self.offset = header_len;
+
+        self.into_state()
+    }
+
+    /// Convenience method for constructing a request.
+    pub fn request(self, code: RequestCode) -> MessageBuilder<'buf, NeedsToken> {
+        self.code(code)
+    }
+
+    /// Convenience method for constructing a response.
+    pub fn response(self, code: ResponseCode) -> MessageBuilder<'buf, NeedsToken> {
+        self.code(code)
+    }
+
+    /// Convenience method for constructing an empty message.
+    pub fn empty(self) -> MessageBuilder<'buf, NeedsToken> {
+        self.code(coap_code!(0, 00))
+    }
+
+    /// Convenience method for constructing a signaling message.
+    ///
+    /// Source: [RFC 8323 5](https://datatracker.ietf.org/doc/html/rfc8323#section-5)
+    pub fn signal(self, code: SignalCode) -> MessageBuilder<'buf, NeedsSignalToken> {
+        self.code(code).into_state()
+    }
+}
+
 impl<'buf> MessageBuilder<'buf, NeedsHeader> {
     /// Construct a header for a CoAP packet.
     pub fn header(
@@ -76,12 +233,7 @@ impl<'buf> MessageBuilder<'buf, NeedsHeader> {
 
         self.offset = 2;
 
-        MessageBuilder {
-            buffer: self.buffer,
-            offset: self.offset,
-            last_option_number: self.last_option_number,
-            _state: PhantomData,
-        }
+        self.into_state()
     }
 
     /// Convenience method for constructing a request packet.
@@ -111,6 +263,69 @@ impl<'buf> MessageBuilder<'buf, NeedsHeader> {
     pub fn ping(self) -> MessageBuilder<'buf, NeedsMessageId> {
         self.header(MessageType::Confirmable, coap_code!(0, 00))
     }
+
+    /// Convenience method for constructing an empty Acknowledgement for the given message.
+    pub fn empty_ack(self, message: &Message<'_>) -> BuilderResult<'buf, Complete> {
+        Ok(self
+            .empty(MessageType::Acknowledgement)
+            .message_id(message.message_id)
+            .no_token()?
+            .no_payload())
+    }
+
+    /// Convenience method for constructing a Reset for the given message.
+    pub fn empty_reset(self, message: &Message<'_>) -> BuilderResult<'buf, Complete> {
+        Ok(self
+            .empty(MessageType::Reset)
+            .message_id(message.message_id)
+            .no_token()?
+            .no_payload())
+    }
+
+    /// Construct the reply rejecting a message that carries an unrecognized critical option: a
+    /// piggybacked 4.02 (Bad Option) response for a Confirmable request, and a Reset for any
+    /// other Confirmable message. Returns `None` for other messages, which must be silently
+    /// ignored.
+    ///
+    /// Source: [RFC 7252 5.4.1](https://datatracker.ietf.org/doc/html/rfc7252#section-5.4.1)
+    pub fn bad_option_reply(
+        self,
+        message: &Message<'_>,
+    ) -> Result<Option<MessageBuilder<'buf, Complete>>, CoapBuildError> {
+        if message.message_type != MessageType::Confirmable {
+            return Ok(None);
+        }
+
+        if !message.is_request() {
+            return self.empty_reset(message).map(Some);
+        }
+
+        Ok(Some(
+            self.reply(message, ResponseCode::BadOption, message.message_id)?
+                .no_payload(),
+        ))
+    }
+
+    /// Convenience method for constructing a response to the given request. A Confirmable request
+    /// is answered with a piggybacked response in its Acknowledgement, and any other request with
+    /// a Non-confirmable response carrying `message_id`.
+    ///
+    /// Source: [RFC 7252 5.2](https://datatracker.ietf.org/doc/html/rfc7252#section-5.2)
+    pub fn reply(
+        self,
+        request: &Message<'_>,
+        code: ResponseCode,
+        message_id: u16,
+    ) -> BuilderResult<'buf, NeedsPayload> {
+        let (msg_type, message_id) = match request.message_type {
+            MessageType::Confirmable => (MessageType::Acknowledgement, request.message_id),
+            _ => (MessageType::NonConfirmable, message_id),
+        };
+
+        self.response(msg_type, code)
+            .message_id(message_id)
+            .token(request.token)
+    }
 }
 
 impl<'buf> MessageBuilder<'buf, NeedsMessageId> {
@@ -119,12 +334,7 @@ impl<'buf> MessageBuilder<'buf, NeedsMessageId> {
         self.buffer[self.offset..self.offset + 2].copy_from_slice(&id.to_be_bytes());
         self.offset += 2;
 
-        MessageBuilder {
-            buffer: self.buffer,
-            offset: self.offset,
-            last_option_number: self.last_option_number,
-            _state: PhantomData,
-        }
+        self.into_state()
     }
 }
 
@@ -146,33 +356,33 @@ impl<'buf> MessageBuilder<'buf, NeedsToken> {
         self.buffer[self.offset..self.offset + token_len].copy_from_slice(token);
         self.offset += token_len;
 
-        Ok(MessageBuilder {
-            buffer: self.buffer,
-            offset: self.offset,
-            last_option_number: self.last_option_number,
-            _state: PhantomData,
-        })
+        Ok(self.into_state())
     }
 
     /// Skip adding a token (uses a zero-length token)
     pub fn no_token(self) -> BuilderResult<'buf, NeedsPayload> {
         // TKL is already set to 0, just transition state.
 
-        Ok(MessageBuilder {
-            buffer: self.buffer,
-            offset: self.offset,
-            last_option_number: self.last_option_number,
-            _state: PhantomData,
-        })
+        Ok(self.into_state())
     }
 }
 
 impl<'buf> MessageBuilder<'buf, NeedsPayload> {
     /// Add an option to the packet.
     pub fn option(
-        mut self,
+        self,
         option_number: impl Into<u16>,
         value: &[u8],
+    ) -> BuilderResult<'buf, NeedsPayload> {
+        self.option_with(option_number, value.len(), |dst| dst.copy_from_slice(value))
+    }
+
+    /// Add an option whose `len` byte value is written in place by `write`.
+    pub(crate) fn option_with(
+        mut self,
+        option_number: impl Into<u16>,
+        len: usize,
+        write: impl FnOnce(&mut [u8]),
     ) -> BuilderResult<'buf, NeedsPayload> {
         let option_number = option_number.into();
 
@@ -180,42 +390,21 @@ impl<'buf> MessageBuilder<'buf, NeedsPayload> {
             return Err(CoapBuildError::OptionNumberOutOfOrder);
         }
 
-        let delta = option_number - self.last_option_number;
-
-        let (delta_field, delta_ext) = match delta {
-            0..=12 => (delta as u8, &[][..]),
-            13..=268 => (13, &((delta - 13) as u8).to_be_bytes()[..]),
-            269.. => (14, &(delta - 269).to_be_bytes()[..]),
-        };
-
-        let (length_field, length_ext) = match value.len() {
-            0..=12 => (value.len() as u8, &[][..]),
-            13..=268 => (13, &((value.len() - 13) as u8).to_be_bytes()[..]),
-            269.. => (14, &(value.len() - 269).to_be_bytes()[..]),
-        };
-
-        let option_header_len = 1 + delta_ext.len() + length_ext.len();
-        let option_len = option_header_len + value.len();
+        let mut header = [0; 5];
+        let header_len =
+            encode_option_header(option_number - self.last_option_number, len, &mut header)?;
 
-        if self.offset + option_len > self.buffer.len() {
+        if self.offset + header_len + len > self.buffer.len() {
             return Err(CoapBuildError::BufferTooSmall);
         }
 
-        // Write the header byte
-        self.buffer[self.offset] = (delta_field << 4) | length_field;
-        self.offset += 1;
-
-        // Write the delta extension. If it's empty, nothing will be written.
-        self.buffer[self.offset..self.offset + delta_ext.len()].copy_from_slice(delta_ext);
-        self.offset += delta_ext.len();
-
-        // Write the length extension. If it's empty, nothing will be written.
-        self.buffer[self.offset..self.offset + length_ext.len()].copy_from_slice(length_ext);
-        self.offset += length_ext.len();
+        // Write the header byte and any delta and length extensions
+        self.buffer[self.offset..self.offset + header_len].copy_from_slice(&header[..header_len]);
+        self.offset += header_len;
 
         // Write the value
-        self.buffer[self.offset..self.offset + value.len()].copy_from_slice(value);
-        self.offset += value.len();
+        write(&mut self.buffer[self.offset..self.offset + len]);
+        self.offset += len;
 
         self.last_option_number = option_number;
 
@@ -244,13 +433,59 @@ impl<'buf> MessageBuilder<'buf, NeedsPayload> {
         self.option(option_number, &bytes[start..])
     }
 
+    /// Add a Block1 or Block2 option.
+    ///
+    /// Source: [RFC 7959 2.2](https://datatracker.ietf.org/doc/html/rfc7959#section-2.2)
+    pub fn option_block(
+        self,
+        option_number: impl Into<u16>,
+        block: BlockValue,
+    ) -> BuilderResult<'buf, NeedsPayload> {
+        if block.num > MAX_BLOCK_NUMBER {
+            return Err(CoapBuildError::BlockNumberTooLarge(block.num));
+        }
+
+        self.option_uint(option_number, block.encode())
+    }
+
+    /// Add an Observe option registering the client as an observer of the target resource.
+    ///
+    /// Source: [RFC 7641 2](https://datatracker.ietf.org/doc/html/rfc7641#section-2)
+    pub fn observe_register(self) -> BuilderResult<'buf, NeedsPayload> {
+        self.option_uint(OptionNumber::Observe, OBSERVE_REGISTER)
+    }
+
+    /// Add an Observe option removing the client from the observers of the target resource.
+    ///
+    /// Source: [RFC 7641 2](https://datatracker.ietf.org/doc/html/rfc7641#section-2)
+    pub fn observe_deregister(self) -> BuilderResult<'buf, NeedsPayload> {
+        self.option_uint(OptionNumber::Observe, OBSERVE_DEREGISTER)
+    }
+
+    /// Add an Observe option carrying a notification sequence number.
+    /// Only the lower 24 bits of the sequence number are encoded, so it wraps around as required.
+    ///
+    /// Source: [RFC 7641 4.4](https://datatracker.ietf.org/doc/html/rfc7641#section-4.4)
+    pub fn observe_sequence(self, sequence: u32) -> BuilderResult<'buf, NeedsPayload> {
+        self.option_uint(OptionNumber::Observe, sequence & SEQUENCE_MASK)
+    }
+
     /// Add a payload to the packet.
-    pub fn payload(mut self, payload: &[u8]) -> BuilderResult<'buf, Complete> {
-        if payload.is_empty() {
+    pub fn payload(self, payload: &[u8]) -> BuilderResult<'buf, Complete> {
+        self.payload_with(payload.len(), |dst| dst.copy_from_slice(payload))
+    }
+
+    /// Add a `len` byte payload which is written in place by `write`.
+    pub(crate) fn payload_with(
+        mut self,
+        len: usize,
+        write: impl FnOnce(&mut [u8]),
+    ) -> BuilderResult<'buf, Complete> {
+        if len == 0 {
             return Err(CoapBuildError::PayloadMarkerWithoutPayload);
         }
 
-        if self.offset + 1 + payload.len() > self.buffer.len() {
+        if self.offset + 1 + len > self.buffer.len() {
             return Err(CoapBuildError::BufferTooSmall);
         }
 
@@ -259,44 +494,190 @@ impl<'buf> MessageBuilder<'buf, NeedsPayload> {
         self.offset += 1;
 
         // Write payload
-        self.buffer[self.offset..self.offset + payload.len()].copy_from_slice(payload);
-        self.offset += payload.len();
+        write(&mut self.buffer[self.offset..self.offset + len]);
+        self.offset += len;
 
-        Ok(MessageBuilder {
-            buffer: self.buffer,
-            offset: self.offset,
-            last_option_number: self.last_option_number,
-            _state: PhantomData,
-        })
+        Ok(self.into_state())
     }
 
     /// Skips adding a payload to the packet.
     pub fn no_payload(self) -> MessageBuilder<'buf, Complete> {
-        MessageBuilder {
-            buffer: self.buffer,
-            offset: self.offset,
-            last_option_number: self.last_option_number,
-            _state: PhantomData,
-        }
+        self.into_state()
+    }
+}
+
+impl<'buf> MessageBuilder<'buf, NeedsSignalToken> {
+    /// Add a token of between 0 and 8 bytes.
+    pub fn token(self, token: &[u8]) -> BuilderResult<'buf, NeedsSignalPayload> {
+        self.into_state::<NeedsToken>()
+            .token(token)
+            .map(MessageBuilder::into_state)
+    }
+
+    /// Skip adding a token (uses a zero-length token)
+    pub fn no_token(self) -> BuilderResult<'buf, NeedsSignalPayload> {
+        Ok(self.into_state())
+    }
+}
+
+impl<'buf> MessageBuilder<'buf, NeedsSignalPayload> {
+    /// Add a signaling option to the packet. Signaling option numbers are only meaningful for the
+    /// signal code of the message.
+    ///
+    /// Source: [RFC 8323 5.2](https://datatracker.ietf.org/doc/html/rfc8323#section-5.2)
+    pub fn option(
+        self,
+        option_number: u16,
+        value: &[u8],
+    ) -> BuilderResult<'buf, NeedsSignalPayload> {
+        self.into_state::<NeedsPayload>()
+            .option(option_number, value)
+            .map(MessageBuilder::into_state)
+    }
+
+    /// Add a signaling option with a UTF8 string value.
+    pub fn option_string(
+        self,
+        option_number: u16,
+        value: &str,
+    ) -> BuilderResult<'buf, NeedsSignalPayload> {
+        self.option(option_number, value.as_bytes())
+    }
+
+    /// Add a signaling option with an unsigned integer value, encoded with minimal bytes.
+    pub fn option_uint(
+        self,
+        option_number: u16,
+        value: impl Into<u64>,
+    ) -> BuilderResult<'buf, NeedsSignalPayload> {
+        let (bytes, start) = uint_to_minimal_bytes(value.into());
+        self.option(option_number, &bytes[start..])
+    }
+
+    /// Add a Max-Message-Size option to a CSM signaling message.
+    ///
+    /// Source: [RFC 8323 5.3.1](https://datatracker.ietf.org/doc/html/rfc8323#section-5.3.1)
+    pub fn max_message_size(self, size: u32) -> BuilderResult<'buf, NeedsSignalPayload> {
+        self.option_uint(MAX_MESSAGE_SIZE, size)
+    }
+
+    /// Add a Block-Wise-Transfer option to a CSM signaling message.
+    ///
+    /// Source: [RFC 8323 5.3.2](https://datatracker.ietf.org/doc/html/rfc8323#section-5.3.2)
+    pub fn block_wise_transfer(self) -> BuilderResult<'buf, NeedsSignalPayload> {
+        self.option(BLOCK_WISE_TRANSFER, &[])
+    }
+
+    /// Add a Custody option to a Ping or Pong signaling message.
+    ///
+    /// Source: [RFC 8323 5.4.1](https://datatracker.ietf.org/doc/html/rfc8323#section-5.4.1)
+    pub fn custody(self) -> BuilderResult<'buf, NeedsSignalPayload> {
+        self.option(CUSTODY, &[])
+    }
+
+    /// Add an Alternative-Address option to a Release signaling message. The address is a URI
+    /// authority, such as `example.org:5684`.
+    ///
+    /// Source: [RFC 8323 5.5.1](https://datatracker.ietf.org/doc/html/rfc8323#section-5.5.1)
+    pub fn alternative_address(self, address: &str) -> BuilderResult<'buf, NeedsSignalPayload> {
+        self.option_string(ALTERNATIVE_ADDRESS, address)
+    }
+
+    /// Add a Hold-Off option to a Release signaling message. The duration is sent in whole
+    /// seconds.
+    ///
+    /// Source: [RFC 8323 5.5.2](https://datatracker.ietf.org/doc/html/rfc8323#section-5.5.2)
+    pub fn hold_off(self, duration: Duration) -> BuilderResult<'buf, NeedsSignalPayload> {
+        self.option_uint(HOLD_OFF, duration.as_secs())
+    }
+
+    /// Add a Bad-CSM-Option option to an Abort signaling message, naming the CSM option that
+    /// could not be processed.
+    ///
+    /// Source: [RFC 8323 5.6.1](https://datatracker.ietf.org/doc/html/rfc8323#section-5.6.1)
+    pub fn bad_csm_option(self, option_number: u16) -> BuilderResult<'buf, NeedsSignalPayload> {
+        self.option_uint(BAD_CSM_OPTION, option_number)
+    }
+
+    /// Add a diagnostic payload to the packet.
+    pub fn payload(self, payload: &[u8]) -> BuilderResult<'buf, Complete> {
+        self.into_state::<NeedsPayload>().payload(payload)
+    }
+
+    /// Skips adding a payload to the packet.
+    pub fn no_payload(self) -> MessageBuilder<'buf, Complete> {
+        self.into_state()
     }
 }
 
 impl<'buf> MessageBuilder<'buf, Complete> {
     /// Build the packet.
-    pub fn build(self) -> &'buf [u8] {
-        &self.buffer[..self.offset]
+    pub fn build(mut self) -> &'buf [u8] {
+        let start = self.write_length();
+        let end = self.offset;
+
+        &self.buffer[start..end]
     }
 
     /// Returns the length of the packet.
     pub fn len(&self) -> usize {
-        self.offset
+        self.offset - self.frame_start()
+    }
+
+    /// Returns the length of the options and payload of a TCP message, and how its length is
+    /// encoded in the header as (length nibble, extended length bytes).
+    fn tcp_length(&self) -> (usize, u8, usize) {
+        let token_len = (self.buffer[0] & 0x0F) as usize;
+        let length = self.offset - Framing::Tcp.reserved_header_len() - token_len;
+
+        match length {
+            0..=12 => (length, length as u8, 0),
+            13..=268 => (length, 13, 1),
+            269..=65804 => (length, 14, 2),
+            _ => (length, 15, 4),
+        }
+    }
+
+    /// Returns the index at which the finished message starts within the buffer.
+    fn frame_start(&self) -> usize {
+        match self.framing {
+            Framing::Udp | Framing::WebSocket => 0,
+            Framing::Tcp => {
+                let (_, _, ext_len) = self.tcp_length();
+                // The length byte and extended length are placed right before the code byte.
+                Framing::Tcp.reserved_header_len() - 2 - ext_len
+            }
+        }
+    }
+
+    /// Write the length into the header of a TCP message, returning the index at which the
+    /// finished message starts.
+    fn write_length(&mut self) -> usize {
+        if self.framing != Framing::Tcp {
+            return 0;
+        }
+
+        let (length, nibble, ext_len) = self.tcp_length();
+        let start = self.frame_start();
+        let token_len = self.buffer[0] & 0x0F;
+
+        let ext = match nibble {
+            13 => (length as u32 - 13).to_be_bytes(),
+            14 => (length as u32 - 269).to_be_bytes(),
+            15 => (length as u32 - 65805).to_be_bytes(),
+            _ => [0; 4],
+        };
+
+        self.buffer[start] = (nibble << 4) | token_len;
+        self.buffer[start + 1..start + 1 + ext_len].copy_from_slice(&ext[4 - ext_len..]);
+
+        start
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::OptionNumber;
 
     #[test]
     fn test_no_token() -> Result<(), CoapBuildError> {
@@ -481,4 +862,115 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_observe_sequence_wraps() -> Result<(), CoapBuildError> {
+        let mut tx_buf = [0; 128];
+
+        let packet = MessageBuilder::new(&mut tx_buf)?
+            .response(MessageType::NonConfirmable, ResponseCode::Content)
+            .message_id(0x1234)
+            .token(&[0x01])?
+            .observe_sequence(0x0100_0005)?
+            .no_payload()
+            .build();
+
+        use crate::parser::Message;
+        let msg = Message::parse(packet).unwrap();
+        assert_eq!(msg.observe(), Some(5));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_option_block_number_too_large() -> Result<(), CoapBuildError> {
+        use crate::{BlockSize, BlockValue};
+
+        let mut tx_buf = [0; 128];
+
+        let result = MessageBuilder::new(&mut tx_buf)?
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(0x1234)
+            .no_token()?
+            .option_block(
+                OptionNumber::Block2,
+                BlockValue {
+                    num: 1 << 20,
+                    more: false,
+                    size: BlockSize::S16,
+                },
+            );
+
+        assert!(matches!(
+            result,
+            Err(CoapBuildError::BlockNumberTooLarge(0x100000))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_option_extended_length() -> Result<(), CoapBuildError> {
+        let value = [0xAB; 300];
+        let mut tx_buf = [0; 512];
+
+        let packet = MessageBuilder::new(&mut tx_buf)?
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(0x1234)
+            .no_token()?
+            .option(300u16, &value)?
+            .no_payload()
+            .build();
+
+        // Delta and length both use the 2-byte extension.
+        assert_eq!(&packet[4..9], &[0xEE, 0x00, 0x1F, 0x00, 0x1F]);
+        let msg = Message::parse(packet).unwrap();
+        let option = msg.options.into_iter().next().unwrap();
+        assert_eq!(option.number, OptionNumber::UnknownOption(300));
+        assert_eq!(option.value, &value[..]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bad_option_reply() -> Result<(), CoapBuildError> {
+        use crate::parser::Message;
+
+        let mut rx_buf = [0; 32];
+        let request = MessageBuilder::new(&mut rx_buf)?
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(0x1234)
+            .token(&[0x01])?
+            .option(9u16, b"x")?
+            .no_payload()
+            .build();
+        let request = Message::parse(request).unwrap();
+
+        let mut tx_buf = [0; 32];
+        let reply = MessageBuilder::new(&mut tx_buf)?
+            .bad_option_reply(&request)?
+            .unwrap()
+            .build();
+        assert_eq!(reply, &[0x61, 0x82, 0x12, 0x34, 0x01]);
+
+        // A Confirmable response is rejected with a Reset.
+        let response = Message::parse(&[0x40, 0x45, 0x00, 0x07, 0x91, b'x']).unwrap();
+        let mut tx_buf = [0; 32];
+        let reply = MessageBuilder::new(&mut tx_buf)?
+            .bad_option_reply(&response)?
+            .unwrap()
+            .build();
+        assert_eq!(reply, &[0x70, 0x00, 0x00, 0x07]);
+
+        // Non-confirmable messages are ignored.
+        let request = Message::parse(&[0x50, 0x01, 0x00, 0x07, 0x91, b'x']).unwrap();
+        let mut tx_buf = [0; 32];
+        assert!(
+            MessageBuilder::new(&mut tx_buf)?
+                .bad_option_reply(&request)?
+                .is_none()
+        );
+
+        Ok(())
+    }
 }
@@ -0,0 +1,630 @@
+use core::time::Duration;
+
+use crate::builder::encode_option_header;
+use crate::error::CoapBuildError;
+use crate::{
+    Clock, Complete, Message, MessageBuilder, MessageType, NeedsHeader, OptionNumber, RequestCode,
+    ResponseCode,
+};
+
+/// Freshness lifetime of a response without a Max-Age option, in seconds.
+///
+/// Source: [RFC 7252 5.10.5](https://datatracker.ietf.org/doc/html/rfc7252#section-5.10.5)
+pub const DEFAULT_MAX_AGE: u32 = 60;
+
+impl<'a> Message<'a> {
+    /// Returns the value of the Max-Age option, or `None` if the message has no valid Max-Age
+    /// option. Responses without one are fresh for [`DEFAULT_MAX_AGE`] seconds.
+    ///
+    /// Source: [RFC 7252 5.10.5](https://datatracker.ietf.org/doc/html/rfc7252#section-5.10.5)
+    pub fn max_age(&self) -> Option<u32> {
+        self.options
+            .into_iter()
+            .find(|opt| opt.number == OptionNumber::MaxAge)
+            .and_then(|opt| opt.as_uint())
+            .and_then(|max_age| u32::try_from(max_age).ok())
+    }
+
+    /// Returns the value of the first ETag option, as carried by a response.
+    ///
+    /// Source: [RFC 7252 5.10.6](https://datatracker.ietf.org/doc/html/rfc7252#section-5.10.6)
+    pub fn etag(&self) -> Option<&'a [u8]> {
+        self.options
+            .into_iter()
+            .find(|opt| opt.number == OptionNumber::Etag)
+            .map(|opt| opt.value)
+    }
+
+    /// Encode the cache key of a request into `buffer`: its method followed by every option that
+    /// is not marked NoCacheKey, in their encoded form.
+    ///
+    /// ETag options are left out, as a cache validates the responses it stores itself.
+    ///
+    /// Source: [RFC 7252 5.6](https://datatracker.ietf.org/doc/html/rfc7252#section-5.6)
+    pub fn cache_key<'b>(&self, buffer: &'b mut [u8]) -> Result<&'b [u8], CoapBuildError> {
+        *buffer.first_mut().ok_or(CoapBuildError::BufferTooSmall)? = self.code;
+        let mut len = 1;
+        let mut last = 0;
+
+        let options = self
+            .options
+            .into_iter()
+            .filter(|opt| !opt.is_no_cache_key() && opt.number != OptionNumber::Etag);
+        for option in options {
+            let number = u16::from(option.number);
+            let mut header = [0; 5];
+            let header_len = encode_option_header(number - last, option.value.len(), &mut header)?;
+
+            let end = len + header_len + option.value.len();
+            let dst = buffer
+                .get_mut(len..end)
+                .ok_or(CoapBuildError::BufferTooSmall)?;
+            dst[..header_len].copy_from_slice(&header[..header_len]);
+            dst[header_len..].copy_from_slice(option.value);
+
+            len = end;
+            last = number;
+        }
+
+        Ok(&buffer[..len])
+    }
+}
+
+/// Copy a message into `buffer`, returning its length.
+fn copy_message(message: &Message<'_>, buffer: &mut [u8]) -> Result<usize, CoapBuildError> {
+    let mut builder = MessageBuilder::new(buffer)?
+        .header(message.message_type, message.code)
+        .message_id(message.message_id)
+        .token(message.token)?;
+    for option in message.options {
+        builder = builder.option(option.number, option.value)?;
+    }
+
+    let builder = match message.payload {
+        Some(payload) => builder.payload(payload)?,
+        None => builder.no_payload(),
+    };
+
+    Ok(builder.build().len())
+}
+
+/// A fresh response served from a [`ResponseCache`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CachedResponse<'c> {
+    /// The stored response, with the header and token of the exchange it was received in
+    pub response: Message<'c>,
+    /// Number of seconds the response remains fresh, which replaces its Max-Age option when it
+    /// is served
+    pub max_age: u32,
+}
+
+impl<'c> CachedResponse<'c> {
+    /// Answer `request` from the cache. A request naming the ETag of the stored response is
+    /// answered with 2.03 (Valid), and any other request with the stored response. Either carries
+    /// a Max-Age option with the remaining freshness.
+    ///
+    /// A Confirmable request is answered with a piggybacked response in its Acknowledgement, and
+    /// any other request with a Non-confirmable response carrying `message_id`.
+    ///
+    /// Source: [RFC 7252 5.6.1](https://datatracker.ietf.org/doc/html/rfc7252#section-5.6.1)
+    pub fn write_reply<'buf>(
+        &self,
+        builder: MessageBuilder<'buf, NeedsHeader>,
+        request: &Message<'_>,
+        message_id: u16,
+    ) -> Result<MessageBuilder<'buf, Complete>, CoapBuildError> {
+        if let Some(etag) = self
+            .response
+            .etag()
+            .filter(|etag| request.matches_etag(etag))
+        {
+            return Ok(builder
+                .valid(request, etag, message_id)?
+                .option_uint(OptionNumber::MaxAge, self.max_age)?
+                .no_payload());
+        }
+
+        let (msg_type, message_id) = match request.message_type {
+            MessageType::Confirmable => (MessageType::Acknowledgement, request.message_id),
+            _ => (MessageType::NonConfirmable, message_id),
+        };
+        let mut builder = builder
+            .header(msg_type, self.response.code)
+            .message_id(message_id)
+            .token(request.token)?;
+
+        let mut max_age_written = false;
+        let options = self
+            .response
+            .options
+            .into_iter()
+            .filter(|opt| opt.number != OptionNumber::MaxAge);
+        for option in options {
+            if !max_age_written && u16::from(option.number) > u16::from(OptionNumber::MaxAge) {
+                builder = builder.option_uint(OptionNumber::MaxAge, self.max_age)?;
+                max_age_written = true;
+            }
+            builder = builder.option(option.number, option.value)?;
+        }
+        if !max_age_written {
+            builder = builder.option_uint(OptionNumber::MaxAge, self.max_age)?;
+        }
+
+        match self.response.payload {
+            Some(payload) => builder.payload(payload),
+            None => Ok(builder.no_payload()),
+        }
+    }
+}
+
+/// Result of looking up a request in a [`ResponseCache`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CacheLookup<'c> {
+    /// A fresh response is stored, and may be served without contacting the origin server.
+    Fresh(CachedResponse<'c>),
+    /// The stored response is stale but has an ETag. The request should be forwarded with this
+    /// ETag added, and a 2.03 (Valid) answer [stored](ResponseCache::store) in the cache makes
+    /// the stored response fresh again.
+    Stale(&'c [u8]),
+    /// No usable response is stored.
+    Miss,
+}
+
+struct CacheEntry<const L: usize> {
+    /// The cache key, followed by the encoded response
+    data: [u8; L],
+    key_len: usize,
+    len: usize,
+    expires: Duration,
+}
+
+impl<const L: usize> CacheEntry<L> {
+    fn key(&self) -> &[u8] {
+        &self.data[..self.key_len]
+    }
+
+    fn response(&self) -> Option<Message<'_>> {
+        Message::parse(&self.data[self.key_len..self.len]).ok()
+    }
+}
+
+/// Fixed-capacity cache of responses to GET requests, as kept by a proxy or client.
+///
+/// Responses are stored under the [cache key](Message::cache_key) of their request, and are
+/// fresh for the number of seconds given by their Max-Age option. 2.05 (Content) responses and
+/// error responses are cached, while 2.03 (Valid) responses refresh the stored response they
+/// validate.
+///
+/// `N` is the number of responses that can be stored, and `L` the maximum size of a cache key
+/// and its encoded response together. When the cache is full, the response expiring first is
+/// replaced.
+///
+/// Source: [RFC 7252 5.6](https://datatracker.ietf.org/doc/html/rfc7252#section-5.6)
+pub struct ResponseCache<C, const N: usize, const L: usize> {
+    clock: C,
+    entries: [Option<CacheEntry<L>>; N],
+}
+
+impl<C: Clock, const N: usize, const L: usize> ResponseCache<C, N, L> {
+    /// Create an empty cache measuring freshness with `clock`.
+    pub fn new(clock: C) -> Self {
+        Self {
+            clock,
+            entries: [const { None }; N],
+        }
+    }
+
+    /// Returns the time source of the cache.
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// Returns the number of stored responses, fresh or stale.
+    pub fn len(&self) -> usize {
+        self.entries.iter().flatten().count()
+    }
+
+    /// Returns whether no responses are stored.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Remove every stored response.
+    pub fn clear(&mut self) {
+        self.entries = [const { None }; N];
+    }
+
+    fn find(&self, key: &[u8]) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|entry| entry.as_ref().is_some_and(|entry| entry.key() == key))
+    }
+
+    /// Look up the response stored for a request.
+    ///
+    /// Source: [RFC 7252 5.6.1](https://datatracker.ietf.org/doc/html/rfc7252#section-5.6.1)
+    pub fn lookup(&self, request: &Message<'_>) -> CacheLookup<'_> {
+        let mut key = [0; L];
+        let key = match request.code == u8::from(RequestCode::Get) {
+            true => request.cache_key(&mut key),
+            false => return CacheLookup::Miss,
+        };
+
+        let entry = key
+            .ok()
+            .and_then(|key| self.find(key))
+            .and_then(|index| self.entries[index].as_ref());
+        let Some((entry, response)) = entry.and_then(|entry| Some((entry, entry.response()?)))
+        else {
+            return CacheLookup::Miss;
+        };
+
+        let now = self.clock.now();
+        if entry.expires > now {
+            return CacheLookup::Fresh(CachedResponse {
+                response,
+                max_age: (entry.expires - now).as_secs() as u32,
+            });
+        }
+
+        match response.etag() {
+            Some(etag) => CacheLookup::Stale(etag),
+            None => CacheLookup::Miss,
+        }
+    }
+
+    /// Store the response received for a request. Returns whether the response was stored, or
+    /// refreshed a stored response.
+    ///
+    /// Responses to requests other than GET, responses that are not cacheable, and responses too
+    /// large for the cache are not stored. A 2.03 (Valid) response refreshes the stored response
+    /// with the same ETag.
+    ///
+    /// Source: [RFC 7252 5.6.2](https://datatracker.ietf.org/doc/html/rfc7252#section-5.6.2)
+    pub fn store(&mut self, request: &Message<'_>, response: &Message<'_>) -> bool {
+        let mut key = [0; L];
+        let key = match request.code == u8::from(RequestCode::Get) {
+            true => request.cache_key(&mut key),
+            false => return false,
+        };
+        let Ok(key) = key else {
+            return false;
+        };
+
+        let max_age = response.max_age().unwrap_or(DEFAULT_MAX_AGE);
+        let expires = self.clock.now() + Duration::from_secs(max_age as u64);
+        let index = self.find(key);
+
+        if response.code == u8::from(ResponseCode::Valid) {
+            let Some(entry) = index.and_then(|index| self.entries[index].as_mut()) else {
+                return false;
+            };
+
+            let validated = entry
+                .response()
+                .and_then(|stored| stored.etag())
+                .is_some_and(|etag| response.etag() == Some(etag));
+            if validated {
+                entry.expires = expires;
+            }
+
+            return validated;
+        }
+
+        let cacheable = response.code == u8::from(ResponseCode::Content)
+            || matches!(response.code_class(), 4 | 5);
+        if !cacheable {
+            return false;
+        }
+
+        // Replace the previous response, or an empty slot, or the response expiring first.
+        let index = index
+            .or_else(|| self.entries.iter().position(Option::is_none))
+            .or_else(|| {
+                (0..N).min_by_key(|&i| self.entries[i].as_ref().map(|entry| entry.expires))
+            });
+        let Some(index) = index else {
+            return false;
+        };
+
+        let mut entry = CacheEntry {
+            data: [0; L],
+            key_len: key.len(),
+            len: 0,
+            expires,
+        };
+        entry.data[..key.len()].copy_from_slice(key);
+        let Ok(len) = copy_message(response, &mut entry.data[key.len()..]) else {
+            return false;
+        };
+        entry.len = key.len() + len;
+
+        self.entries[index] = Some(entry);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::Cell;
+
+    use super::*;
+
+    struct TestClock(Cell<Duration>);
+
+    impl TestClock {
+        fn advance(&self, by: Duration) {
+            self.0.set(self.0.get() + by);
+        }
+    }
+
+    impl Clock for &TestClock {
+        fn now(&self) -> Duration {
+            self.0.get()
+        }
+    }
+
+    #[test]
+    fn cache_key_skips_no_cache_key_options() {
+        let mut buffer = [0; 32];
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(0x1234)
+            .token(&[7])
+            .unwrap()
+            .option(OptionNumber::Etag, b"v1")
+            .unwrap()
+            .option_string(OptionNumber::UriPath, "temp")
+            .unwrap()
+            // Size1 is marked NoCacheKey.
+            .option_uint(OptionNumber::Size1, 10u8)
+            .unwrap()
+            .no_payload()
+            .build();
+        let get = Message::parse(packet).unwrap();
+
+        let mut key = [0; 16];
+        let key = get.cache_key(&mut key).unwrap();
+        assert_eq!(key, &[0x01, 0xB4, b't', b'e', b'm', b'p']);
+
+        let mut small = [0; 4];
+        assert_eq!(
+            get.cache_key(&mut small),
+            Err(CoapBuildError::BufferTooSmall)
+        );
+    }
+
+    #[test]
+    fn serve_fresh_and_revalidate_stale_responses() {
+        let clock = TestClock(Cell::new(Duration::ZERO));
+        let mut cache = ResponseCache::<_, 2, 64>::new(&clock);
+
+        let mut buffer = [0; 32];
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(0x1234)
+            .token(&[7])
+            .unwrap()
+            .option_string(OptionNumber::UriPath, "temp")
+            .unwrap()
+            .no_payload()
+            .build();
+        let get = Message::parse(packet).unwrap();
+        assert_eq!(cache.lookup(&get), CacheLookup::Miss);
+
+        let mut buffer = [0; 32];
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .response(MessageType::Acknowledgement, ResponseCode::Content)
+            .message_id(0x1234)
+            .token(&[7])
+            .unwrap()
+            .option(OptionNumber::Etag, b"v1")
+            .unwrap()
+            .option_uint(OptionNumber::MaxAge, 30u8)
+            .unwrap()
+            .payload(b"22.5")
+            .unwrap()
+            .build();
+        let content = Message::parse(packet).unwrap();
+        assert!(cache.store(&get, &content));
+        assert_eq!(cache.len(), 1);
+
+        clock.advance(Duration::from_secs(10));
+        let CacheLookup::Fresh(cached) = cache.lookup(&get) else {
+            panic!("expected a fresh response");
+        };
+        assert_eq!(cached.max_age, 20);
+        assert_eq!(cached.response.payload, Some(&b"22.5"[..]));
+
+        let mut tx_buf = [0; 32];
+        let packet = cached
+            .write_reply(MessageBuilder::new(&mut tx_buf).unwrap(), &get, 0)
+            .unwrap()
+            .build();
+        let reply = Message::parse(packet).unwrap();
+        assert_eq!(reply.code, u8::from(ResponseCode::Content));
+        assert_eq!(reply.message_id, 0x1234);
+        assert_eq!(reply.max_age(), Some(20));
+        assert_eq!(reply.etag(), Some(&b"v1"[..]));
+        assert_eq!(reply.payload, Some(&b"22.5"[..]));
+
+        // A client holding the same representation gets 2.03 (Valid).
+        let mut buffer = [0; 32];
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(0x1234)
+            .token(&[7])
+            .unwrap()
+            .option(OptionNumber::Etag, b"v1")
+            .unwrap()
+            .option_string(OptionNumber::UriPath, "temp")
+            .unwrap()
+            .no_payload()
+            .build();
+        let conditional = Message::parse(packet).unwrap();
+        let mut tx_buf = [0; 32];
+        let packet = cached
+            .write_reply(MessageBuilder::new(&mut tx_buf).unwrap(), &conditional, 0)
+            .unwrap()
+            .build();
+        let reply = Message::parse(packet).unwrap();
+        assert_eq!(reply.code, u8::from(ResponseCode::Valid));
+        assert_eq!(reply.max_age(), Some(20));
+        assert_eq!(reply.payload, None);
+
+        clock.advance(Duration::from_secs(20));
+        assert_eq!(cache.lookup(&get), CacheLookup::Stale(b"v1"));
+
+        let mut buffer = [0; 32];
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .response(MessageType::Acknowledgement, ResponseCode::Valid)
+            .message_id(0x1234)
+            .token(&[7])
+            .unwrap()
+            .option(OptionNumber::Etag, b"v1")
+            .unwrap()
+            .no_payload()
+            .build();
+        let valid = Message::parse(packet).unwrap();
+        assert!(cache.store(&get, &valid));
+        let CacheLookup::Fresh(cached) = cache.lookup(&get) else {
+            panic!("expected a fresh response");
+        };
+        assert_eq!(cached.max_age, DEFAULT_MAX_AGE);
+        assert_eq!(cached.response.payload, Some(&b"22.5"[..]));
+    }
+
+    #[test]
+    fn store_only_cacheable_responses() {
+        let clock = TestClock(Cell::new(Duration::ZERO));
+        let mut cache = ResponseCache::<_, 2, 64>::new(&clock);
+
+        let mut buffer = [0; 32];
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .response(MessageType::Acknowledgement, ResponseCode::Changed)
+            .message_id(0x1234)
+            .token(&[7])
+            .unwrap()
+            .option(OptionNumber::Etag, b"v1")
+            .unwrap()
+            .no_payload()
+            .build();
+        let changed = Message::parse(packet).unwrap();
+        let mut buffer = [0; 32];
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(0x1234)
+            .token(&[7])
+            .unwrap()
+            .option_string(OptionNumber::UriPath, "a")
+            .unwrap()
+            .no_payload()
+            .build();
+        let get = Message::parse(packet).unwrap();
+        assert!(!cache.store(&get, &changed));
+
+        let mut buffer = [0; 32];
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .response(MessageType::Acknowledgement, ResponseCode::Valid)
+            .message_id(0x1234)
+            .token(&[7])
+            .unwrap()
+            .option(OptionNumber::Etag, b"v1")
+            .unwrap()
+            .no_payload()
+            .build();
+        let valid = Message::parse(packet).unwrap();
+        assert!(!cache.store(&get, &valid));
+
+        let mut buffer = [0; 32];
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .response(MessageType::Acknowledgement, ResponseCode::NotFound)
+            .message_id(0x1234)
+            .token(&[7])
+            .unwrap()
+            .option(OptionNumber::Etag, b"v1")
+            .unwrap()
+            .option_uint(OptionNumber::MaxAge, 5u8)
+            .unwrap()
+            .no_payload()
+            .build();
+        let not_found = Message::parse(packet).unwrap();
+        assert!(cache.store(&get, &not_found));
+
+        // The third response replaces the one expiring first.
+        let mut buffer = [0; 32];
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .response(MessageType::Acknowledgement, ResponseCode::Content)
+            .message_id(0x1234)
+            .token(&[7])
+            .unwrap()
+            .option(OptionNumber::Etag, b"v1")
+            .unwrap()
+            .option_uint(OptionNumber::MaxAge, 100u8)
+            .unwrap()
+            .payload(b"x")
+            .unwrap()
+            .build();
+        let content = Message::parse(packet).unwrap();
+        let mut buffer = [0; 32];
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(0x1234)
+            .token(&[7])
+            .unwrap()
+            .option_string(OptionNumber::UriPath, "b")
+            .unwrap()
+            .no_payload()
+            .build();
+        let b = Message::parse(packet).unwrap();
+        assert!(cache.store(&b, &content));
+        let mut buffer = [0; 32];
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(0x1234)
+            .token(&[7])
+            .unwrap()
+            .option_string(OptionNumber::UriPath, "c")
+            .unwrap()
+            .no_payload()
+            .build();
+        let c = Message::parse(packet).unwrap();
+        assert!(cache.store(&c, &content));
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.lookup(&get), CacheLookup::Miss);
+        assert!(matches!(cache.lookup(&b), CacheLookup::Fresh(_)));
+
+        let mut large = [0; 96];
+        let packet = MessageBuilder::new(&mut large)
+            .unwrap()
+            .response(MessageType::Acknowledgement, ResponseCode::Content)
+            .message_id(0x1234)
+            .token(&[7])
+            .unwrap()
+            .option(OptionNumber::Etag, b"v1")
+            .unwrap()
+            .payload(&[0; 64])
+            .unwrap()
+            .build();
+        let large = Message::parse(packet).unwrap();
+        assert!(!cache.store(&b, &large));
+
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,324 @@
+pub(crate) const UNSIGNED: u8 = 0;
+pub(crate) const NEGATIVE: u8 = 1;
+pub(crate) const BYTES: u8 = 2;
+pub(crate) const TEXT: u8 = 3;
+pub(crate) const ARRAY: u8 = 4;
+pub(crate) const MAP: u8 = 5;
+pub(crate) const TAG: u8 = 6;
+#[cfg(feature = "oscore")]
+pub(crate) const SIMPLE: u8 = 7;
+
+/// Simple value `null`.
+#[cfg(feature = "oscore")]
+pub(crate) const NULL: u64 = 22;
+
+/// Errors from the CBOR encoder and decoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CborError {
+    /// The output buffer is too small.
+    BufferTooSmall,
+    /// The input is truncated, malformed, or uses indefinite lengths.
+    Malformed,
+    /// A text string is not valid UTF-8.
+    InvalidUtf8,
+}
+
+/// Returns the encoded length of an item head with the given argument.
+pub(crate) fn head_len(argument: u64) -> usize {
+    match argument {
+        0..=23 => 1,
+        24..=0xFF => 2,
+        0x100..=0xFFFF => 3,
+        0x1_0000..=0xFFFF_FFFF => 5,
+        _ => 9,
+    }
+}
+
+/// Returns the encoded length of a text or byte string.
+pub(crate) fn string_len(len: usize) -> usize {
+    head_len(len as u64) + len
+}
+
+/// Returns the encoded length of an integer.
+pub(crate) fn int_len(value: i64) -> usize {
+    match value {
+        0.. => head_len(value as u64),
+        _ => head_len(!value as u64),
+    }
+}
+
+/// A decoded data item. Arrays, maps and tags only carry their head; their contents follow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Item<'a> {
+    Unsigned(u64),
+    /// A negative integer `-1 - n`
+    Negative(u64),
+    Bytes(&'a [u8]),
+    Text(&'a str),
+    Array(u64),
+    Map(u64),
+    Tag(u64),
+    /// A simple value or float, as its raw argument
+    Simple(u64),
+}
+
+impl Item<'_> {
+    /// Returns the value of an integer item that fits in an `i64`.
+    pub(crate) fn as_int(&self) -> Option<i64> {
+        match *self {
+            Item::Unsigned(n) => i64::try_from(n).ok(),
+            Item::Negative(n) => i64::try_from(n).ok().map(|n| -1 - n),
+            _ => None,
+        }
+    }
+}
+
+/// Decoder for definite-length CBOR items.
+///
+/// Source: [RFC 8949 3](https://datatracker.ietf.org/doc/html/rfc8949#section-3)
+#[derive(Debug, Clone)]
+pub(crate) struct Decoder<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Decoder<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Decoder { data, offset: 0 }
+    }
+
+    pub(crate) fn is_finished(&self) -> bool {
+        self.offset == self.data.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], CborError> {
+        let end = self.offset.checked_add(len).ok_or(CborError::Malformed)?;
+        let bytes = self
+            .data
+            .get(self.offset..end)
+            .ok_or(CborError::Malformed)?;
+        self.offset = end;
+
+        Ok(bytes)
+    }
+
+    fn head(&mut self) -> Result<(u8, u64), CborError> {
+        let initial = self.take(1)?[0];
+        let major = initial >> 5;
+
+        let argument = match initial & 0x1F {
+            info @ 0..=23 => info as u64,
+            info @ 24..=27 => {
+                let len = 1 << (info - 24);
+                self.take(len)?
+                    .iter()
+                    .fold(0, |acc, &byte| (acc << 8) | byte as u64)
+            }
+            // Reserved, or an indefinite length, which is not supported.
+            _ => return Err(CborError::Malformed),
+        };
+
+        Ok((major, argument))
+    }
+
+    /// Decode the next data item.
+    pub(crate) fn item(&mut self) -> Result<Item<'a>, CborError> {
+        let (major, argument) = self.head()?;
+
+        let item = match major {
+            UNSIGNED => Item::Unsigned(argument),
+            NEGATIVE => Item::Negative(argument),
+            BYTES => Item::Bytes(self.take_len(argument)?),
+            TEXT => {
+                let bytes = self.take_len(argument)?;
+                Item::Text(core::str::from_utf8(bytes).map_err(|_| CborError::InvalidUtf8)?)
+            }
+            ARRAY => Item::Array(argument),
+            MAP => Item::Map(argument),
+            TAG => Item::Tag(argument),
+            _ => Item::Simple(argument),
+        };
+
+        Ok(item)
+    }
+
+    fn take_len(&mut self, len: u64) -> Result<&'a [u8], CborError> {
+        self.take(usize::try_from(len).map_err(|_| CborError::Malformed)?)
+    }
+
+    /// Skip the next data item, including the contents of arrays, maps and tags.
+    pub(crate) fn skip(&mut self) -> Result<(), CborError> {
+        let item = self.item()?;
+        self.skip_contents(item)
+    }
+
+    /// Skip the contents of an array, map or tag whose head was just decoded.
+    pub(crate) fn skip_contents(&mut self, item: Item<'_>) -> Result<(), CborError> {
+        // Counted instead of recursing, so nesting cannot exhaust the stack.
+        let mut remaining = nested_items(item)?;
+
+        while remaining > 0 {
+            remaining -= 1;
+            remaining = remaining
+                .checked_add(nested_items(self.item()?)?)
+                .ok_or(CborError::Malformed)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the number of items nested directly inside an item.
+fn nested_items(item: Item<'_>) -> Result<u64, CborError> {
+    match item {
+        Item::Array(len) => Ok(len),
+        Item::Map(len) => len.checked_mul(2).ok_or(CborError::Malformed),
+        Item::Tag(_) => Ok(1),
+        _ => Ok(0),
+    }
+}
+
+/// Encoder for CBOR items in preferred serialization.
+///
+/// Source: [RFC 8949 4.1](https://datatracker.ietf.org/doc/html/rfc8949#section-4.1)
+pub(crate) struct Encoder<'b> {
+    buffer: &'b mut [u8],
+    len: usize,
+}
+
+impl<'b> Encoder<'b> {
+    pub(crate) fn new(buffer: &'b mut [u8]) -> Self {
+        Encoder { buffer, len: 0 }
+    }
+
+    fn push(&mut self, bytes: &[u8]) -> Result<(), CborError> {
+        let end = self.len + bytes.len();
+        let dst = self
+            .buffer
+            .get_mut(self.len..end)
+            .ok_or(CborError::BufferTooSmall)?;
+        dst.copy_from_slice(bytes);
+        self.len = end;
+
+        Ok(())
+    }
+
+    pub(crate) fn head(&mut self, major: u8, argument: u64) -> Result<(), CborError> {
+        let major = major << 5;
+        let bytes = argument.to_be_bytes();
+
+        match head_len(argument) {
+            1 => self.push(&[major | argument as u8]),
+            2 => self.push(&[major | 24, argument as u8]),
+            3 => {
+                self.push(&[major | 25])?;
+                self.push(&bytes[6..])
+            }
+            5 => {
+                self.push(&[major | 26])?;
+                self.push(&bytes[4..])
+            }
+            _ => {
+                self.push(&[major | 27])?;
+                self.push(&bytes)
+            }
+        }
+    }
+
+    pub(crate) fn int(&mut self, value: i64) -> Result<(), CborError> {
+        match value {
+            0.. => self.head(UNSIGNED, value as u64),
+            _ => self.head(NEGATIVE, !value as u64),
+        }
+    }
+
+    #[cfg(feature = "oscore")]
+    pub(crate) fn bytes(&mut self, value: &[u8]) -> Result<(), CborError> {
+        self.head(BYTES, value.len() as u64)?;
+        self.push(value)
+    }
+
+    pub(crate) fn text(&mut self, value: &str) -> Result<(), CborError> {
+        self.head(TEXT, value.len() as u64)?;
+        self.push(value.as_bytes())
+    }
+
+    #[cfg(feature = "oscore")]
+    pub(crate) fn null(&mut self) -> Result<(), CborError> {
+        self.head(SIMPLE, NULL)
+    }
+
+    pub(crate) fn finish(self) -> &'b [u8] {
+        &self.buffer[..self.len]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_items() {
+        // Examples from RFC 8949 Appendix A.
+        let mut buffer = [0; 32];
+        let mut encoder = Encoder::new(&mut buffer);
+        encoder.int(0).unwrap();
+        encoder.int(23).unwrap();
+        encoder.int(24).unwrap();
+        encoder.int(1000).unwrap();
+        encoder.int(-1).unwrap();
+        encoder.int(-1000).unwrap();
+        encoder.int(1_000_000).unwrap();
+        encoder.text("IETF").unwrap();
+        encoder.head(ARRAY, 0).unwrap();
+        assert_eq!(
+            encoder.finish(),
+            &[
+                0x00, 0x17, 0x18, 0x18, 0x19, 0x03, 0xE8, 0x20, 0x39, 0x03, 0xE7, 0x1A, 0x00, 0x0F,
+                0x42, 0x40, 0x64, b'I', b'E', b'T', b'F', 0x80
+            ]
+        );
+
+        assert_eq!(int_len(-1000), 3);
+        assert_eq!(string_len(4), 5);
+
+        let mut buffer = [0; 2];
+        let mut encoder = Encoder::new(&mut buffer);
+        assert_eq!(encoder.text("IETF"), Err(CborError::BufferTooSmall));
+    }
+
+    #[test]
+    fn decode_and_skip_items() {
+        // {"a": [1, h'01', 1.5], -2: "b"}, null
+        let data = [
+            0xA2, 0x61, b'a', 0x83, 0x01, 0x41, 0x01, 0xF9, 0x3E, 0x00, 0x21, 0x61, b'b', 0xF6,
+        ];
+        let mut decoder = Decoder::new(&data);
+        assert_eq!(decoder.item(), Ok(Item::Map(2)));
+        assert_eq!(decoder.item(), Ok(Item::Text("a")));
+        decoder.skip().unwrap();
+        assert_eq!(decoder.item().unwrap().as_int(), Some(-2));
+        assert_eq!(decoder.item(), Ok(Item::Text("b")));
+        assert_eq!(decoder.item(), Ok(Item::Simple(22)));
+        assert!(decoder.is_finished());
+
+        let mut decoder = Decoder::new(&data);
+        decoder.skip().unwrap();
+        assert_eq!(decoder.item(), Ok(Item::Simple(22)));
+
+        // Truncated, indefinite-length, and nested beyond the input.
+        assert_eq!(
+            Decoder::new(&[0x19, 0x03]).item(),
+            Err(CborError::Malformed)
+        );
+        assert_eq!(
+            Decoder::new(&[0x9F, 0xFF]).item(),
+            Err(CborError::Malformed)
+        );
+        assert_eq!(Decoder::new(&[0x81; 64]).skip(), Err(CborError::Malformed));
+        assert_eq!(
+            Decoder::new(&[0x62, 0xFF, 0xFE]).item(),
+            Err(CborError::InvalidUtf8)
+        );
+    }
+}
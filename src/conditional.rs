@@ -0,0 +1,225 @@
+use crate::error::CoapBuildError;
+use crate::{
+    Message, MessageBuilder, NeedsHeader, NeedsPayload, OptionNumber, RequestCode, ResponseCode,
+};
+
+type BuilderResult<'buf, T> = core::result::Result<MessageBuilder<'buf, T>, CoapBuildError>;
+
+/// Outcome of evaluating the preconditions of a request.
+///
+/// Source: [RFC 7252 5.10.8](https://datatracker.ietf.org/doc/html/rfc7252#section-5.10.8)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Precondition {
+    /// The request has no preconditions, or all of them hold. The request may be performed.
+    Proceed,
+    /// A precondition does not hold. The request must not be performed, and is answered with
+    /// 4.12 (Precondition Failed).
+    Failed,
+}
+
+impl Precondition {
+    /// Returns the response code a failed precondition is answered with.
+    pub fn response_code(&self) -> Option<ResponseCode> {
+        match self {
+            Precondition::Proceed => None,
+            Precondition::Failed => Some(ResponseCode::PreconditionFailed),
+        }
+    }
+}
+
+impl<'a> Message<'a> {
+    /// Evaluate the If-Match and If-None-Match options of a request against the current ETag of
+    /// the target resource. `current_etag` is `None` if the resource does not exist, and an empty
+    /// slice if it exists without an ETag.
+    ///
+    /// If-Match holds if any of its values is the current ETag, or is empty and the resource
+    /// exists. If-None-Match holds if the resource does not exist.
+    ///
+    /// Source: [RFC 7252 5.10.8](https://datatracker.ietf.org/doc/html/rfc7252#section-5.10.8)
+    pub fn evaluate_preconditions(&self, current_etag: Option<&[u8]>) -> Precondition {
+        let mut has_if_match = false;
+        let mut if_match = false;
+        let mut if_none_match = true;
+
+        for option in self.options {
+            match option.number {
+                OptionNumber::IfMatch => {
+                    has_if_match = true;
+                    if_match |= current_etag
+                        .is_some_and(|etag| option.value.is_empty() || option.value == etag);
+                }
+                OptionNumber::IfNoneMatch => if_none_match = current_etag.is_none(),
+                _ => {}
+            }
+        }
+
+        if (has_if_match && !if_match) || !if_none_match {
+            Precondition::Failed
+        } else {
+            Precondition::Proceed
+        }
+    }
+
+    /// Returns whether a GET request names `current_etag` in one of its ETag options, so that the
+    /// server may answer with [`MessageBuilder::valid`] instead of sending the representation.
+    ///
+    /// Source: [RFC 7252 5.10.6.2](https://datatracker.ietf.org/doc/html/rfc7252#section-5.10.6.2)
+    pub fn matches_etag(&self, current_etag: &[u8]) -> bool {
+        self.code == u8::from(RequestCode::Get)
+            && self
+                .options
+                .into_iter()
+                .any(|opt| opt.number == OptionNumber::Etag && opt.value == current_etag)
+    }
+}
+
+impl<'buf> MessageBuilder<'buf, NeedsHeader> {
+    /// Construct the 2.03 (Valid) response to a GET request whose ETag options name `etag`,
+    /// carrying the ETag. Options numbered above 4 (ETag), such as Max-Age, may be added before
+    /// completing the response without a payload.
+    ///
+    /// Source: [RFC 7252 5.9.1.3](https://datatracker.ietf.org/doc/html/rfc7252#section-5.9.1.3)
+    pub fn valid(
+        self,
+        request: &Message<'_>,
+        etag: &[u8],
+        message_id: u16,
+    ) -> BuilderResult<'buf, NeedsPayload> {
+        self.reply(request, ResponseCode::Valid, message_id)?
+            .option(OptionNumber::Etag, etag)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MessageType;
+
+    #[test]
+    fn evaluate_if_match_and_if_none_match() {
+        let mut buffer = [0; 32];
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Put)
+            .message_id(0x1234)
+            .token(&[7])
+            .unwrap()
+            .no_payload()
+            .build();
+        let put = Message::parse(packet).unwrap();
+        assert_eq!(put.evaluate_preconditions(None), Precondition::Proceed);
+
+        let mut buffer = [0; 32];
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Put)
+            .message_id(0x1234)
+            .token(&[7])
+            .unwrap()
+            .option(OptionNumber::IfMatch, b"v1")
+            .unwrap()
+            .option(OptionNumber::IfMatch, b"v2")
+            .unwrap()
+            .no_payload()
+            .build();
+        let put = Message::parse(packet).unwrap();
+        assert_eq!(
+            put.evaluate_preconditions(Some(b"v2")),
+            Precondition::Proceed
+        );
+        assert_eq!(
+            put.evaluate_preconditions(Some(b"v3")),
+            Precondition::Failed
+        );
+        assert_eq!(put.evaluate_preconditions(None), Precondition::Failed);
+
+        // An empty If-Match matches any existing representation.
+        let mut buffer = [0; 32];
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Put)
+            .message_id(0x1234)
+            .token(&[7])
+            .unwrap()
+            .option(OptionNumber::IfMatch, b"")
+            .unwrap()
+            .no_payload()
+            .build();
+        let put = Message::parse(packet).unwrap();
+        assert_eq!(put.evaluate_preconditions(Some(b"")), Precondition::Proceed);
+        assert_eq!(put.evaluate_preconditions(None), Precondition::Failed);
+
+        let mut buffer = [0; 32];
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Put)
+            .message_id(0x1234)
+            .token(&[7])
+            .unwrap()
+            .option(OptionNumber::IfNoneMatch, b"")
+            .unwrap()
+            .no_payload()
+            .build();
+        let put = Message::parse(packet).unwrap();
+        assert_eq!(put.evaluate_preconditions(None), Precondition::Proceed);
+        let failed = put.evaluate_preconditions(Some(b"v1"));
+        assert_eq!(failed, Precondition::Failed);
+        assert_eq!(
+            failed.response_code(),
+            Some(ResponseCode::PreconditionFailed)
+        );
+    }
+
+    #[test]
+    fn valid_response_for_matching_etag() -> Result<(), CoapBuildError> {
+        let mut buffer = [0; 32];
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(0x1234)
+            .token(&[7])
+            .unwrap()
+            .option(OptionNumber::Etag, b"v1")
+            .unwrap()
+            .option(OptionNumber::Etag, b"v2")
+            .unwrap()
+            .no_payload()
+            .build();
+        let get = Message::parse(packet).unwrap();
+        assert!(get.matches_etag(b"v2"));
+        assert!(!get.matches_etag(b"v3"));
+
+        let mut tx_buf = [0; 32];
+        let packet = MessageBuilder::new(&mut tx_buf)?
+            .valid(&get, b"v2", 0)?
+            .option_uint(OptionNumber::MaxAge, 30u8)?
+            .no_payload()
+            .build();
+
+        let response = Message::parse(packet).unwrap();
+        assert_eq!(response.message_type, MessageType::Acknowledgement);
+        assert_eq!(response.code, u8::from(ResponseCode::Valid));
+        assert_eq!(response.token, &[7]);
+        let etag = response.options.into_iter().next().unwrap();
+        assert_eq!(etag.number, OptionNumber::Etag);
+        assert_eq!(etag.value, b"v2");
+        assert_eq!(response.payload, None);
+
+        let mut buffer = [0; 32];
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Put)
+            .message_id(0x1234)
+            .token(&[7])
+            .unwrap()
+            .option(OptionNumber::Etag, b"v1")
+            .unwrap()
+            .no_payload()
+            .build();
+        let put = Message::parse(packet).unwrap();
+        assert!(!put.matches_etag(b"v1"));
+
+        Ok(())
+    }
+}
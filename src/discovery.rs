@@ -0,0 +1,430 @@
+use crate::error::{CoapBuildError, CoapDiscoveryError, CoapLinkFormatError};
+use crate::link_format::format_uint;
+use crate::{
+    BlockSize, BlockwiseSender, Complete, ContentFormat, LinkWriter, Message, MessageBuilder,
+    NeedsPayload, OptionNumber, RequestCode,
+};
+
+/// Path of the resource discovery resource.
+const WELL_KNOWN_CORE: [&str; 2] = [".well-known", "core"];
+
+/// Space needed after the Content-Format option for the Block2 option (up to 3 bytes), the Size2
+/// option (up to 4 bytes), their option headers and the payload marker.
+const BLOCK_OVERHEAD: usize = 10;
+
+/// Check if `value` matches a query filter `pattern`, which may end in `*` to match any value
+/// starting with the rest of the pattern.
+///
+/// Source: [RFC 6690 4.1](https://datatracker.ietf.org/doc/html/rfc6690#section-4.1)
+fn matches(value: &str, pattern: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => value.starts_with(prefix),
+        None => value == pattern,
+    }
+}
+
+/// An attribute of a [`Resource`], written as a link attribute in discovery responses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ResourceAttribute<'a> {
+    /// An attribute with a quoted string value, such as `title="Temperature"`.
+    Text(&'a str, &'a str),
+    /// An attribute with several space-separated values, such as `rt="temperature-c core.s"`.
+    Values(&'a str, &'a [&'a str]),
+    /// An attribute with an integer value, such as `ct=0` or `sz=128`.
+    Uint(&'a str, u32),
+    /// An attribute without a value, such as `obs`.
+    Flag(&'a str),
+}
+
+impl<'a> ResourceAttribute<'a> {
+    /// Returns the name of the attribute.
+    pub fn name(&self) -> &'a str {
+        match *self {
+            ResourceAttribute::Text(name, _)
+            | ResourceAttribute::Values(name, _)
+            | ResourceAttribute::Uint(name, _)
+            | ResourceAttribute::Flag(name) => name,
+        }
+    }
+
+    /// Check if any value of the attribute matches a query filter pattern. Attributes without a
+    /// value only match an empty pattern.
+    fn matches(&self, pattern: &str) -> bool {
+        match *self {
+            ResourceAttribute::Text(_, value) => {
+                matches(value, pattern)
+                    || value
+                        .split_ascii_whitespace()
+                        .any(|value| matches(value, pattern))
+            }
+            ResourceAttribute::Values(_, values) => {
+                values.iter().any(|value| matches(value, pattern))
+            }
+            ResourceAttribute::Uint(_, value) => {
+                let mut digits = [0; 10];
+                let digits = format_uint(value, &mut digits);
+                core::str::from_utf8(digits).is_ok_and(|value| matches(value, pattern))
+            }
+            ResourceAttribute::Flag(_) => pattern.is_empty() || pattern == "*",
+        }
+    }
+}
+
+/// A resource advertised by [`Discovery`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Resource<'a> {
+    /// Path of the resource, such as `/sensors/temp`
+    pub path: &'a str,
+    /// Attributes advertised for the resource
+    pub attributes: &'a [ResourceAttribute<'a>],
+}
+
+impl<'a> Resource<'a> {
+    /// Check if the resource matches a single `name=value` query filter. A filter without `=`
+    /// matches resources that have an attribute with that name.
+    ///
+    /// Source: [RFC 6690 4.1](https://datatracker.ietf.org/doc/html/rfc6690#section-4.1)
+    pub fn matches_query(&self, query: &str) -> bool {
+        let Some((name, pattern)) = query.split_once('=') else {
+            return self
+                .attributes
+                .iter()
+                .any(|attribute| attribute.name() == query);
+        };
+
+        if name == "href" {
+            return matches(self.path, pattern);
+        }
+
+        self.attributes
+            .iter()
+            .filter(|attribute| attribute.name() == name)
+            .any(|attribute| attribute.matches(pattern))
+    }
+
+    fn write(&self, writer: &mut LinkWriter<'_>) -> Result<(), CoapLinkFormatError> {
+        writer.link(self.path)?;
+
+        for attribute in self.attributes {
+            match *attribute {
+                ResourceAttribute::Text(name, value) => writer.attribute(name, value)?,
+                ResourceAttribute::Values(name, values) => writer.attribute_values(name, values)?,
+                ResourceAttribute::Uint(name, value) => writer.attribute_uint(name, value)?,
+                ResourceAttribute::Flag(name) => writer.flag(name)?,
+            };
+        }
+
+        Ok(())
+    }
+}
+
+/// Answers resource discovery requests to `/.well-known/core` from a table of resources.
+///
+/// Source: [RFC 6690 4](https://datatracker.ietf.org/doc/html/rfc6690#section-4)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Discovery<'a> {
+    resources: &'a [Resource<'a>],
+}
+
+impl<'a> Discovery<'a> {
+    /// Create a responder advertising `resources`.
+    pub const fn new(resources: &'a [Resource<'a>]) -> Self {
+        Self { resources }
+    }
+
+    /// Returns the advertised resources.
+    pub fn resources(&self) -> &'a [Resource<'a>] {
+        self.resources
+    }
+
+    /// Check if a request is a GET request for `/.well-known/core`.
+    pub fn is_discovery_request(request: &Message<'_>) -> bool {
+        if request.code != u8::from(RequestCode::Get) {
+            return false;
+        }
+
+        let mut path = request
+            .options
+            .into_iter()
+            .filter(|opt| opt.number == OptionNumber::UriPath)
+            .map(|opt| opt.value);
+
+        WELL_KNOWN_CORE
+            .iter()
+            .all(|segment| path.next() == Some(segment.as_bytes()))
+            && path.next().is_none()
+    }
+
+    /// Write the links of the resources matching every Uri-Query filter of a request into
+    /// `document`.
+    pub fn write_links<'d>(
+        &self,
+        request: &Message<'_>,
+        document: &'d mut [u8],
+    ) -> Result<&'d [u8], CoapLinkFormatError> {
+        let mut writer = LinkWriter::new(document);
+
+        for resource in self.resources {
+            let mut queries = request
+                .options
+                .into_iter()
+                .filter(|opt| opt.number == OptionNumber::UriQuery);
+
+            let matching = queries.all(|query| {
+                query
+                    .as_str()
+                    .is_ok_and(|query| resource.matches_query(query))
+            });
+
+            if matching {
+                resource.write(&mut writer)?;
+            }
+        }
+
+        Ok(writer.finish())
+    }
+
+    /// Answer a discovery request with the matching links, using `document` to hold the full
+    /// link format document.
+    ///
+    /// The builder should carry a 2.05 (Content) response code, and only options numbered below
+    /// 12 (Content-Format) may be added to it beforehand. If the document does not fit in the
+    /// remaining space of the builder, or the request asks for a specific block, the response
+    /// carries one block of the document with a Block2 option.
+    ///
+    /// Source: [RFC 6690 4](https://datatracker.ietf.org/doc/html/rfc6690#section-4),
+    /// [RFC 7959 2.4](https://datatracker.ietf.org/doc/html/rfc7959#section-2.4)
+    pub fn write_response<'buf>(
+        &self,
+        builder: MessageBuilder<'buf, NeedsPayload>,
+        request: &Message<'_>,
+        document: &mut [u8],
+    ) -> Result<MessageBuilder<'buf, Complete>, CoapDiscoveryError> {
+        let document = self.write_links(request, document)?;
+
+        let builder = builder.option_uint(
+            OptionNumber::ContentFormat,
+            u16::from(ContentFormat::ApplicationLinkFormat),
+        )?;
+
+        let block_requested = request
+            .options
+            .into_iter()
+            .any(|opt| opt.number == OptionNumber::Block2);
+
+        if !block_requested && builder.remaining_buffer() > document.len() {
+            if document.is_empty() {
+                return Ok(builder.no_payload());
+            }
+
+            return Ok(builder.payload(document)?);
+        }
+
+        let max = builder.remaining_buffer().saturating_sub(BLOCK_OVERHEAD);
+        let size = BlockSize::fitting(max).ok_or(CoapBuildError::BufferTooSmall)?;
+
+        Ok(BlockwiseSender::new(document, size).write_block2_response(builder, request)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockValue, LinkFormat, MessageType, ResponseCode};
+
+    static RESOURCES: &[Resource] = &[
+        Resource {
+            path: "/sensors/temp",
+            attributes: &[
+                ResourceAttribute::Values("rt", &["temperature-c"]),
+                ResourceAttribute::Text("if", "sensor"),
+                ResourceAttribute::Uint("ct", 0),
+                ResourceAttribute::Flag("obs"),
+            ],
+        },
+        Resource {
+            path: "/sensors/light",
+            attributes: &[
+                ResourceAttribute::Values("rt", &["light-lux", "core.s"]),
+                ResourceAttribute::Text("if", "sensor"),
+            ],
+        },
+        Resource {
+            path: "/firmware",
+            attributes: &[ResourceAttribute::Uint("sz", 262144)],
+        },
+    ];
+
+    fn discovery_request<'a>(
+        buffer: &'a mut [u8],
+        query: Option<&str>,
+        block: Option<BlockValue>,
+    ) -> Message<'a> {
+        let mut builder = MessageBuilder::new(buffer)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(1)
+            .no_token()
+            .unwrap()
+            .option_string(OptionNumber::UriPath, ".well-known")
+            .unwrap()
+            .option_string(OptionNumber::UriPath, "core")
+            .unwrap();
+
+        if let Some(query) = query {
+            builder = builder
+                .option_string(OptionNumber::UriQuery, query)
+                .unwrap();
+        }
+        if let Some(block) = block {
+            builder = builder.option_block(OptionNumber::Block2, block).unwrap();
+        }
+
+        Message::parse(builder.no_payload().build()).unwrap()
+    }
+
+    fn respond<'a>(request: &Message<'_>, buffer: &'a mut [u8]) -> Message<'a> {
+        let mut document = [0; 256];
+        let builder = MessageBuilder::new(buffer)
+            .unwrap()
+            .response(MessageType::Acknowledgement, ResponseCode::Content)
+            .message_id(request.message_id)
+            .no_token()
+            .unwrap();
+
+        let packet = Discovery::new(RESOURCES)
+            .write_response(builder, request, &mut document)
+            .unwrap()
+            .build();
+
+        Message::parse(packet).unwrap()
+    }
+
+    #[test]
+    fn detects_discovery_requests() {
+        let mut buffer = [0; 64];
+        assert!(Discovery::is_discovery_request(&discovery_request(
+            &mut buffer,
+            None,
+            None
+        )));
+
+        let mut buffer = [0; 64];
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(1)
+            .no_token()
+            .unwrap()
+            .option_string(OptionNumber::UriPath, ".well-known")
+            .unwrap()
+            .no_payload()
+            .build();
+        assert!(!Discovery::is_discovery_request(
+            &Message::parse(packet).unwrap()
+        ));
+    }
+
+    #[test]
+    fn filters_by_query() {
+        let discovery = Discovery::new(RESOURCES);
+        let mut document = [0; 256];
+
+        let mut buffer = [0; 64];
+        let request = discovery_request(&mut buffer, Some("rt=temperature*"), None);
+        assert_eq!(
+            discovery.write_links(&request, &mut document),
+            Ok(&b"</sensors/temp>;rt=\"temperature-c\";if=\"sensor\";ct=0;obs"[..])
+        );
+
+        let mut buffer = [0; 64];
+        let request = discovery_request(&mut buffer, Some("href=/sensors*"), None);
+        let links = discovery.write_links(&request, &mut document).unwrap();
+        let links = LinkFormat::parse(links).unwrap();
+        assert_eq!(links.iter().count(), 2);
+
+        let mut buffer = [0; 64];
+        let request = discovery_request(&mut buffer, Some("rt=core.s"), None);
+        let links = discovery.write_links(&request, &mut document).unwrap();
+        assert_eq!(
+            LinkFormat::parse(links)
+                .unwrap()
+                .iter()
+                .next()
+                .unwrap()
+                .unwrap()
+                .target,
+            "/sensors/light"
+        );
+
+        let mut buffer = [0; 64];
+        let request = discovery_request(&mut buffer, Some("sz=262144"), None);
+        assert_eq!(
+            discovery.write_links(&request, &mut document),
+            Ok(&b"</firmware>;sz=262144"[..])
+        );
+
+        let mut buffer = [0; 64];
+        let request = discovery_request(&mut buffer, Some("rt=missing"), None);
+        assert_eq!(discovery.write_links(&request, &mut document), Ok(&b""[..]));
+    }
+
+    #[test]
+    fn small_document_fits_in_response() {
+        let mut buffer = [0; 64];
+        let request = discovery_request(&mut buffer, Some("href=/firmware"), None);
+
+        let mut response = [0; 64];
+        let response = respond(&request, &mut response);
+
+        let mut options = response.options.into_iter();
+        let format = options.next().unwrap();
+        assert_eq!(format.number, OptionNumber::ContentFormat);
+        assert_eq!(format.as_uint(), Some(40));
+        assert!(options.next().is_none());
+        assert_eq!(response.payload, Some(&b"</firmware>;sz=262144"[..]));
+    }
+
+    #[test]
+    fn large_document_is_sliced() {
+        let mut buffer = [0; 64];
+        let request = discovery_request(&mut buffer, None, None);
+
+        // 64 bytes leave room for a 32 byte block.
+        let mut response = [0; 64];
+        let first = respond(&request, &mut response);
+        let block = first
+            .options
+            .into_iter()
+            .find(|opt| opt.number == OptionNumber::Block2)
+            .unwrap()
+            .as_block()
+            .unwrap();
+        assert_eq!(block.size, BlockSize::S32);
+        assert!(block.more);
+        assert_eq!(
+            first.payload,
+            Some(&b"</sensors/temp>;rt=\"temperature-"[..])
+        );
+
+        let mut buffer = [0; 64];
+        let request = discovery_request(
+            &mut buffer,
+            None,
+            Some(BlockValue {
+                num: 1,
+                more: false,
+                size: BlockSize::S32,
+            }),
+        );
+        let mut response = [0; 64];
+        let second = respond(&request, &mut response);
+        assert_eq!(
+            second.payload,
+            Some(&b"c\";if=\"sensor\";ct=0;obs,</sensor"[..])
+        );
+    }
+}
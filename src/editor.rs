@@ -0,0 +1,498 @@
+use core::ops::Range;
+
+use crate::builder::encode_option_header;
+use crate::error::{CoapBuildError, CoapParseError};
+use crate::{Message, MessageType};
+
+type EditResult<T> = core::result::Result<T, CoapBuildError>;
+
+/// Location of an option within a packet.
+#[derive(Debug, Clone, Copy)]
+struct OptionSpan {
+    number: u16,
+    /// Offset of the option header
+    start: usize,
+    /// Offset of the option value
+    value_start: usize,
+    /// Offset just past the option value
+    end: usize,
+}
+
+/// A mutable view of an encoded CoAP message, which edits the header, token, options and payload
+/// in place. The packet occupies the start of the buffer and may grow into the rest of it.
+///
+/// Options are kept in order and their deltas are re-encoded as options are inserted and
+/// removed, shifting everything after them.
+///
+/// Source: [RFC 7252 3](https://datatracker.ietf.org/doc/html/rfc7252#section-3)
+#[derive(Debug)]
+pub struct MessageMut<'buf> {
+    buffer: &'buf mut [u8],
+    len: usize,
+}
+
+impl<'buf> MessageMut<'buf> {
+    /// Wrap the `len` byte packet at the start of `buffer`, checking that it is a valid message.
+    pub fn new(buffer: &'buf mut [u8], len: usize) -> Result<Self, CoapParseError> {
+        let packet = buffer.get(..len).ok_or(CoapParseError::MessageTooShort)?;
+        Message::parse(packet)?;
+
+        Ok(MessageMut { buffer, len })
+    }
+
+    /// Returns the encoded packet.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+
+    /// Returns the encoded packet, consuming the view.
+    pub fn into_bytes(self) -> &'buf [u8] {
+        &self.buffer[..self.len]
+    }
+
+    /// Parse the edited packet. Fails if the edits produced an invalid message, such as an Empty
+    /// message with a token, options or payload.
+    pub fn as_message(&self) -> Result<Message<'_>, CoapParseError> {
+        Message::parse(self.as_bytes())
+    }
+
+    /// Set the message type.
+    pub fn set_message_type(&mut self, message_type: MessageType) {
+        self.buffer[0] = (self.buffer[0] & 0b1100_1111) | (u8::from(message_type) << 4);
+    }
+
+    /// Set the message code.
+    pub fn set_code(&mut self, code: impl Into<u8>) {
+        self.buffer[1] = code.into();
+    }
+
+    /// Set the message ID.
+    pub fn set_message_id(&mut self, message_id: u16) {
+        self.buffer[2..4].copy_from_slice(&message_id.to_be_bytes());
+    }
+
+    /// Replace the token with one of between 0 and 8 bytes.
+    pub fn set_token(&mut self, token: &[u8]) -> EditResult<()> {
+        if token.len() > 8 {
+            return Err(CoapBuildError::TokenTooLong(token.len()));
+        }
+
+        self.splice(4..self.options_start(), &[token])?;
+        self.buffer[0] = (self.buffer[0] & 0xF0) | token.len() as u8;
+
+        Ok(())
+    }
+
+    /// Insert an option after any existing options with the same number.
+    pub fn insert_option(&mut self, number: impl Into<u16>, value: &[u8]) -> EditResult<()> {
+        let number = number.into();
+
+        let mut previous = 0;
+        let mut position = self.options_end();
+        let mut next = None;
+        for span in self.spans() {
+            if span.number > number {
+                position = span.start;
+                next = Some(span);
+                break;
+            }
+            previous = span.number;
+        }
+
+        let mut header = [0; 5];
+        let header_len = encode_option_header(number - previous, value.len(), &mut header)?;
+
+        // The delta of the following option now starts from the inserted option.
+        let mut next_header = [0; 5];
+        let (end, next_header_len) = match next {
+            Some(span) => (
+                span.value_start,
+                encode_option_header(
+                    span.number - number,
+                    span.end - span.value_start,
+                    &mut next_header,
+                )?,
+            ),
+            None => (position, 0),
+        };
+
+        self.splice(
+            position..end,
+            &[
+                &header[..header_len],
+                value,
+                &next_header[..next_header_len],
+            ],
+        )
+    }
+
+    /// Remove every option with the given number, returning how many were removed.
+    pub fn remove_option(&mut self, number: impl Into<u16>) -> usize {
+        let number = number.into();
+        let mut removed = 0;
+
+        while self.remove_first(number).is_some() {
+            removed += 1;
+        }
+
+        removed
+    }
+
+    fn remove_first(&mut self, number: u16) -> Option<()> {
+        let mut previous = 0;
+        let mut spans = self.spans();
+        let span = spans.find(|span| {
+            let found = span.number == number;
+            if !found {
+                previous = span.number;
+            }
+            found
+        })?;
+        let next = spans.next();
+        drop(spans);
+
+        // The delta of the following option now starts from the option before the removed one.
+        let mut next_header = [0; 5];
+        let (end, next_header_len) = match next {
+            Some(next) => (
+                next.value_start,
+                encode_option_header(
+                    next.number - previous,
+                    next.end - next.value_start,
+                    &mut next_header,
+                )
+                .ok()?,
+            ),
+            None => (span.end, 0),
+        };
+
+        // Removing an option never grows the packet, so this cannot run out of space.
+        self.splice(span.start..end, &[&next_header[..next_header_len]])
+            .ok()
+    }
+
+    /// Replace every option with the given number by a single option with `value`. If the buffer
+    /// is too small for the new value, the message is left unchanged.
+    pub fn replace_option(&mut self, number: impl Into<u16>, value: &[u8]) -> EditResult<()> {
+        let number = number.into();
+
+        let options_len = self.options_end() - self.options_start();
+        let len = self.len - options_len + self.replaced_options_len(number, value.len())?;
+        if len > self.buffer.len() {
+            return Err(CoapBuildError::BufferTooSmall);
+        }
+
+        self.remove_option(number);
+        self.insert_option(number, value)
+    }
+
+    /// Returns the encoded length of the options once those numbered `number` are replaced by a
+    /// single option with a `value_len` byte value.
+    fn replaced_options_len(&self, number: u16, value_len: usize) -> EditResult<usize> {
+        let spans = || {
+            self.spans()
+                .map(|span| (span.number, span.end - span.value_start))
+        };
+        let options = spans()
+            .filter(|&(option_number, _)| option_number < number)
+            .chain([(number, value_len)])
+            .chain(spans().filter(|&(option_number, _)| option_number > number));
+
+        let mut header = [0; 5];
+        let mut len = 0;
+        let mut previous = 0;
+        for (option_number, option_len) in options {
+            len += encode_option_header(option_number - previous, option_len, &mut header)?
+                + option_len;
+            previous = option_number;
+        }
+
+        Ok(len)
+    }
+
+    /// Replace the payload. An empty payload removes the payload and its marker.
+    pub fn set_payload(&mut self, payload: &[u8]) -> EditResult<()> {
+        let options_end = self.options_end();
+
+        if payload.is_empty() {
+            self.len = options_end;
+            return Ok(());
+        }
+
+        let end = options_end + 1 + payload.len();
+        if end > self.buffer.len() {
+            return Err(CoapBuildError::BufferTooSmall);
+        }
+
+        self.buffer[options_end] = 0xFF;
+        self.buffer[options_end + 1..end].copy_from_slice(payload);
+        self.len = end;
+
+        Ok(())
+    }
+
+    fn options_start(&self) -> usize {
+        4 + (self.buffer[0] & 0x0F) as usize
+    }
+
+    /// Returns the offset of the payload marker, or the end of the packet if there is none.
+    fn options_end(&self) -> usize {
+        self.spans()
+            .last()
+            .map_or(self.options_start(), |span| span.end)
+    }
+
+    fn spans(&self) -> impl Iterator<Item = OptionSpan> + '_ {
+        let packet = self.as_bytes();
+        let mut offset = self.options_start();
+        let mut number = 0u16;
+
+        core::iter::from_fn(move || {
+            let start = offset;
+            let header = *packet.get(start).filter(|&&byte| byte != 0xFF)?;
+            offset += 1;
+
+            let delta = read_extended(packet, &mut offset, header >> 4)?;
+            let length = read_extended(packet, &mut offset, header & 0x0F)?;
+            number = number.checked_add(delta as u16)?;
+
+            let value_start = offset;
+            offset += length;
+
+            Some(OptionSpan {
+                number,
+                start,
+                value_start,
+                end: offset,
+            })
+        })
+    }
+
+    /// Replace `range` of the packet by the concatenation of `parts`, moving the rest of the
+    /// packet to make room.
+    fn splice(&mut self, range: Range<usize>, parts: &[&[u8]]) -> EditResult<()> {
+        let new_len: usize = parts.iter().map(|part| part.len()).sum();
+        let tail_start = range.start + new_len;
+        let len = self.len - range.len() + new_len;
+
+        if len > self.buffer.len() {
+            return Err(CoapBuildError::BufferTooSmall);
+        }
+
+        self.buffer.copy_within(range.end..self.len, tail_start);
+
+        let mut offset = range.start;
+        for part in parts {
+            self.buffer[offset..offset + part.len()].copy_from_slice(part);
+            offset += part.len();
+        }
+
+        self.len = len;
+
+        Ok(())
+    }
+}
+
+/// Decode an option delta or length from its 4-bit field and any extended bytes at `offset`.
+fn read_extended(packet: &[u8], offset: &mut usize, field: u8) -> Option<usize> {
+    let value = match field {
+        0..=12 => field as usize,
+        13 => *packet.get(*offset)? as usize + 13,
+        14 => {
+            let ext = packet.get(*offset..*offset + 2)?;
+            u16::from_be_bytes([ext[0], ext[1]]) as usize + 269
+        }
+        _ => return None,
+    };
+
+    *offset += match field {
+        13 => 1,
+        14 => 2,
+        _ => 0,
+    };
+
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MessageBuilder, OptionNumber, RequestCode};
+
+    fn request(buffer: &mut [u8]) -> usize {
+        MessageBuilder::new(buffer)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(0x1234)
+            .token(&[1, 2])
+            .unwrap()
+            .option_string(OptionNumber::UriHost, "example.com")
+            .unwrap()
+            .option_string(OptionNumber::UriPath, "temp")
+            .unwrap()
+            .option_string(OptionNumber::ProxyUri, "coap://example.com/temp")
+            .unwrap()
+            .payload(b"hello")
+            .unwrap()
+            .len()
+    }
+
+    fn option_numbers(message: &Message<'_>) -> [Option<OptionNumber>; 4] {
+        let mut numbers = [None; 4];
+        for (slot, option) in numbers.iter_mut().zip(message.options) {
+            *slot = Some(option.number);
+        }
+        numbers
+    }
+
+    #[test]
+    fn edit_header_and_token() {
+        let mut buffer = [0; 96];
+        let len = request(&mut buffer);
+        let mut message = MessageMut::new(&mut buffer, len).unwrap();
+
+        message.set_message_type(MessageType::NonConfirmable);
+        message.set_code(RequestCode::Post);
+        message.set_message_id(0xBEEF);
+        message.set_token(&[9, 8, 7, 6, 5]).unwrap();
+        assert_eq!(
+            message.set_token(&[0; 9]),
+            Err(CoapBuildError::TokenTooLong(9))
+        );
+
+        let parsed = message.as_message().unwrap();
+        assert_eq!(parsed.message_type, MessageType::NonConfirmable);
+        assert_eq!(parsed.code, u8::from(RequestCode::Post));
+        assert_eq!(parsed.message_id, 0xBEEF);
+        assert_eq!(parsed.token, &[9, 8, 7, 6, 5]);
+        assert_eq!(parsed.options.into_iter().count(), 3);
+        assert_eq!(parsed.payload, Some(&b"hello"[..]));
+
+        message.set_token(&[]).unwrap();
+        assert_eq!(message.as_message().unwrap().token, &[]);
+    }
+
+    #[test]
+    fn insert_and_remove_options() {
+        let mut buffer = [0; 96];
+        let len = request(&mut buffer);
+        let mut message = MessageMut::new(&mut buffer, len).unwrap();
+
+        assert_eq!(message.remove_option(OptionNumber::ProxyUri), 1);
+        assert_eq!(message.remove_option(OptionNumber::ProxyUri), 0);
+        message
+            .insert_option(OptionNumber::UriPath, b"now")
+            .unwrap();
+        message.insert_option(OptionNumber::Observe, &[]).unwrap();
+
+        let parsed = message.as_message().unwrap();
+        assert_eq!(
+            option_numbers(&parsed),
+            [
+                Some(OptionNumber::UriHost),
+                Some(OptionNumber::Observe),
+                Some(OptionNumber::UriPath),
+                Some(OptionNumber::UriPath),
+            ]
+        );
+        let mut options = parsed.options.into_iter().skip(2);
+        assert_eq!(options.next().unwrap().as_str(), Ok("temp"));
+        assert_eq!(options.next().unwrap().as_str(), Ok("now"));
+        assert_eq!(parsed.payload, Some(&b"hello"[..]));
+
+        // Removing the first option re-encodes the delta of the next one.
+        assert_eq!(message.remove_option(OptionNumber::UriHost), 1);
+        assert_eq!(message.remove_option(OptionNumber::UriPath), 2);
+        let parsed = message.as_message().unwrap();
+        assert_eq!(
+            option_numbers(&parsed),
+            [Some(OptionNumber::Observe), None, None, None]
+        );
+    }
+
+    #[test]
+    fn replace_options_with_extended_deltas() {
+        let mut buffer = [0; 96];
+        let len = request(&mut buffer);
+        let mut message = MessageMut::new(&mut buffer, len).unwrap();
+
+        message
+            .replace_option(OptionNumber::UriPath, b"humidity")
+            .unwrap();
+        message
+            .insert_option(OptionNumber::UnknownOption(300), b"x")
+            .unwrap();
+        message.remove_option(OptionNumber::ProxyUri);
+
+        let parsed = message.as_message().unwrap();
+        assert_eq!(
+            option_numbers(&parsed),
+            [
+                Some(OptionNumber::UriHost),
+                Some(OptionNumber::UriPath),
+                Some(OptionNumber::UnknownOption(300)),
+                None,
+            ]
+        );
+        assert_eq!(
+            parsed.options.into_iter().nth(1).unwrap().as_str(),
+            Ok("humidity")
+        );
+    }
+
+    #[test]
+    fn failed_replace_leaves_message_unchanged() {
+        let mut buffer = [0; 96];
+        let len = request(&mut buffer);
+        let mut message = MessageMut::new(&mut buffer, len).unwrap();
+        let mut original = [0; 96];
+        original[..len].copy_from_slice(message.as_bytes());
+
+        assert_eq!(
+            message.replace_option(11u16, &[b'x'; 96]),
+            Err(CoapBuildError::BufferTooSmall)
+        );
+        assert_eq!(message.as_bytes(), &original[..len]);
+
+        message.replace_option(11u16, b"temp").unwrap();
+        assert_eq!(message.as_bytes(), &original[..len]);
+    }
+
+    #[test]
+    fn edit_payload() {
+        let mut buffer = [0; 96];
+        let len = request(&mut buffer);
+        let mut message = MessageMut::new(&mut buffer, len).unwrap();
+
+        message.set_payload(b"a longer payload").unwrap();
+        assert_eq!(
+            message.as_message().unwrap().payload,
+            Some(&b"a longer payload"[..])
+        );
+
+        message.set_payload(&[]).unwrap();
+        assert_eq!(message.as_message().unwrap().payload, None);
+        message.insert_option(OptionNumber::Size1, &[5]).unwrap();
+        assert_eq!(message.as_message().unwrap().options.into_iter().count(), 4);
+
+        assert_eq!(
+            message.set_payload(&[0; 96]),
+            Err(CoapBuildError::BufferTooSmall)
+        );
+    }
+
+    #[test]
+    fn grow_beyond_buffer() {
+        let mut buffer = [0; 64];
+        let len = request(&mut buffer);
+        let before = buffer;
+        let mut message = MessageMut::new(&mut buffer, len).unwrap();
+
+        assert_eq!(
+            message.insert_option(OptionNumber::UriQuery, &[b'q'; 32]),
+            Err(CoapBuildError::BufferTooSmall)
+        );
+        assert_eq!(message.as_bytes(), &before[..len]);
+        assert!(MessageMut::new(&mut buffer, 65).is_err());
+    }
+}
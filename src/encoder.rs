@@ -0,0 +1,316 @@
+use core::convert::Infallible;
+
+use crate::builder::{encode_option_header, uint_to_minimal_bytes};
+use crate::error::{CoapBuildError, CoapEncodeError};
+use crate::{MessageType, Version};
+
+type EncodeResult<T, E> = core::result::Result<T, CoapEncodeError<E>>;
+
+/// A destination for encoded messages, such as a socket or a ring buffer.
+pub trait MessageSink {
+    /// Error returned when bytes cannot be written.
+    type Error;
+
+    /// Write all of `bytes`, in order.
+    fn write_all(&mut self, bytes: &[u8]) -> Result<(), Self::Error>;
+}
+
+/// A [`MessageSink`] that discards the bytes written to it and counts them, to find the exact
+/// encoded length of a message before encoding it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SizeCounter {
+    size: usize,
+}
+
+impl SizeCounter {
+    /// Create a counter at zero.
+    pub const fn new() -> Self {
+        SizeCounter { size: 0 }
+    }
+
+    /// Returns the number of bytes written so far.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+impl MessageSink for SizeCounter {
+    type Error = Infallible;
+
+    fn write_all(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.size += bytes.len();
+        Ok(())
+    }
+}
+
+impl<S: MessageSink + ?Sized> MessageSink for &mut S {
+    type Error = S::Error;
+
+    fn write_all(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        (**self).write_all(bytes)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl MessageSink for alloc::vec::Vec<u8> {
+    type Error = Infallible;
+
+    fn write_all(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.extend_from_slice(bytes);
+        Ok(())
+    }
+}
+
+/// Adapter writing messages into an [`embedded_io::Write`].
+#[cfg(feature = "embedded-io")]
+#[derive(Debug)]
+pub struct IoSink<W>(pub W);
+
+#[cfg(feature = "embedded-io")]
+impl<W: embedded_io::Write> MessageSink for IoSink<W> {
+    type Error = W::Error;
+
+    fn write_all(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.0.write_all(bytes)
+    }
+}
+
+/// Encodes a CoAP message directly into a [`MessageSink`] as it is described, without a
+/// contiguous buffer. Options must be added in ascending order, as with [`MessageBuilder`].
+///
+/// Encoding into a [`SizeCounter`] gives the exact length the message will have.
+///
+/// Source: [RFC 7252 3](https://datatracker.ietf.org/doc/html/rfc7252#section-3)
+///
+/// [`MessageBuilder`]: crate::MessageBuilder
+#[derive(Debug)]
+pub struct MessageEncoder<S: MessageSink> {
+    sink: S,
+    last_option_number: u16,
+}
+
+impl<S: MessageSink> MessageEncoder<S> {
+    /// Start a message by writing its header and a token of between 0 and 8 bytes.
+    pub fn new(
+        sink: S,
+        msg_type: MessageType,
+        code: impl Into<u8>,
+        message_id: u16,
+        token: &[u8],
+    ) -> EncodeResult<Self, S::Error> {
+        if token.len() > 8 {
+            return Err(CoapBuildError::TokenTooLong(token.len()).into());
+        }
+
+        let mut encoder = MessageEncoder {
+            sink,
+            last_option_number: 0,
+        };
+
+        let [id_high, id_low] = message_id.to_be_bytes();
+        encoder.write(&[
+            (u8::from(Version::V1) << 6) | (u8::from(msg_type) << 4) | token.len() as u8,
+            code.into(),
+            id_high,
+            id_low,
+        ])?;
+        encoder.write(token)?;
+
+        Ok(encoder)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> EncodeResult<(), S::Error> {
+        self.sink.write_all(bytes).map_err(CoapEncodeError::Write)
+    }
+
+    /// Add an option.
+    pub fn option(
+        mut self,
+        option_number: impl Into<u16>,
+        value: &[u8],
+    ) -> EncodeResult<Self, S::Error> {
+        let option_number = option_number.into();
+
+        if option_number < self.last_option_number {
+            return Err(CoapBuildError::OptionNumberOutOfOrder.into());
+        }
+
+        let mut header = [0; 5];
+        let header_len = encode_option_header(
+            option_number - self.last_option_number,
+            value.len(),
+            &mut header,
+        )?;
+
+        self.write(&header[..header_len])?;
+        self.write(value)?;
+        self.last_option_number = option_number;
+
+        Ok(self)
+    }
+
+    /// Add an option with a UTF8 string value.
+    pub fn option_string(
+        self,
+        option_number: impl Into<u16>,
+        value: &str,
+    ) -> EncodeResult<Self, S::Error> {
+        self.option(option_number, value.as_bytes())
+    }
+
+    /// Add an option with an unsigned integer value, encoded with minimal bytes.
+    pub fn option_uint(
+        self,
+        option_number: impl Into<u16>,
+        value: impl Into<u64>,
+    ) -> EncodeResult<Self, S::Error> {
+        let (bytes, start) = uint_to_minimal_bytes(value.into());
+        self.option(option_number, &bytes[start..])
+    }
+
+    /// Add a payload, completing the message and returning the sink.
+    pub fn payload(mut self, payload: &[u8]) -> EncodeResult<S, S::Error> {
+        if payload.is_empty() {
+            return Err(CoapBuildError::PayloadMarkerWithoutPayload.into());
+        }
+
+        self.write(&[0xFF])?;
+        self.write(payload)?;
+
+        Ok(self.sink)
+    }
+
+    /// Complete the message without a payload, returning the sink.
+    pub fn no_payload(self) -> S {
+        self.sink
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MessageBuilder, OptionNumber, RequestCode};
+
+    /// A sink writing into two separate halves, like a wrapped ring buffer.
+    struct SplitSink<'a> {
+        first: &'a mut [u8],
+        second: &'a mut [u8],
+        len: usize,
+    }
+
+    impl MessageSink for SplitSink<'_> {
+        type Error = ();
+
+        fn write_all(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+            for &byte in bytes {
+                let first_len = self.first.len();
+                let slot = match self.len.checked_sub(first_len) {
+                    None => &mut self.first[self.len],
+                    Some(i) => self.second.get_mut(i).ok_or(())?,
+                };
+                *slot = byte;
+                self.len += 1;
+            }
+            Ok(())
+        }
+    }
+
+    fn encode<S: MessageSink>(sink: S) -> EncodeResult<S, S::Error> {
+        MessageEncoder::new(
+            sink,
+            MessageType::Confirmable,
+            RequestCode::Put,
+            0x1234,
+            &[1, 2],
+        )?
+        .option_string(OptionNumber::UriPath, "config")?
+        .option_uint(OptionNumber::ContentFormat, 50u8)?
+        .option(OptionNumber::UnknownOption(2000), &[0xAB; 300])?
+        .payload(b"{}")
+    }
+
+    #[test]
+    fn matches_builder_output() -> Result<(), CoapBuildError> {
+        let mut buffer = [0; 512];
+        let expected = MessageBuilder::new(&mut buffer)?
+            .request(MessageType::Confirmable, RequestCode::Put)
+            .message_id(0x1234)
+            .token(&[1, 2])?
+            .option_string(OptionNumber::UriPath, "config")?
+            .option_uint(OptionNumber::ContentFormat, 50u8)?
+            .option(OptionNumber::UnknownOption(2000), &[0xAB; 300])?
+            .payload(b"{}")?
+            .build();
+
+        assert_eq!(encode(SizeCounter::new()).unwrap().size(), expected.len());
+
+        let mut first = [0; 100];
+        let mut second = [0; 300];
+        let sink = encode(SplitSink {
+            first: &mut first,
+            second: &mut second,
+            len: 0,
+        })
+        .unwrap();
+        assert_eq!(sink.len, expected.len());
+        assert_eq!(&first[..], &expected[..100]);
+        assert_eq!(&second[..expected.len() - 100], &expected[100..]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn reports_errors() {
+        let mut first = [0; 8];
+        let mut second = [0; 8];
+        let result = encode(SplitSink {
+            first: &mut first,
+            second: &mut second,
+            len: 0,
+        });
+        assert!(matches!(result, Err(CoapEncodeError::Write(()))));
+
+        let result = MessageEncoder::new(
+            SizeCounter::new(),
+            MessageType::NonConfirmable,
+            RequestCode::Get,
+            1,
+            &[],
+        )
+        .and_then(|encoder| encoder.option_uint(OptionNumber::Accept, 0u8))
+        .and_then(|encoder| encoder.option_uint(OptionNumber::UriPath, 0u8));
+        assert_eq!(
+            result.unwrap_err(),
+            CoapEncodeError::Build(CoapBuildError::OptionNumberOutOfOrder)
+        );
+
+        let result = MessageEncoder::new(
+            SizeCounter::new(),
+            MessageType::NonConfirmable,
+            RequestCode::Get,
+            1,
+            &[0; 9],
+        );
+        assert_eq!(
+            result.unwrap_err(),
+            CoapEncodeError::Build(CoapBuildError::TokenTooLong(9))
+        );
+    }
+
+    #[cfg(feature = "embedded-io")]
+    #[test]
+    fn writes_into_embedded_io() {
+        let mut buffer = [0; 512];
+        let size = encode(SizeCounter::new()).unwrap().size();
+
+        let IoSink(remaining) = encode(IoSink(&mut buffer[..])).unwrap();
+        assert_eq!(remaining.len(), 512 - size);
+
+        let mut small = [0; 16];
+        assert!(matches!(
+            encode(IoSink(&mut small[..])),
+            Err(CoapEncodeError::Write(_))
+        ));
+    }
+}
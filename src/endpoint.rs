@@ -0,0 +1,572 @@
+use core::time::Duration;
+
+use crate::error::CoapEndpointError;
+use crate::{Message, MessageType};
+
+/// Maximum time a datagram is expected to take from the start of its transmission to the
+/// completion of its reception.
+///
+/// Source: [RFC 7252 4.8.2](https://datatracker.ietf.org/doc/html/rfc7252#section-4.8.2)
+const MAX_LATENCY: Duration = Duration::from_secs(100);
+
+/// Multiply `duration` by `factor`, saturating on overflow. Factors below 1, which the
+/// transmission parameters do not allow, are treated as 1.
+fn scale(duration: Duration, factor: f32) -> Duration {
+    Duration::try_from_secs_f64(duration.as_secs_f64() * f64::from(factor.max(1.0)))
+        .unwrap_or(Duration::MAX)
+}
+
+/// A monotonic time source.
+pub trait Clock {
+    /// Returns the time elapsed since an arbitrary, fixed point in the past.
+    fn now(&self) -> Duration;
+}
+
+/// Message transmission parameters.
+///
+/// Source: [RFC 7252 4.8](https://datatracker.ietf.org/doc/html/rfc7252#section-4.8)
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TransmissionParameters {
+    /// Initial retransmission timeout of a Confirmable message (ACK_TIMEOUT)
+    pub ack_timeout: Duration,
+    /// Factor by which the initial timeout is randomly extended (ACK_RANDOM_FACTOR)
+    pub ack_random_factor: f32,
+    /// Maximum number of retransmissions of a Confirmable message (MAX_RETRANSMIT)
+    pub max_retransmit: u8,
+}
+
+impl Default for TransmissionParameters {
+    fn default() -> Self {
+        Self {
+            ack_timeout: Duration::from_secs(2),
+            ack_random_factor: 1.5,
+            max_retransmit: 4,
+        }
+    }
+}
+
+impl TransmissionParameters {
+    /// Maximum time from the first transmission of a Confirmable message to its last
+    /// retransmission (MAX_TRANSMIT_SPAN).
+    pub fn max_transmit_span(&self) -> Duration {
+        let retransmissions = 1u32
+            .checked_shl(self.max_retransmit.into())
+            .map_or(u32::MAX, |n| n - 1);
+        let span = self
+            .ack_timeout
+            .checked_mul(retransmissions)
+            .unwrap_or(Duration::MAX);
+
+        scale(span, self.ack_random_factor)
+    }
+
+    /// Time from starting to send a Confirmable message to the time when an acknowledgement is no
+    /// longer expected and its message ID can be safely reused (EXCHANGE_LIFETIME).
+    pub fn exchange_lifetime(&self) -> Duration {
+        self.max_transmit_span()
+            .saturating_add(2 * MAX_LATENCY)
+            .saturating_add(self.ack_timeout)
+    }
+
+    /// Time from sending a Non-confirmable message to the time its message ID can be safely
+    /// reused (NON_LIFETIME).
+    pub fn non_lifetime(&self) -> Duration {
+        self.max_transmit_span().saturating_add(MAX_LATENCY)
+    }
+}
+
+/// A Confirmable message awaiting acknowledgement.
+struct Outstanding<E, const L: usize> {
+    peer: E,
+    message_id: u16,
+    datagram: [u8; L],
+    len: usize,
+    retransmissions: u8,
+    timeout: Duration,
+    deadline: Duration,
+}
+
+/// A recently received message, kept for duplicate detection.
+struct Recent<E, const L: usize> {
+    peer: E,
+    message_id: u16,
+    expires: Duration,
+    reply: [u8; L],
+    reply_len: Option<usize>,
+}
+
+/// Outcome of passing a received datagram to [`Endpoint::receive`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Received<'a, 's> {
+    /// A new Confirmable or Non-confirmable message to be processed by the application.
+    New(Message<'a>),
+    /// A duplicate of a recently received message, which must not be processed again. Contains
+    /// the reply sent for the original message, if any, which should be sent again.
+    Duplicate(Option<&'s [u8]>),
+    /// An Acknowledgement for an outstanding Confirmable message, possibly carrying a
+    /// piggybacked response.
+    Acknowledged(Message<'a>),
+    /// A Reset for an outstanding Confirmable message.
+    Reset(Message<'a>),
+    /// An Acknowledgement or Reset that does not match any outstanding message.
+    Unmatched(Message<'a>),
+}
+
+/// An event produced by [`Endpoint::poll`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum EndpointEvent<'s, E> {
+    /// An outstanding Confirmable message has timed out and must be sent again.
+    Retransmit {
+        /// Peer to send the datagram to
+        peer: &'s E,
+        /// Datagram to send
+        datagram: &'s [u8],
+    },
+    /// An outstanding Confirmable message was not acknowledged after the maximum number of
+    /// retransmissions, and the peer should be considered unreachable.
+    Timeout {
+        /// Peer the message was sent to
+        peer: E,
+        /// Message ID of the message
+        message_id: u16,
+    },
+}
+
+/// Transport-agnostic CoAP message layer.
+///
+/// Tracks outstanding Confirmable messages for retransmission with exponential back-off, matches
+/// Acknowledgement and Reset messages by message ID, and remembers recently received message IDs
+/// so duplicates are answered with the original reply instead of being processed again.
+///
+/// `N` is the number of outstanding and recent messages that can be tracked, and `L` the maximum
+/// size of a stored datagram.
+///
+/// Source: [RFC 7252 4](https://datatracker.ietf.org/doc/html/rfc7252#section-4)
+pub struct Endpoint<E, C, const N: usize, const L: usize> {
+    clock: C,
+    parameters: TransmissionParameters,
+    random: u32,
+    next_message_id: u16,
+    outstanding: [Option<Outstanding<E, L>>; N],
+    recent: [Option<Recent<E, L>>; N],
+}
+
+impl<E: Clone + PartialEq, C: Clock, const N: usize, const L: usize> Endpoint<E, C, N, L> {
+    /// Create an endpoint using the default transmission parameters. The `seed` should be random,
+    /// and is used to pick the initial message ID and randomize retransmission timeouts.
+    pub fn new(clock: C, seed: u32) -> Self {
+        Self::with_parameters(clock, TransmissionParameters::default(), seed)
+    }
+
+    /// Create an endpoint using custom transmission parameters.
+    pub fn with_parameters(clock: C, parameters: TransmissionParameters, seed: u32) -> Self {
+        const {
+            assert!(
+                N > 0,
+                "endpoint must have capacity for at least one message"
+            )
+        };
+
+        let mut endpoint = Self {
+            clock,
+            parameters,
+            random: if seed == 0 { 0x9E37_79B9 } else { seed },
+            next_message_id: 0,
+            outstanding: [const { None }; N],
+            recent: [const { None }; N],
+        };
+
+        endpoint.next_message_id = endpoint.next_random() as u16;
+        endpoint
+    }
+
+    /// Returns the transmission parameters of the endpoint.
+    pub fn parameters(&self) -> &TransmissionParameters {
+        &self.parameters
+    }
+
+    /// Returns the time source of the endpoint.
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// Returns a fresh message ID for a new message.
+    pub fn next_message_id(&mut self) -> u16 {
+        let id = self.next_message_id;
+        self.next_message_id = self.next_message_id.wrapping_add(1);
+        id
+    }
+
+    /// Returns the number of Confirmable messages awaiting acknowledgement.
+    pub fn outstanding(&self) -> usize {
+        self.outstanding.iter().flatten().count()
+    }
+
+    /// Register a datagram that is being sent to `peer`.
+    ///
+    /// Confirmable messages are tracked for retransmission until they are acknowledged.
+    /// Acknowledgement and Reset messages are remembered as the reply to the message they refer
+    /// to, so that duplicates of that message are answered the same way.
+    pub fn send(&mut self, peer: E, datagram: &[u8]) -> Result<(), CoapEndpointError> {
+        let message = Message::parse(datagram)?;
+        let now = self.clock.now();
+
+        match message.message_type {
+            MessageType::Confirmable => {
+                if datagram.len() > L {
+                    return Err(CoapEndpointError::MessageTooLarge(datagram.len()));
+                }
+
+                let timeout = self.initial_timeout();
+                let slot = self
+                    .outstanding
+                    .iter_mut()
+                    .find(|slot| slot.is_none())
+                    .ok_or(CoapEndpointError::TooManyOutstanding)?;
+
+                let mut stored = [0; L];
+                stored[..datagram.len()].copy_from_slice(datagram);
+
+                *slot = Some(Outstanding {
+                    peer,
+                    message_id: message.message_id,
+                    datagram: stored,
+                    len: datagram.len(),
+                    retransmissions: 0,
+                    timeout,
+                    deadline: now.saturating_add(timeout),
+                });
+            }
+            MessageType::Acknowledgement | MessageType::Reset => {
+                let recent =
+                    self.recent.iter_mut().flatten().find(|recent| {
+                        recent.peer == peer && recent.message_id == message.message_id
+                    });
+
+                if let Some(recent) = recent {
+                    if datagram.len() > L {
+                        return Err(CoapEndpointError::MessageTooLarge(datagram.len()));
+                    }
+
+                    recent.reply[..datagram.len()].copy_from_slice(datagram);
+                    recent.reply_len = Some(datagram.len());
+                }
+            }
+            MessageType::NonConfirmable => {}
+        }
+
+        Ok(())
+    }
+
+    /// Process a datagram received from `peer`.
+    pub fn receive<'a>(
+        &mut self,
+        peer: E,
+        datagram: &'a [u8],
+    ) -> Result<Received<'a, '_>, CoapEndpointError> {
+        let message = Message::parse(datagram)?;
+        let now = self.clock.now();
+
+        match message.message_type {
+            MessageType::Acknowledgement | MessageType::Reset => {
+                let slot = self.outstanding.iter_mut().find(|slot| {
+                    slot.as_ref().is_some_and(|outstanding| {
+                        outstanding.peer == peer && outstanding.message_id == message.message_id
+                    })
+                });
+
+                let Some(slot) = slot else {
+                    return Ok(Received::Unmatched(message));
+                };
+                *slot = None;
+
+                if message.message_type == MessageType::Reset {
+                    Ok(Received::Reset(message))
+                } else {
+                    Ok(Received::Acknowledged(message))
+                }
+            }
+            MessageType::Confirmable | MessageType::NonConfirmable => {
+                self.expire_recent(now);
+
+                let duplicate = self.recent.iter().position(|slot| {
+                    slot.as_ref().is_some_and(|recent| {
+                        recent.peer == peer && recent.message_id == message.message_id
+                    })
+                });
+
+                if let Some(index) = duplicate {
+                    let reply = self.recent[index]
+                        .as_ref()
+                        .and_then(|recent| recent.reply_len.map(|len| &recent.reply[..len]));
+                    return Ok(Received::Duplicate(reply));
+                }
+
+                let lifetime = match message.message_type {
+                    MessageType::Confirmable => self.parameters.exchange_lifetime(),
+                    _ => self.parameters.non_lifetime(),
+                };
+
+                self.remember(Recent {
+                    peer,
+                    message_id: message.message_id,
+                    expires: now.saturating_add(lifetime),
+                    reply: [0; L],
+                    reply_len: None,
+                });
+
+                Ok(Received::New(message))
+            }
+        }
+    }
+
+    /// Returns the time until the next retransmission is due, if any message is outstanding.
+    pub fn next_timeout(&self) -> Option<Duration> {
+        let now = self.clock.now();
+
+        self.outstanding
+            .iter()
+            .flatten()
+            .map(|outstanding| outstanding.deadline.saturating_sub(now))
+            .min()
+    }
+
+    /// Check for outstanding Confirmable messages whose retransmission timeout has expired.
+    /// Should be called at least as often as [`next_timeout`](Endpoint::next_timeout) suggests,
+    /// until it returns `None`.
+    pub fn poll(&mut self) -> Option<EndpointEvent<'_, E>> {
+        let now = self.clock.now();
+        let max_retransmit = self.parameters.max_retransmit;
+
+        let slot = self
+            .outstanding
+            .iter_mut()
+            .filter(|slot| slot.as_ref().is_some_and(|o| o.deadline <= now))
+            .min_by_key(|slot| slot.as_ref().map(|o| o.deadline))?;
+
+        if slot
+            .as_ref()
+            .is_some_and(|o| o.retransmissions >= max_retransmit)
+        {
+            let outstanding = slot.take()?;
+            return Some(EndpointEvent::Timeout {
+                peer: outstanding.peer,
+                message_id: outstanding.message_id,
+            });
+        }
+
+        let outstanding = slot.as_mut()?;
+        outstanding.retransmissions += 1;
+        outstanding.timeout = outstanding.timeout.saturating_mul(2);
+        outstanding.deadline = now.saturating_add(outstanding.timeout);
+
+        Some(EndpointEvent::Retransmit {
+            peer: &outstanding.peer,
+            datagram: &outstanding.datagram[..outstanding.len],
+        })
+    }
+
+    fn initial_timeout(&mut self) -> Duration {
+        let fraction = self.next_random() as f32 / u32::MAX as f32;
+        let factor = 1.0 + (self.parameters.ack_random_factor - 1.0) * fraction;
+
+        scale(self.parameters.ack_timeout, factor)
+    }
+
+    fn next_random(&mut self) -> u32 {
+        // xorshift32
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 17;
+        self.random ^= self.random << 5;
+        self.random
+    }
+
+    fn expire_recent(&mut self, now: Duration) {
+        for slot in self.recent.iter_mut() {
+            if slot.as_ref().is_some_and(|recent| recent.expires <= now) {
+                *slot = None;
+            }
+        }
+    }
+
+    fn remember(&mut self, recent: Recent<E, L>) {
+        // When full, the entry closest to expiring is evicted.
+        let slot = match self.recent.iter().position(Option::is_none) {
+            Some(index) => &mut self.recent[index],
+            None => self
+                .recent
+                .iter_mut()
+                .min_by_key(|slot| slot.as_ref().map(|recent| recent.expires))
+                .expect("endpoint must have capacity for at least one message"),
+        };
+
+        *slot = Some(recent);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::Cell;
+
+    use super::*;
+    use crate::{MessageBuilder, RequestCode, ResponseCode};
+
+    struct TestClock(Cell<Duration>);
+
+    impl TestClock {
+        fn advance(&self, by: Duration) {
+            self.0.set(self.0.get() + by);
+        }
+    }
+
+    impl Clock for &TestClock {
+        fn now(&self) -> Duration {
+            self.0.get()
+        }
+    }
+
+    fn request(buffer: &mut [u8], msg_type: MessageType, message_id: u16) -> &[u8] {
+        MessageBuilder::new(buffer)
+            .unwrap()
+            .request(msg_type, RequestCode::Get)
+            .message_id(message_id)
+            .token(&[0x01])
+            .unwrap()
+            .no_payload()
+            .build()
+    }
+
+    #[test]
+    fn default_lifetimes() {
+        let parameters = TransmissionParameters::default();
+
+        assert_eq!(parameters.max_transmit_span(), Duration::from_secs(45));
+        assert_eq!(parameters.exchange_lifetime(), Duration::from_secs(247));
+        assert_eq!(parameters.non_lifetime(), Duration::from_secs(145));
+
+        let extreme = TransmissionParameters {
+            ack_timeout: Duration::MAX,
+            ack_random_factor: f32::NAN,
+            max_retransmit: 64,
+        };
+        assert_eq!(extreme.max_transmit_span(), Duration::MAX);
+        assert_eq!(extreme.exchange_lifetime(), Duration::MAX);
+    }
+
+    #[test]
+    fn retransmits_with_backoff_until_timeout() {
+        let clock = TestClock(Cell::new(Duration::ZERO));
+        let mut endpoint = Endpoint::<u8, _, 2, 32>::new(&clock, 1234);
+
+        let mut buffer = [0; 32];
+        let packet = request(&mut buffer, MessageType::Confirmable, 7);
+        endpoint.send(1, packet).unwrap();
+
+        let first = endpoint.next_timeout().unwrap();
+        assert!(first >= Duration::from_secs(2) && first <= Duration::from_secs(3));
+        assert_eq!(endpoint.poll(), None);
+
+        let mut timeout = first;
+        for _ in 0..4 {
+            clock.advance(timeout);
+            assert_eq!(
+                endpoint.poll(),
+                Some(EndpointEvent::Retransmit {
+                    peer: &1,
+                    datagram: packet
+                })
+            );
+            timeout *= 2;
+            assert_eq!(endpoint.next_timeout(), Some(timeout));
+        }
+
+        clock.advance(timeout);
+        assert_eq!(
+            endpoint.poll(),
+            Some(EndpointEvent::Timeout {
+                peer: 1,
+                message_id: 7
+            })
+        );
+        assert_eq!(endpoint.outstanding(), 0);
+    }
+
+    #[test]
+    fn ack_and_reset_match_outstanding() {
+        let clock = TestClock(Cell::new(Duration::ZERO));
+        let mut endpoint = Endpoint::<u8, _, 2, 32>::new(&clock, 1);
+
+        let mut buffer = [0; 32];
+        endpoint
+            .send(1, request(&mut buffer, MessageType::Confirmable, 7))
+            .unwrap();
+        let mut buffer = [0; 32];
+        endpoint
+            .send(1, request(&mut buffer, MessageType::Confirmable, 8))
+            .unwrap();
+        assert_eq!(
+            endpoint.send(1, request(&mut buffer, MessageType::Confirmable, 9)),
+            Err(CoapEndpointError::TooManyOutstanding)
+        );
+
+        // Wrong peer
+        let ack = [0x60, 0x45, 0x00, 0x07];
+        assert!(matches!(
+            endpoint.receive(2, &ack),
+            Ok(Received::Unmatched(_))
+        ));
+
+        let ack = [0x60, 0x45, 0x00, 0x07];
+        assert!(matches!(
+            endpoint.receive(1, &ack),
+            Ok(Received::Acknowledged(message)) if message.code == u8::from(ResponseCode::Content)
+        ));
+
+        let reset = [0x70, 0x00, 0x00, 0x08];
+        assert!(matches!(
+            endpoint.receive(1, &reset),
+            Ok(Received::Reset(_))
+        ));
+        assert_eq!(endpoint.outstanding(), 0);
+    }
+
+    #[test]
+    fn duplicates_are_answered_with_cached_reply() {
+        let clock = TestClock(Cell::new(Duration::ZERO));
+        let mut endpoint = Endpoint::<u8, _, 2, 32>::new(&clock, 1);
+
+        let mut buffer = [0; 32];
+        let packet = request(&mut buffer, MessageType::Confirmable, 0x1234);
+
+        let Ok(Received::New(message)) = endpoint.receive(1, packet) else {
+            panic!("expected a new message");
+        };
+
+        let mut tx_buf = [0; 32];
+        let reply = MessageBuilder::new(&mut tx_buf)
+            .unwrap()
+            .response(MessageType::Acknowledgement, ResponseCode::Content)
+            .message_id(message.message_id)
+            .token(message.token)
+            .unwrap()
+            .payload(b"hi")
+            .unwrap()
+            .build();
+        endpoint.send(1, reply).unwrap();
+
+        assert_eq!(
+            endpoint.receive(1, packet),
+            Ok(Received::Duplicate(Some(reply)))
+        );
+
+        // The same message ID from another peer is not a duplicate.
+        assert!(matches!(endpoint.receive(2, packet), Ok(Received::New(_))));
+
+        // After EXCHANGE_LIFETIME, the message ID may be reused.
+        clock.advance(Duration::from_secs(248));
+        assert!(matches!(endpoint.receive(1, packet), Ok(Received::New(_))));
+    }
+}
@@ -1,3 +1,6 @@
+use crate::ResponseCode;
+use crate::cbor::CborError;
+
 /// Errors that can occur when building a CoAP message.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -12,6 +15,18 @@ pub enum CoapBuildError {
     /// Options must be added in ascending order by option number.
     /// An attempt was made to add an option with a number less than or equal to the previous option.
     OptionNumberOutOfOrder,
+    /// The block number does not fit in the 20 bits available in a Block1/Block2 option.
+    /// Contains the block number that was provided.
+    BlockNumberTooLarge(u32),
+    /// The option value is longer than the 65804 bytes an option header can describe.
+    /// Contains the length that was provided.
+    OptionTooLong(usize),
+    /// More options were added than the builder has room to stage.
+    /// Contains the number of options the builder can stage.
+    TooManyOptions(usize),
+    /// The option value is outside the range allowed for the option.
+    /// Contains the option number.
+    InvalidOptionValue(u16),
 }
 
 impl core::fmt::Display for CoapBuildError {
@@ -25,6 +40,18 @@ impl core::fmt::Display for CoapBuildError {
                 write!(f, "Payload marker without payload")
             }
             CoapBuildError::OptionNumberOutOfOrder => write!(f, "Option number out of order"),
+            CoapBuildError::BlockNumberTooLarge(num) => {
+                write!(f, "Block number too large (expected < 2^20, got {})", num)
+            }
+            CoapBuildError::OptionTooLong(len) => {
+                write!(f, "Option too long (expected <= 65804, got {})", len)
+            }
+            CoapBuildError::TooManyOptions(capacity) => {
+                write!(f, "Too many options (expected <= {})", capacity)
+            }
+            CoapBuildError::InvalidOptionValue(number) => {
+                write!(f, "Invalid value for option {}", number)
+            }
         }
     }
 }
@@ -52,6 +79,14 @@ pub enum CoapParseError {
     EmptyMessageWithData,
     /// A payload marker (0xFF) was present but no payload data followed it.
     PayloadMarkerWithoutPayload,
+    /// A Block1/Block2 option value is longer than 3 bytes.
+    InvalidBlockOption,
+    /// The length field of a WebSocket message is not 0, as required by RFC 8323, or the length
+    /// of a TCP/TLS message does not fit in memory. Contains the length field that was found.
+    InvalidFrameLength(u8),
+    /// A Block1/Block2 option uses the size exponent 7, which is reserved outside of BERT
+    /// (RFC 8323) transports.
+    ReservedBlockSize,
 }
 
 impl core::fmt::Display for CoapParseError {
@@ -70,8 +105,626 @@ impl core::fmt::Display for CoapParseError {
             CoapParseError::PayloadMarkerWithoutPayload => {
                 write!(f, "Payload marker present but no payload data")
             }
+            CoapParseError::InvalidBlockOption => {
+                write!(f, "Invalid block option (expected 0-3 bytes)")
+            }
+            CoapParseError::ReservedBlockSize => write!(f, "Reserved block size exponent (7)"),
+            CoapParseError::InvalidFrameLength(len) => {
+                write!(f, "Invalid frame length field ({})", len)
+            }
         }
     }
 }
 
 impl core::error::Error for CoapParseError {}
+
+/// Errors that can occur when managing the observers of a resource.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CoapObserveError {
+    /// The observer registry has no free slots for a new observer.
+    RegistryFull,
+    /// The Observe option in the request has a value other than 0 (register) or 1 (deregister).
+    /// Contains the value that was found.
+    InvalidObserveValue(u32),
+}
+
+impl core::fmt::Display for CoapObserveError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CoapObserveError::RegistryFull => write!(f, "Observer registry full"),
+            CoapObserveError::InvalidObserveValue(v) => {
+                write!(f, "Invalid Observe value (expected 0 or 1, got {})", v)
+            }
+        }
+    }
+}
+
+impl core::error::Error for CoapObserveError {}
+
+/// Errors that can occur during a block-wise transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CoapBlockError {
+    /// A block option could not be decoded. Contains the underlying parse error.
+    InvalidBlockOption(CoapParseError),
+    /// A block did not start where the previous block ended. Contains the offset that was
+    /// expected. Block1 transfers should be answered with 4.08 (Request Entity Incomplete).
+    Incomplete(usize),
+    /// The body does not fit in the reassembly buffer. Contains the maximum body size. Block1
+    /// transfers should be answered with 4.13 (Request Entity Too Large) carrying a Size1 option.
+    TooLarge(usize),
+    /// A block that is not the last one has a payload length other than its block size.
+    UnexpectedBlockLength(usize),
+    /// The requested block starts beyond the end of the body.
+    BlockOutOfRange(u32),
+    /// The payload of a 4.08 (Request Entity Incomplete) response is not a CBOR sequence of
+    /// block numbers.
+    InvalidMissingBlocks,
+    /// The message carrying a block could not be built. Contains the underlying build error.
+    Build(CoapBuildError),
+}
+
+impl core::fmt::Display for CoapBlockError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CoapBlockError::InvalidBlockOption(e) => write!(f, "Invalid block option: {}", e),
+            CoapBlockError::Incomplete(offset) => {
+                write!(f, "Request entity incomplete (expected offset {})", offset)
+            }
+            CoapBlockError::TooLarge(max) => {
+                write!(f, "Request entity too large (maximum {} bytes)", max)
+            }
+            CoapBlockError::UnexpectedBlockLength(len) => {
+                write!(f, "Unexpected block length ({})", len)
+            }
+            CoapBlockError::BlockOutOfRange(num) => write!(f, "Block {} out of range", num),
+            CoapBlockError::InvalidMissingBlocks => write!(f, "Invalid missing blocks payload"),
+            CoapBlockError::Build(e) => write!(f, "Failed to build block: {}", e),
+        }
+    }
+}
+
+impl core::error::Error for CoapBlockError {}
+
+impl CoapBlockError {
+    /// Returns the response code a server should answer a failed Block1 transfer with.
+    ///
+    /// Source: [RFC 7959 2.9](https://datatracker.ietf.org/doc/html/rfc7959#section-2.9)
+    pub fn response_code(&self) -> ResponseCode {
+        match self {
+            CoapBlockError::Incomplete(_) => ResponseCode::RequestEntityIncomplete,
+            CoapBlockError::TooLarge(_) => ResponseCode::RequestEntityTooLarge,
+            CoapBlockError::InvalidBlockOption(_)
+            | CoapBlockError::UnexpectedBlockLength(_)
+            | CoapBlockError::BlockOutOfRange(_) => ResponseCode::BadOption,
+            CoapBlockError::InvalidMissingBlocks => ResponseCode::BadRequest,
+            CoapBlockError::Build(_) => ResponseCode::InternalServerError,
+        }
+    }
+}
+
+impl From<CoapBuildError> for CoapBlockError {
+    fn from(error: CoapBuildError) -> Self {
+        CoapBlockError::Build(error)
+    }
+}
+
+/// Errors that can occur in the message layer of an [`Endpoint`](crate::Endpoint).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CoapEndpointError {
+    /// A datagram could not be parsed. Contains the underlying parse error.
+    Parse(CoapParseError),
+    /// A datagram is larger than the endpoint can store. Contains the datagram length.
+    MessageTooLarge(usize),
+    /// All slots for outstanding Confirmable messages are in use.
+    TooManyOutstanding,
+}
+
+impl core::fmt::Display for CoapEndpointError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CoapEndpointError::Parse(e) => write!(f, "Failed to parse datagram: {}", e),
+            CoapEndpointError::MessageTooLarge(len) => {
+                write!(f, "Message too large ({} bytes)", len)
+            }
+            CoapEndpointError::TooManyOutstanding => {
+                write!(f, "Too many outstanding confirmable messages")
+            }
+        }
+    }
+}
+
+impl core::error::Error for CoapEndpointError {}
+
+impl From<CoapParseError> for CoapEndpointError {
+    fn from(error: CoapParseError) -> Self {
+        CoapEndpointError::Parse(error)
+    }
+}
+
+/// Errors that can occur when matching responses to requests with an
+/// [`ExchangeTracker`](crate::ExchangeTracker).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CoapExchangeError {
+    /// A datagram could not be parsed. Contains the underlying parse error.
+    Parse(CoapParseError),
+    /// A reply could not be built. Contains the underlying build error.
+    Build(CoapBuildError),
+    /// The peer rejected the request with a Reset message. Contains the message ID of the request.
+    Reset(u16),
+    /// All slots for pending exchanges are in use.
+    TooManyExchanges,
+}
+
+impl core::fmt::Display for CoapExchangeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CoapExchangeError::Parse(e) => write!(f, "Failed to parse datagram: {}", e),
+            CoapExchangeError::Build(e) => write!(f, "Failed to build reply: {}", e),
+            CoapExchangeError::Reset(id) => write!(f, "Request {} rejected with Reset", id),
+            CoapExchangeError::TooManyExchanges => write!(f, "Too many pending exchanges"),
+        }
+    }
+}
+
+impl core::error::Error for CoapExchangeError {}
+
+impl From<CoapParseError> for CoapExchangeError {
+    fn from(error: CoapParseError) -> Self {
+        CoapExchangeError::Parse(error)
+    }
+}
+
+impl From<CoapBuildError> for CoapExchangeError {
+    fn from(error: CoapBuildError) -> Self {
+        CoapExchangeError::Build(error)
+    }
+}
+
+/// Errors that can occur when parsing or writing a CoRE Link Format document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CoapLinkFormatError {
+    /// The document is not valid UTF-8.
+    InvalidUtf8,
+    /// A link does not start with `<`. Contains the byte offset where a link was expected.
+    ExpectedLink(usize),
+    /// A link target is missing its closing `>`. Contains the byte offset of the opening `<`.
+    UnterminatedTarget(usize),
+    /// A quoted attribute value is missing its closing `"`. Contains the byte offset of the
+    /// opening `"`.
+    UnterminatedQuote(usize),
+    /// A link is followed by something other than `;` or `,`. Contains the byte offset of the
+    /// unexpected character.
+    UnexpectedCharacter(usize),
+    /// The provided buffer is too small to fit the document being written.
+    BufferTooSmall,
+    /// An attribute was written before any link.
+    AttributeWithoutLink,
+}
+
+impl core::fmt::Display for CoapLinkFormatError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CoapLinkFormatError::InvalidUtf8 => write!(f, "Link format document is not UTF-8"),
+            CoapLinkFormatError::ExpectedLink(offset) => {
+                write!(f, "Expected '<' at offset {}", offset)
+            }
+            CoapLinkFormatError::UnterminatedTarget(offset) => {
+                write!(f, "Unterminated link target starting at offset {}", offset)
+            }
+            CoapLinkFormatError::UnterminatedQuote(offset) => {
+                write!(
+                    f,
+                    "Unterminated quoted string starting at offset {}",
+                    offset
+                )
+            }
+            CoapLinkFormatError::UnexpectedCharacter(offset) => {
+                write!(f, "Unexpected character at offset {}", offset)
+            }
+            CoapLinkFormatError::BufferTooSmall => write!(f, "Buffer too small"),
+            CoapLinkFormatError::AttributeWithoutLink => {
+                write!(f, "Attribute written before any link")
+            }
+        }
+    }
+}
+
+impl core::error::Error for CoapLinkFormatError {}
+
+/// Errors that can occur when answering a resource discovery request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CoapDiscoveryError {
+    /// The link format document could not be written. Contains the underlying error.
+    LinkFormat(CoapLinkFormatError),
+    /// The requested block could not be sent. Contains the underlying error.
+    Block(CoapBlockError),
+    /// The response could not be built. Contains the underlying error.
+    Build(CoapBuildError),
+}
+
+impl CoapDiscoveryError {
+    /// Returns the response code a server should answer the failed request with.
+    pub fn response_code(&self) -> ResponseCode {
+        match self {
+            CoapDiscoveryError::Block(e) => e.response_code(),
+            CoapDiscoveryError::LinkFormat(_) | CoapDiscoveryError::Build(_) => {
+                ResponseCode::InternalServerError
+            }
+        }
+    }
+}
+
+impl core::fmt::Display for CoapDiscoveryError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CoapDiscoveryError::LinkFormat(e) => write!(f, "Failed to write links: {}", e),
+            CoapDiscoveryError::Block(e) => write!(f, "Failed to send block: {}", e),
+            CoapDiscoveryError::Build(e) => write!(f, "Failed to build response: {}", e),
+        }
+    }
+}
+
+impl core::error::Error for CoapDiscoveryError {}
+
+impl From<CoapLinkFormatError> for CoapDiscoveryError {
+    fn from(error: CoapLinkFormatError) -> Self {
+        CoapDiscoveryError::LinkFormat(error)
+    }
+}
+
+impl From<CoapBlockError> for CoapDiscoveryError {
+    fn from(error: CoapBlockError) -> Self {
+        CoapDiscoveryError::Block(error)
+    }
+}
+
+impl From<CoapBuildError> for CoapDiscoveryError {
+    fn from(error: CoapBuildError) -> Self {
+        CoapDiscoveryError::Build(error)
+    }
+}
+
+/// Errors that can occur when decomposing or composing a CoAP URI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CoapUriError {
+    /// The URI is not an absolute URI with an authority, such as `coap://example.org/`.
+    NotAbsolute,
+    /// The URI scheme is not `coap` or `coaps`.
+    UnsupportedScheme,
+    /// The URI contains a fragment, which CoAP URIs must not have.
+    Fragment,
+    /// The authority has a missing or malformed host.
+    InvalidHost,
+    /// The port is not a number between 0 and 65535.
+    InvalidPort,
+    /// A `%` is not followed by two hexadecimal digits.
+    InvalidPercentEncoding,
+    /// An option value that must be a string is not valid UTF-8.
+    InvalidUtf8,
+    /// The provided buffer is too small to fit the URI being composed.
+    BufferTooSmall,
+    /// The URI options could not be added to a message. Contains the underlying build error.
+    Build(CoapBuildError),
+}
+
+impl core::fmt::Display for CoapUriError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CoapUriError::NotAbsolute => write!(f, "URI is not absolute"),
+            CoapUriError::UnsupportedScheme => write!(f, "Unsupported URI scheme"),
+            CoapUriError::Fragment => write!(f, "URI must not contain a fragment"),
+            CoapUriError::InvalidHost => write!(f, "Invalid URI host"),
+            CoapUriError::InvalidPort => write!(f, "Invalid URI port"),
+            CoapUriError::InvalidPercentEncoding => write!(f, "Invalid percent-encoding"),
+            CoapUriError::InvalidUtf8 => write!(f, "URI option is not UTF-8"),
+            CoapUriError::BufferTooSmall => write!(f, "Buffer too small"),
+            CoapUriError::Build(e) => write!(f, "Failed to add URI options: {}", e),
+        }
+    }
+}
+
+impl core::error::Error for CoapUriError {}
+
+impl From<CoapBuildError> for CoapUriError {
+    fn from(error: CoapBuildError) -> Self {
+        CoapUriError::Build(error)
+    }
+}
+
+/// Errors that can occur when strictly validating a CoAP message against the option registry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CoapValidationError {
+    /// The message could not be parsed. Contains the underlying parse error.
+    Parse(CoapParseError),
+    /// An option value is shorter or longer than its definition allows.
+    InvalidLength {
+        /// Number of the offending option
+        option: u16,
+        /// Length of its value
+        length: usize,
+    },
+    /// A non-repeatable option occurs more than once. Contains the option number.
+    NotRepeatable(u16),
+    /// A uint option value has leading zero bytes. Contains the option number.
+    NonMinimalUint(u16),
+    /// A string option value is not valid UTF-8. Contains the option number.
+    InvalidString(u16),
+}
+
+impl CoapValidationError {
+    /// Returns the number of the offending option, if the error is about a single option.
+    pub fn option_number(&self) -> Option<u16> {
+        match *self {
+            CoapValidationError::Parse(_) => None,
+            CoapValidationError::InvalidLength { option, .. }
+            | CoapValidationError::NotRepeatable(option)
+            | CoapValidationError::NonMinimalUint(option)
+            | CoapValidationError::InvalidString(option) => Some(option),
+        }
+    }
+}
+
+impl core::fmt::Display for CoapValidationError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CoapValidationError::Parse(e) => write!(f, "Failed to parse message: {}", e),
+            CoapValidationError::InvalidLength { option, length } => {
+                write!(f, "Invalid length {} for option {}", length, option)
+            }
+            CoapValidationError::NotRepeatable(option) => {
+                write!(f, "Option {} is not repeatable", option)
+            }
+            CoapValidationError::NonMinimalUint(option) => {
+                write!(f, "Option {} has a non-minimal uint encoding", option)
+            }
+            CoapValidationError::InvalidString(option) => {
+                write!(f, "Option {} is not a valid UTF-8 string", option)
+            }
+        }
+    }
+}
+
+impl core::error::Error for CoapValidationError {}
+
+impl From<CoapParseError> for CoapValidationError {
+    fn from(error: CoapParseError) -> Self {
+        CoapValidationError::Parse(error)
+    }
+}
+
+/// Errors that can occur when encoding a message into a [`MessageSink`](crate::MessageSink).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CoapEncodeError<E> {
+    /// The message is invalid. Contains the underlying build error.
+    Build(CoapBuildError),
+    /// The sink failed to write the message. Contains the error returned by the sink.
+    Write(E),
+}
+
+impl<E: core::fmt::Display> core::fmt::Display for CoapEncodeError<E> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CoapEncodeError::Build(e) => write!(f, "Failed to build message: {}", e),
+            CoapEncodeError::Write(e) => write!(f, "Failed to write message: {}", e),
+        }
+    }
+}
+
+impl<E: core::fmt::Debug + core::fmt::Display> core::error::Error for CoapEncodeError<E> {}
+
+impl<E> From<CoapBuildError> for CoapEncodeError<E> {
+    fn from(error: CoapBuildError) -> Self {
+        CoapEncodeError::Build(error)
+    }
+}
+
+impl From<CoapEncodeError<core::convert::Infallible>> for CoapBuildError {
+    fn from(error: CoapEncodeError<core::convert::Infallible>) -> Self {
+        match error {
+            CoapEncodeError::Build(e) => e,
+            CoapEncodeError::Write(never) => match never {},
+        }
+    }
+}
+
+/// Errors that can occur when encoding or decoding problem details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CoapProblemError {
+    /// The provided buffer is too small to fit the encoded problem details.
+    BufferTooSmall,
+    /// The data is not well-formed CBOR, uses indefinite lengths, or has trailing bytes.
+    InvalidCbor,
+    /// A text string is not valid UTF-8.
+    InvalidUtf8,
+    /// The data is not a map, or a standard entry has a value of the wrong type.
+    UnexpectedType,
+}
+
+impl core::fmt::Display for CoapProblemError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CoapProblemError::BufferTooSmall => write!(f, "Buffer too small"),
+            CoapProblemError::InvalidCbor => write!(f, "Invalid CBOR"),
+            CoapProblemError::InvalidUtf8 => write!(f, "Invalid UTF-8 in text string"),
+            CoapProblemError::UnexpectedType => write!(f, "Unexpected problem details type"),
+        }
+    }
+}
+
+impl core::error::Error for CoapProblemError {}
+
+impl From<CborError> for CoapProblemError {
+    fn from(error: CborError) -> Self {
+        match error {
+            CborError::BufferTooSmall => CoapProblemError::BufferTooSmall,
+            CborError::Malformed => CoapProblemError::InvalidCbor,
+            CborError::InvalidUtf8 => CoapProblemError::InvalidUtf8,
+        }
+    }
+}
+
+/// Errors that can occur when a [`Proxy`](crate::Proxy) forwards a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CoapProxyError {
+    /// The request targets a URI the proxy cannot forward to. Contains the underlying URI error,
+    /// which is [`CoapUriError::UnsupportedScheme`] for a scheme other than `coap` or `coaps`.
+    Uri(CoapUriError),
+    /// The proxy does not act as a forward-proxy for requests with Proxy-Uri or Proxy-Scheme.
+    NotSupported,
+    /// The request carries an unrecognized critical option that is unsafe to forward. Contains
+    /// the option number.
+    BadOption(u16),
+    /// The Hop-Limit of the request would reach 0.
+    HopLimitReached,
+    /// All slots for forwarded requests are in use.
+    TooManyExchanges,
+    /// The token chosen for the origin request is empty, or already used by another request
+    /// forwarded to the same origin server.
+    InvalidToken,
+    /// The origin request could not be built. Contains the underlying build error.
+    Build(CoapBuildError),
+}
+
+impl CoapProxyError {
+    /// Returns the response code the proxy should answer the request with.
+    ///
+    /// Source: [RFC 7252 5.7](https://datatracker.ietf.org/doc/html/rfc7252#section-5.7)
+    pub fn response_code(&self) -> ResponseCode {
+        match self {
+            CoapProxyError::Uri(CoapUriError::UnsupportedScheme) | CoapProxyError::NotSupported => {
+                ResponseCode::ProxyingNotSupported
+            }
+            CoapProxyError::Uri(CoapUriError::BufferTooSmall | CoapUriError::Build(_))
+            | CoapProxyError::InvalidToken
+            | CoapProxyError::Build(_) => ResponseCode::InternalServerError,
+            CoapProxyError::Uri(_) => ResponseCode::BadRequest,
+            CoapProxyError::BadOption(_) => ResponseCode::BadOption,
+            CoapProxyError::HopLimitReached => ResponseCode::HopLimitReached,
+            CoapProxyError::TooManyExchanges => ResponseCode::ServiceUnavailable,
+        }
+    }
+}
+
+impl core::fmt::Display for CoapProxyError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CoapProxyError::Uri(e) => write!(f, "Invalid proxy target: {}", e),
+            CoapProxyError::NotSupported => write!(f, "Forward-proxying not supported"),
+            CoapProxyError::BadOption(number) => {
+                write!(f, "Unrecognized unsafe option {}", number)
+            }
+            CoapProxyError::HopLimitReached => write!(f, "Hop limit reached"),
+            CoapProxyError::TooManyExchanges => write!(f, "Too many forwarded requests"),
+            CoapProxyError::InvalidToken => write!(f, "Invalid token for origin request"),
+            CoapProxyError::Build(e) => write!(f, "Failed to build origin request: {}", e),
+        }
+    }
+}
+
+impl core::error::Error for CoapProxyError {}
+
+impl From<CoapUriError> for CoapProxyError {
+    fn from(error: CoapUriError) -> Self {
+        CoapProxyError::Uri(error)
+    }
+}
+
+impl From<CoapBuildError> for CoapProxyError {
+    fn from(error: CoapBuildError) -> Self {
+        CoapProxyError::Build(error)
+    }
+}
+
+/// Errors that can occur when protecting or unprotecting a message with OSCORE.
+#[cfg(feature = "oscore")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CoapOscoreError {
+    /// A Sender ID, Recipient ID or ID Context is too long.
+    InvalidId,
+    /// The message has no OSCORE option.
+    NotProtected,
+    /// The OSCORE option is malformed, or lacks a field the message requires.
+    InvalidOption,
+    /// The request is not protected with this security context.
+    UnknownKid,
+    /// The request was already received.
+    Replay,
+    /// The protected payload could not be decrypted or verified.
+    DecryptionFailed,
+    /// The message cannot be protected, or the decrypted message is malformed.
+    InvalidMessage,
+    /// The Sender Sequence Number cannot be encoded in a Partial IV anymore. The security
+    /// context must be renewed.
+    SequenceNumberExhausted,
+    /// The protected or unprotected message could not be built. Contains the underlying error.
+    Build(CoapBuildError),
+}
+
+#[cfg(feature = "oscore")]
+impl CoapOscoreError {
+    /// Returns the response code a server should answer the failed request with.
+    ///
+    /// Source: [RFC 8613 8.2](https://datatracker.ietf.org/doc/html/rfc8613#section-8.2)
+    pub fn response_code(&self) -> ResponseCode {
+        match self {
+            CoapOscoreError::InvalidOption => ResponseCode::BadOption,
+            CoapOscoreError::UnknownKid | CoapOscoreError::Replay => ResponseCode::Unauthorized,
+            CoapOscoreError::DecryptionFailed | CoapOscoreError::InvalidMessage => {
+                ResponseCode::BadRequest
+            }
+            CoapOscoreError::InvalidId
+            | CoapOscoreError::NotProtected
+            | CoapOscoreError::SequenceNumberExhausted
+            | CoapOscoreError::Build(_) => ResponseCode::InternalServerError,
+        }
+    }
+}
+
+#[cfg(feature = "oscore")]
+impl core::fmt::Display for CoapOscoreError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CoapOscoreError::InvalidId => write!(f, "Invalid OSCORE identifier"),
+            CoapOscoreError::NotProtected => write!(f, "Message is not protected"),
+            CoapOscoreError::InvalidOption => write!(f, "Invalid OSCORE option"),
+            CoapOscoreError::UnknownKid => write!(f, "Unknown security context"),
+            CoapOscoreError::Replay => write!(f, "Replayed request"),
+            CoapOscoreError::DecryptionFailed => write!(f, "Decryption failed"),
+            CoapOscoreError::InvalidMessage => write!(f, "Invalid protected message"),
+            CoapOscoreError::SequenceNumberExhausted => {
+                write!(f, "Sender Sequence Number exhausted")
+            }
+            CoapOscoreError::Build(e) => write!(f, "Failed to build message: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "oscore")]
+impl core::error::Error for CoapOscoreError {}
+
+#[cfg(feature = "oscore")]
+impl From<CoapBuildError> for CoapOscoreError {
+    fn from(error: CoapBuildError) -> Self {
+        CoapOscoreError::Build(error)
+    }
+}
+
+#[cfg(feature = "oscore")]
+impl From<CborError> for CoapOscoreError {
+    fn from(_: CborError) -> Self {
+        // CBOR is only encoded into buffers sized for the largest identifiers.
+        CoapOscoreError::Build(CoapBuildError::BufferTooSmall)
+    }
+}
@@ -0,0 +1,390 @@
+use crate::error::CoapExchangeError;
+use crate::observe::OBSERVE_REGISTER;
+use crate::{Message, MessageBuilder, MessageType};
+
+/// How a response was delivered.
+///
+/// Source: [RFC 7252 5.2](https://datatracker.ietf.org/doc/html/rfc7252#section-5.2)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ResponseKind {
+    /// The response was carried in the Acknowledgement of a Confirmable request.
+    Piggybacked,
+    /// The response was sent in its own Confirmable message after the request was acknowledged.
+    Separate,
+    /// The response was sent in a Non-confirmable message.
+    NonConfirmable,
+}
+
+/// Outcome of passing a received datagram to [`ExchangeTracker::receive`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ExchangeEvent<'a, 'buf> {
+    /// A response to a pending request.
+    Response {
+        /// The response message
+        response: Message<'a>,
+        /// How the response was delivered
+        kind: ResponseKind,
+        /// Empty Acknowledgement that must be sent for a Confirmable response
+        ack: Option<&'buf [u8]>,
+    },
+    /// A Confirmable request was acknowledged with an empty Acknowledgement, and its response
+    /// will follow as a separate response.
+    Acknowledged,
+    /// The message does not belong to any pending exchange. Contains the Reset that must be sent
+    /// for an unexpected Confirmable message.
+    Unmatched(Option<&'buf [u8]>),
+}
+
+/// A request awaiting its response.
+struct Exchange<E> {
+    peer: E,
+    token: [u8; 8],
+    token_len: u8,
+    message_id: u16,
+    awaiting_ack: bool,
+    observe: bool,
+}
+
+impl<E> Exchange<E> {
+    fn token(&self) -> &[u8] {
+        &self.token[..self.token_len as usize]
+    }
+}
+
+/// Client-side tracker matching responses to requests by token.
+///
+/// Piggybacked responses are matched by message ID and token, while separate and Non-confirmable
+/// responses are matched by token alone. Exchanges registering an observation are kept open so
+/// every notification is matched.
+///
+/// Source: [RFC 7252 5.3](https://datatracker.ietf.org/doc/html/rfc7252#section-5.3)
+pub struct ExchangeTracker<E, const N: usize> {
+    exchanges: [Option<Exchange<E>>; N],
+}
+
+impl<E, const N: usize> Default for ExchangeTracker<E, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E, const N: usize> ExchangeTracker<E, N> {
+    /// Create an empty tracker.
+    pub const fn new() -> Self {
+        Self {
+            exchanges: [const { None }; N],
+        }
+    }
+
+    /// Returns the number of pending exchanges.
+    pub fn len(&self) -> usize {
+        self.exchanges.iter().flatten().count()
+    }
+
+    /// Check if there are no pending exchanges.
+    pub fn is_empty(&self) -> bool {
+        self.exchanges.iter().all(Option::is_none)
+    }
+}
+
+impl<E: PartialEq, const N: usize> ExchangeTracker<E, N> {
+    /// Start tracking a request sent to `peer`.
+    pub fn track(&mut self, peer: E, request: &Message<'_>) -> Result<(), CoapExchangeError> {
+        let slot = self
+            .exchanges
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(CoapExchangeError::TooManyExchanges)?;
+
+        let mut token = [0; 8];
+        token[..request.token.len()].copy_from_slice(request.token);
+
+        *slot = Some(Exchange {
+            peer,
+            token,
+            token_len: request.token.len() as u8,
+            message_id: request.message_id,
+            awaiting_ack: request.message_type == MessageType::Confirmable,
+            observe: request.observe() == Some(OBSERVE_REGISTER),
+        });
+
+        Ok(())
+    }
+
+    /// Stop tracking the exchange with the given token, e.g. when cancelling an observation.
+    /// Returns `true` if an exchange was removed.
+    pub fn remove(&mut self, peer: &E, token: &[u8]) -> bool {
+        self.remove_where(|exchange| &exchange.peer == peer && exchange.token() == token)
+    }
+
+    /// Stop tracking the exchange whose request had the given message ID, e.g. after the message
+    /// layer gave up retransmitting it. Returns `true` if an exchange was removed.
+    pub fn remove_by_message_id(&mut self, peer: &E, message_id: u16) -> bool {
+        self.remove_where(|exchange| &exchange.peer == peer && exchange.message_id == message_id)
+    }
+
+    /// Process a datagram received from `peer`. Any Acknowledgement or Reset that must be sent in
+    /// return is built into `reply_buffer`.
+    pub fn receive<'a, 'buf>(
+        &mut self,
+        peer: &E,
+        datagram: &'a [u8],
+        reply_buffer: &'buf mut [u8],
+    ) -> Result<ExchangeEvent<'a, 'buf>, CoapExchangeError> {
+        let message = Message::parse(datagram)?;
+
+        match message.message_type {
+            MessageType::Reset => {
+                if self.remove_by_message_id(peer, message.message_id) {
+                    return Err(CoapExchangeError::Reset(message.message_id));
+                }
+
+                Ok(ExchangeEvent::Unmatched(None))
+            }
+            MessageType::Acknowledgement => {
+                let index = self.position(|exchange| {
+                    &exchange.peer == peer
+                        && exchange.awaiting_ack
+                        && exchange.message_id == message.message_id
+                        && (message.is_empty() || exchange.token() == message.token)
+                });
+
+                let Some(index) = index else {
+                    return Ok(ExchangeEvent::Unmatched(None));
+                };
+
+                if message.is_empty() {
+                    // The response will arrive separately, so keep the exchange open.
+                    if let Some(exchange) = self.exchanges[index].as_mut() {
+                        exchange.awaiting_ack = false;
+                    }
+                    return Ok(ExchangeEvent::Acknowledged);
+                }
+
+                self.complete(index, &message);
+
+                Ok(ExchangeEvent::Response {
+                    response: message,
+                    kind: ResponseKind::Piggybacked,
+                    ack: None,
+                })
+            }
+            MessageType::Confirmable | MessageType::NonConfirmable => {
+                let confirmable = message.message_type == MessageType::Confirmable;
+                let index = self.position(|exchange| {
+                    &exchange.peer == peer && exchange.token() == message.token
+                });
+
+                let index = index.filter(|_| message.is_response());
+                let Some(index) = index else {
+                    if confirmable && !message.is_request() {
+                        let reset = MessageBuilder::new(reply_buffer)?
+                            .empty_reset(&message)?
+                            .build();
+                        return Ok(ExchangeEvent::Unmatched(Some(reset)));
+                    }
+
+                    return Ok(ExchangeEvent::Unmatched(None));
+                };
+
+                self.complete(index, &message);
+
+                if !confirmable {
+                    return Ok(ExchangeEvent::Response {
+                        response: message,
+                        kind: ResponseKind::NonConfirmable,
+                        ack: None,
+                    });
+                }
+
+                let ack = MessageBuilder::new(reply_buffer)?
+                    .empty_ack(&message)?
+                    .build();
+
+                Ok(ExchangeEvent::Response {
+                    response: message,
+                    kind: ResponseKind::Separate,
+                    ack: Some(ack),
+                })
+            }
+        }
+    }
+
+    /// Close the exchange at `index` after a response, unless it is an ongoing observation.
+    fn complete(&mut self, index: usize, response: &Message<'_>) {
+        let keep = self.exchanges[index]
+            .as_ref()
+            .is_some_and(|exchange| exchange.observe && response.observe().is_some());
+
+        if keep {
+            if let Some(exchange) = self.exchanges[index].as_mut() {
+                exchange.awaiting_ack = false;
+            }
+        } else {
+            self.exchanges[index] = None;
+        }
+    }
+
+    fn position(&self, predicate: impl Fn(&Exchange<E>) -> bool) -> Option<usize> {
+        self.exchanges
+            .iter()
+            .position(|slot| slot.as_ref().is_some_and(&predicate))
+    }
+
+    fn remove_where(&mut self, predicate: impl Fn(&Exchange<E>) -> bool) -> bool {
+        let mut removed = false;
+
+        for slot in self.exchanges.iter_mut() {
+            if slot.as_ref().is_some_and(&predicate) {
+                *slot = None;
+                removed = true;
+            }
+        }
+
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RequestCode, ResponseCode};
+
+    fn get<'a>(buffer: &'a mut [u8], msg_type: MessageType, message_id: u16) -> Message<'a> {
+        let packet = MessageBuilder::new(buffer)
+            .unwrap()
+            .request(msg_type, RequestCode::Get)
+            .message_id(message_id)
+            .token(&[0xCA, 0xFE])
+            .unwrap()
+            .no_payload()
+            .build();
+
+        Message::parse(packet).unwrap()
+    }
+
+    fn content(buffer: &mut [u8], msg_type: MessageType, message_id: u16) -> &[u8] {
+        MessageBuilder::new(buffer)
+            .unwrap()
+            .response(msg_type, ResponseCode::Content)
+            .message_id(message_id)
+            .token(&[0xCA, 0xFE])
+            .unwrap()
+            .payload(b"21")
+            .unwrap()
+            .build()
+    }
+
+    #[test]
+    fn piggybacked_response() {
+        let mut tracker = ExchangeTracker::<u8, 2>::new();
+        let mut buffer = [0; 32];
+        tracker
+            .track(1, &get(&mut buffer, MessageType::Confirmable, 10))
+            .unwrap();
+
+        let mut rx_buf = [0; 32];
+        let mut reply_buf = [0; 32];
+        let datagram = content(&mut rx_buf, MessageType::Acknowledgement, 10);
+
+        // Responses from other peers are not matched.
+        assert_eq!(
+            tracker.receive(&2, datagram, &mut reply_buf),
+            Ok(ExchangeEvent::Unmatched(None))
+        );
+
+        let event = tracker.receive(&1, datagram, &mut reply_buf).unwrap();
+        assert!(matches!(
+            event,
+            ExchangeEvent::Response {
+                kind: ResponseKind::Piggybacked,
+                ack: None,
+                ..
+            }
+        ));
+        assert!(tracker.is_empty());
+    }
+
+    #[test]
+    fn separate_response_is_acknowledged() {
+        let mut tracker = ExchangeTracker::<u8, 2>::new();
+        let mut buffer = [0; 32];
+        tracker
+            .track(1, &get(&mut buffer, MessageType::Confirmable, 10))
+            .unwrap();
+
+        let mut reply_buf = [0; 32];
+        let empty_ack = [0x60, 0x00, 0x00, 0x0A];
+        assert_eq!(
+            tracker.receive(&1, &empty_ack, &mut reply_buf),
+            Ok(ExchangeEvent::Acknowledged)
+        );
+
+        let mut rx_buf = [0; 32];
+        let datagram = content(&mut rx_buf, MessageType::Confirmable, 0x5555);
+        let event = tracker.receive(&1, datagram, &mut reply_buf).unwrap();
+
+        let ExchangeEvent::Response {
+            response,
+            kind: ResponseKind::Separate,
+            ack: Some(ack),
+        } = event
+        else {
+            panic!("expected a separate response");
+        };
+
+        assert_eq!(response.payload, Some(&b"21"[..]));
+        assert_eq!(ack, &[0x60, 0x00, 0x55, 0x55]);
+        assert!(tracker.is_empty());
+    }
+
+    #[test]
+    fn non_confirmable_response() {
+        let mut tracker = ExchangeTracker::<u8, 2>::new();
+        let mut buffer = [0; 32];
+        tracker
+            .track(1, &get(&mut buffer, MessageType::NonConfirmable, 10))
+            .unwrap();
+
+        let mut rx_buf = [0; 32];
+        let mut reply_buf = [0; 32];
+        let datagram = content(&mut rx_buf, MessageType::NonConfirmable, 0x0100);
+        assert!(matches!(
+            tracker.receive(&1, datagram, &mut reply_buf),
+            Ok(ExchangeEvent::Response {
+                kind: ResponseKind::NonConfirmable,
+                ack: None,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn reset_and_unknown_token() {
+        let mut tracker = ExchangeTracker::<u8, 2>::new();
+        let mut buffer = [0; 32];
+        tracker
+            .track(1, &get(&mut buffer, MessageType::Confirmable, 10))
+            .unwrap();
+
+        let mut reply_buf = [0; 32];
+        let reset = [0x70, 0x00, 0x00, 0x0A];
+        assert_eq!(
+            tracker.receive(&1, &reset, &mut reply_buf),
+            Err(CoapExchangeError::Reset(10))
+        );
+        assert!(tracker.is_empty());
+
+        // A Confirmable response with an unknown token is rejected with a Reset.
+        let mut rx_buf = [0; 32];
+        let datagram = content(&mut rx_buf, MessageType::Confirmable, 0x0200);
+        assert_eq!(
+            tracker.receive(&1, datagram, &mut reply_buf),
+            Ok(ExchangeEvent::Unmatched(Some(
+                &[0x70, 0x00, 0x02, 0x00][..]
+            )))
+        );
+    }
+}
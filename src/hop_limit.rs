@@ -0,0 +1,183 @@
+use crate::error::CoapBuildError;
+use crate::{
+    Complete, Message, MessageBuilder, NeedsHeader, NeedsPayload, OptionNumber, ResponseCode,
+};
+
+type BuilderResult<'buf, T> = core::result::Result<MessageBuilder<'buf, T>, CoapBuildError>;
+
+/// Hop-Limit a proxy adds to a request that arrives without one.
+///
+/// Source: [RFC 8768 3](https://datatracker.ietf.org/doc/html/rfc8768#section-3)
+pub const DEFAULT_HOP_LIMIT: u8 = 16;
+
+impl<'a> Message<'a> {
+    /// Returns the value of the Hop-Limit option, or `None` if the message has no valid
+    /// Hop-Limit option.
+    ///
+    /// Source: [RFC 8768 3](https://datatracker.ietf.org/doc/html/rfc8768#section-3)
+    pub fn hop_limit(&self) -> Option<u8> {
+        self.options
+            .into_iter()
+            .find(|opt| opt.number == OptionNumber::HopLimit)
+            .and_then(|opt| opt.as_uint())
+            .and_then(|limit| u8::try_from(limit).ok())
+    }
+
+    /// Returns the Hop-Limit a proxy should forward this request with: the received value
+    /// decremented by one, or [`DEFAULT_HOP_LIMIT`] if the request has no Hop-Limit option.
+    ///
+    /// Returns `None` if the decremented value would be 0. The proxy must then not forward the
+    /// request, and answers it with [`MessageBuilder::hop_limit_reached`] instead.
+    ///
+    /// Source: [RFC 8768 3](https://datatracker.ietf.org/doc/html/rfc8768#section-3)
+    pub fn forwarded_hop_limit(&self) -> Option<u8> {
+        let has_option = self
+            .options
+            .into_iter()
+            .any(|opt| opt.number == OptionNumber::HopLimit);
+        if !has_option {
+            return Some(DEFAULT_HOP_LIMIT);
+        }
+
+        self.hop_limit()
+            .and_then(|limit| limit.checked_sub(1))
+            .filter(|&limit| limit > 0)
+    }
+}
+
+impl<'buf> MessageBuilder<'buf, NeedsHeader> {
+    /// Construct the 5.08 (Hop Limit Reached) response to a request that a proxy cannot forward,
+    /// with `proxy` identifying the proxy in the diagnostic payload.
+    ///
+    /// Source: [RFC 8768 3](https://datatracker.ietf.org/doc/html/rfc8768#section-3)
+    pub fn hop_limit_reached(
+        self,
+        request: &Message<'_>,
+        message_id: u16,
+        proxy: &str,
+    ) -> BuilderResult<'buf, Complete> {
+        let builder = self.reply(request, ResponseCode::HopLimitReached, message_id)?;
+
+        if proxy.is_empty() {
+            Ok(builder.no_payload())
+        } else {
+            builder.payload(proxy.as_bytes())
+        }
+    }
+}
+
+impl<'buf> MessageBuilder<'buf, NeedsPayload> {
+    /// Add a Hop-Limit option, which must be between 1 and 255. A limit of 0 is rejected with
+    /// [`CoapBuildError::InvalidOptionValue`].
+    ///
+    /// Source: [RFC 8768 3](https://datatracker.ietf.org/doc/html/rfc8768#section-3)
+    pub fn hop_limit(self, limit: u8) -> BuilderResult<'buf, NeedsPayload> {
+        if limit == 0 {
+            return Err(CoapBuildError::InvalidOptionValue(
+                OptionNumber::HopLimit.into(),
+            ));
+        }
+
+        self.option(OptionNumber::HopLimit, &[limit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MessageType, RequestCode};
+
+    #[test]
+    fn decrement_hop_limit() {
+        let mut buffer = [0; 32];
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(0x1234)
+            .token(&[7])
+            .unwrap()
+            .option_string(OptionNumber::UriPath, "temp")
+            .unwrap()
+            .no_payload()
+            .build();
+        let message = Message::parse(packet).unwrap();
+        assert_eq!(message.hop_limit(), None);
+        assert_eq!(message.forwarded_hop_limit(), Some(DEFAULT_HOP_LIMIT));
+
+        let mut buffer = [0; 32];
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(0x1234)
+            .token(&[7])
+            .unwrap()
+            .option_string(OptionNumber::UriPath, "temp")
+            .unwrap()
+            .hop_limit(5)
+            .unwrap()
+            .no_payload()
+            .build();
+        let message = Message::parse(packet).unwrap();
+        assert_eq!(message.hop_limit(), Some(5));
+        assert_eq!(message.forwarded_hop_limit(), Some(4));
+        assert!(message.validate().is_ok());
+
+        let mut buffer = [0; 32];
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(0x1234)
+            .token(&[7])
+            .unwrap()
+            .option_string(OptionNumber::UriPath, "temp")
+            .unwrap()
+            .hop_limit(1)
+            .unwrap()
+            .no_payload()
+            .build();
+        let message = Message::parse(packet).unwrap();
+        assert_eq!(message.forwarded_hop_limit(), None);
+
+        let mut buffer = [0; 32];
+        let builder = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(0x1234)
+            .no_token()
+            .unwrap();
+        assert_eq!(
+            builder.hop_limit(0).err(),
+            Some(CoapBuildError::InvalidOptionValue(16))
+        );
+    }
+
+    #[test]
+    fn hop_limit_reached_response() -> Result<(), CoapBuildError> {
+        let mut buffer = [0; 32];
+        let packet = MessageBuilder::new(&mut buffer)?
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(0x1234)
+            .token(&[7])?
+            .option_string(OptionNumber::UriPath, "temp")?
+            .hop_limit(1)?
+            .no_payload()
+            .build();
+        let request = Message::parse(packet).unwrap();
+
+        let mut tx_buf = [0; 32];
+        let packet = MessageBuilder::new(&mut tx_buf)?
+            .hop_limit_reached(&request, 0, "proxy-1")?
+            .build();
+
+        let response = Message::parse(packet).unwrap();
+        assert_eq!(response.message_type, MessageType::Acknowledgement);
+        assert_eq!(response.message_id, 0x1234);
+        assert_eq!(response.code, u8::from(ResponseCode::HopLimitReached));
+        assert_eq!(response.code_class(), 5);
+        assert_eq!(response.code_detail(), 8);
+        assert_eq!(response.token, &[7]);
+        assert_eq!(response.payload, Some(&b"proxy-1"[..]));
+
+        Ok(())
+    }
+}
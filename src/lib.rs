@@ -4,27 +4,99 @@
 //!
 //! ## Supported RFCs
 //!
+//! - [RFC 6690](https://datatracker.ietf.org/doc/html/rfc6690): Constrained RESTful Environments (CoRE) Link Format
 //! - [RFC 7252](https://datatracker.ietf.org/doc/html/rfc7252): The Constrained Application Protocol (CoAP)
+//! - [RFC 7641](https://datatracker.ietf.org/doc/html/rfc7641): Observing Resources in CoAP
 //! - [RFC 7959](https://datatracker.ietf.org/doc/html/rfc7959): Block-Wise Transfers in CoAP
 //! - [RFC 7967](https://datatracker.ietf.org/doc/html/rfc7967): Constrained Application Protocol (CoAP) Option for No Server Response
 //! - [RFC 8132](https://datatracker.ietf.org/doc/html/rfc8132): PATCH and FETCH Methods for CoAP
+//! - [RFC 8323](https://datatracker.ietf.org/doc/html/rfc8323): CoAP over TCP, TLS, and WebSockets
+//! - [RFC 8613](https://datatracker.ietf.org/doc/html/rfc8613): Object Security for Constrained RESTful Environments (OSCORE)
+//! - [RFC 8768](https://datatracker.ietf.org/doc/html/rfc8768): Constrained Application Protocol (CoAP) Hop-Limit Option
 //! - [RFC 9175](https://datatracker.ietf.org/doc/html/rfc9175): CoAP: Echo, Request-Tag, and Token Processing
+//! - [RFC 9177](https://datatracker.ietf.org/doc/html/rfc9177): CoAP Block-Wise Transfer Options Supporting Robust Transmission
+//! - [RFC 9290](https://datatracker.ietf.org/doc/html/rfc9290): Concise Problem Details for Constrained Application Protocol (CoAP) APIs
 
 #![no_std]
 #![deny(clippy::cargo, missing_docs)]
 #![warn(clippy::all)]
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
 use num_enum::{FromPrimitive, IntoPrimitive};
 
+mod block;
 mod builder;
+mod cache;
+mod cbor;
+mod conditional;
+mod discovery;
+mod editor;
+mod encoder;
+mod endpoint;
 pub(crate) mod error;
+mod exchange;
+mod hop_limit;
+mod link_format;
+mod observe;
+#[cfg(feature = "oscore")]
+mod oscore;
+#[cfg(feature = "alloc")]
+mod owned;
 mod parser;
+mod problem;
+mod proxy;
+mod qblock;
+mod reliable;
+mod router;
+mod signal;
+mod unordered;
+mod uri;
+mod validate;
 
+pub use block::{BlockSize, BlockStatus, BlockValue, BlockwiseReceiver, BlockwiseSender};
 pub use builder::MessageBuilder;
 #[doc(hidden)]
-pub use builder::{Complete, NeedsBuffer, NeedsHeader, NeedsMessageId, NeedsPayload, NeedsToken};
-pub use error::{CoapBuildError, CoapParseError};
+pub use builder::{
+    Complete, NeedsBuffer, NeedsCode, NeedsHeader, NeedsMessageId, NeedsPayload,
+    NeedsSignalPayload, NeedsSignalToken, NeedsToken,
+};
+pub use cache::{CacheLookup, CachedResponse, DEFAULT_MAX_AGE, ResponseCache};
+pub use conditional::Precondition;
+pub use discovery::{Discovery, Resource, ResourceAttribute};
+pub use editor::MessageMut;
+#[cfg(feature = "embedded-io")]
+pub use encoder::IoSink;
+pub use encoder::{MessageEncoder, MessageSink, SizeCounter};
+pub use endpoint::{Clock, Endpoint, EndpointEvent, Received, TransmissionParameters};
+#[cfg(feature = "oscore")]
+pub use error::CoapOscoreError;
+pub use error::{
+    CoapBlockError, CoapBuildError, CoapDiscoveryError, CoapEncodeError, CoapEndpointError,
+    CoapExchangeError, CoapLinkFormatError, CoapObserveError, CoapParseError, CoapProblemError,
+    CoapProxyError, CoapUriError, CoapValidationError,
+};
+pub use exchange::{ExchangeEvent, ExchangeTracker, ResponseKind};
+pub use hop_limit::DEFAULT_HOP_LIMIT;
+pub use link_format::{
+    AttributeIterator, Link, LinkAttribute, LinkFormat, LinkIterator, LinkWriter,
+};
+pub use observe::{ObserveRequest, Observer, ObserverRegistry};
+#[cfg(feature = "oscore")]
+pub use oscore::{RequestBinding, SecurityContext};
+#[cfg(feature = "alloc")]
+pub use owned::{OwnedMessage, OwnedOption};
 pub use parser::{CoapOption, CoapOptions, Message, OptionIterator};
+pub use problem::ProblemDetails;
+pub use proxy::{OriginRequest, Proxy};
+pub use qblock::{DEFAULT_MAX_PAYLOADS, MissingBlocks, QBlockReceiver, QBlockSender};
+pub use reliable::{FrameStatus, ReliableMessage};
+pub use router::{Handler, Route, RouteRequest, Router};
+pub use signal::{DEFAULT_MAX_MESSAGE_SIZE, SignalCode};
+pub use unordered::UnorderedBuilder;
+pub use uri::{CoapUri, UriScheme};
+pub use validate::{OptionDefinition, OptionFormat};
 
 #[macro_export]
 /// Converts a CoAP code into a u8 value.
@@ -390,6 +462,12 @@ pub enum ResponseCode {
     ///
     /// Source: [RFC 7252 5.9.3.6](https://datatracker.ietf.org/doc/html/rfc7252#section-5.9.3.6)
     ProxyingNotSupported = coap_code!(5, 05),
+    /// A proxy could not forward the request because the value of the Hop-Limit Option would
+    /// have reached 0, which usually indicates a forwarding loop. The diagnostic payload should
+    /// identify the proxy.
+    ///
+    /// Source: [RFC 8768 3](https://datatracker.ietf.org/doc/html/rfc8768#section-3)
+    HopLimitReached = coap_code!(5, 08),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, IntoPrimitive, FromPrimitive)]
@@ -466,6 +544,19 @@ pub enum OptionNumber {
     ///
     /// Source: [RFC 7252 5.10.8.2](https://datatracker.ietf.org/doc/html/rfc7252#section-5.10.8.2)
     IfNoneMatch = 5,
+    /// The Observe Option, when present in a GET request, extends the GET method so it does not
+    /// only retrieve a current representation of the target resource, but also requests the server
+    /// to add or remove an entry in the list of observers of the resource. A value of 0 registers
+    /// the client as an observer, while a value of 1 deregisters it.
+    ///
+    /// In a response, the Observe Option identifies the message as a notification and carries a
+    /// 24-bit sequence number used by the client to reorder notifications that arrive out of order.
+    ///
+    /// The option value is a uint with 0-3 bytes. This is an elective option that is safe to
+    /// forward, part of the cache key, and not repeatable.
+    ///
+    /// Source: [RFC 7641 2](https://datatracker.ietf.org/doc/html/rfc7641#section-2)
+    Observe = 6,
     /// The Uri-Port Option specifies the transport-layer port number of the resource.
     ///
     /// The default value of the Uri-Port Option is the destination UDP port. The default value for
@@ -489,6 +580,15 @@ pub enum OptionNumber {
     ///
     /// Source: [RFC 7252 5.10.7](https://datatracker.ietf.org/doc/html/rfc7252#section-5.10.7)
     LocationPath = 8,
+    /// The OSCORE Option indicates that the message is protected with Object Security for
+    /// Constrained RESTful Environments. Its value carries the compressed COSE header parameters
+    /// needed to decrypt the message: the Partial IV, the kid context and the kid.
+    ///
+    /// The option value is opaque with 0-255 bytes. This is a critical option that is safe to
+    /// forward, part of the cache key, and not repeatable.
+    ///
+    /// Source: [RFC 8613 2](https://datatracker.ietf.org/doc/html/rfc8613#section-2)
+    Oscore = 9,
     /// The Uri-Host, Uri-Port, Uri-Path, and Uri-Query Options are used to specify the target
     /// resource of a request to a CoAP origin server. Each Uri-Path Option specifies one segment of
     /// the absolute path to the resource.
@@ -527,6 +627,15 @@ pub enum OptionNumber {
     ///
     /// Source: [RFC 7252 5.10.1](https://datatracker.ietf.org/doc/html/rfc7252#section-5.10.1)
     UriQuery = 15,
+    /// The Hop-Limit Option limits the number of proxies a request may traverse. Each proxy
+    /// decrements its value when forwarding the request, and answers with 5.08 (Hop Limit
+    /// Reached) instead when the value would reach 0.
+    ///
+    /// The option value is a uint of 1 byte between 1 and 255, with a default of 16. This is an
+    /// elective option that is safe to forward and not repeatable.
+    ///
+    /// Source: [RFC 8768 3](https://datatracker.ietf.org/doc/html/rfc8768#section-3)
+    HopLimit = 16,
     /// The CoAP Accept option can be used to indicate which Content-Format is acceptable to the
     /// client. The representation format is given as a numeric Content-Format identifier that is
     /// defined in the "CoAP Content-Formats" registry (Section 12.3). If no Accept option is given,
@@ -538,6 +647,14 @@ pub enum OptionNumber {
     ///
     /// Source: [RFC 7252 5.10.4](https://datatracker.ietf.org/doc/html/rfc7252#section-5.10.4)
     Accept = 17,
+    /// The Q-Block1 Option provides robust block-wise transfer of request payloads, sending
+    /// several blocks without waiting for each to be acknowledged. The option value is encoded
+    /// like that of the Block1 Option.
+    ///
+    /// This is a Critical and Unsafe option.
+    ///
+    /// Source: [RFC 9177 4](https://datatracker.ietf.org/doc/html/rfc9177#section-4)
+    QBlock1 = 19,
     /// The Location-Path and Location-Query Options together indicate a relative URI that consists
     /// either of an absolute path, a query string, or both. A combination of these options is
     /// included in a 2.01 (Created) response to indicate the location of the resource created as the
@@ -579,6 +696,14 @@ pub enum OptionNumber {
     ///
     /// Source: [RFC 7959 4](https://datatracker.ietf.org/doc/html/rfc7959#section-4)
     Size2 = 28,
+    /// The Q-Block2 Option provides robust block-wise transfer of response payloads, sending
+    /// several blocks without waiting for each to be requested. The option value is encoded like
+    /// that of the Block2 Option. A request may repeat it to ask for several missing blocks.
+    ///
+    /// This is a Critical and Unsafe option.
+    ///
+    /// Source: [RFC 9177 4](https://datatracker.ietf.org/doc/html/rfc9177#section-4)
+    QBlock2 = 31,
     /// The Proxy-Uri Option is used to make a request to a forward-proxy. The forward-proxy is
     /// requested to forward the request or service it from a valid cache and return the response.
     ///
@@ -892,6 +1017,18 @@ pub enum ContentFormat {
     ///
     /// Source: [RFC 9254](https://datatracker.ietf.org/doc/html/rfc9254)
     ApplicationYangDataCborSid = 140,
+    /// application/concise-problem-details+cbor
+    ///
+    /// Problem details for an error response in CBOR.
+    ///
+    /// Source: [RFC 9290](https://datatracker.ietf.org/doc/html/rfc9290)
+    ApplicationConciseProblemDetailsCbor = 257,
+    /// application/missing-blocks+cbor-seq
+    ///
+    /// The numbers of the blocks missing from a Q-Block1 transfer, as a CBOR sequence.
+    ///
+    /// Source: [RFC 9177 5](https://datatracker.ietf.org/doc/html/rfc9177#section-5)
+    ApplicationMissingBlocksCborSeq = 272,
 
     /// An unrecognized content format. CoAP allows for content formats beyond those
     /// defined in the base specification.
@@ -0,0 +1,582 @@
+use crate::error::CoapLinkFormatError;
+
+type LinkFormatResult<T> = core::result::Result<T, CoapLinkFormatError>;
+
+/// Find the first `delimiter` that is not part of a quoted string. Returns the length of `s` if
+/// there is none, or the offset of the opening quote of an unterminated quoted string.
+fn find_unquoted(s: &str, delimiter: u8) -> Result<usize, usize> {
+    let mut quote_start = None;
+    let mut escaped = false;
+
+    for (i, &byte) in s.as_bytes().iter().enumerate() {
+        match quote_start {
+            Some(_) if escaped => escaped = false,
+            Some(_) if byte == b'\\' => escaped = true,
+            Some(_) if byte == b'"' => quote_start = None,
+            Some(_) => {}
+            None if byte == b'"' => quote_start = Some(i),
+            None if byte == delimiter => return Ok(i),
+            None => {}
+        }
+    }
+
+    match quote_start {
+        Some(start) => Err(start),
+        None => Ok(s.len()),
+    }
+}
+
+/// Returns the length of `value` once quotes and backslashes are escaped.
+fn escaped_len(value: &str) -> usize {
+    value.len() + value.bytes().filter(|&b| b == b'"' || b == b'\\').count()
+}
+
+/// Write the decimal representation of `value` into the end of `digits`, returning the digits.
+pub(crate) fn format_uint(value: u32, digits: &mut [u8; 10]) -> &[u8] {
+    let mut start = digits.len();
+    let mut remaining = value;
+
+    loop {
+        start -= 1;
+        digits[start] = b'0' + (remaining % 10) as u8;
+        remaining /= 10;
+        if remaining == 0 {
+            break;
+        }
+    }
+
+    &digits[start..]
+}
+
+/// A CoRE Link Format document, such as the payload of a `/.well-known/core` response.
+///
+/// Iterating over the document yields its links without copying. Parsing is lazy, so syntax
+/// errors are reported by the iterator when the malformed link is reached.
+///
+/// Source: [RFC 6690 2](https://datatracker.ietf.org/doc/html/rfc6690#section-2)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LinkFormat<'a> {
+    document: &'a str,
+}
+
+impl<'a> LinkFormat<'a> {
+    /// Create a link format document from a string.
+    pub fn new(document: &'a str) -> Self {
+        Self { document }
+    }
+
+    /// Create a link format document from a message payload, which must be valid UTF-8.
+    pub fn parse(payload: &'a [u8]) -> LinkFormatResult<Self> {
+        core::str::from_utf8(payload)
+            .map(Self::new)
+            .map_err(|_| CoapLinkFormatError::InvalidUtf8)
+    }
+
+    /// Returns an iterator over the links in the document.
+    pub fn iter(&self) -> LinkIterator<'a> {
+        LinkIterator {
+            document: self.document,
+            offset: 0,
+        }
+    }
+}
+
+impl<'a> IntoIterator for LinkFormat<'a> {
+    type Item = LinkFormatResult<Link<'a>>;
+    type IntoIter = LinkIterator<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over the links in a [`LinkFormat`] document. Iteration stops after the first error.
+#[derive(Debug, Clone)]
+pub struct LinkIterator<'a> {
+    document: &'a str,
+    offset: usize,
+}
+
+impl<'a> LinkIterator<'a> {
+    fn parse_link(&mut self, start: usize) -> LinkFormatResult<Link<'a>> {
+        let rest = &self.document[start..];
+
+        if !rest.starts_with('<') {
+            return Err(CoapLinkFormatError::ExpectedLink(start));
+        }
+
+        let target_end = rest
+            .find('>')
+            .ok_or(CoapLinkFormatError::UnterminatedTarget(start))?;
+        let target = &rest[1..target_end];
+
+        let params = &rest[target_end + 1..];
+        let params_end = find_unquoted(params, b',').map_err(|quote| {
+            CoapLinkFormatError::UnterminatedQuote(start + target_end + 1 + quote)
+        })?;
+        let trimmed = params[..params_end].trim_start();
+
+        if !trimmed.is_empty() && !trimmed.starts_with(';') {
+            let offset = start + target_end + 1 + params_end - trimmed.len();
+            return Err(CoapLinkFormatError::UnexpectedCharacter(offset));
+        }
+
+        // Continue after the comma separating this link from the next.
+        self.offset = start + target_end + 1 + params_end + 1;
+
+        Ok(Link {
+            target,
+            params: trimmed.trim_end(),
+        })
+    }
+}
+
+impl<'a> Iterator for LinkIterator<'a> {
+    type Item = LinkFormatResult<Link<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = self.document.get(self.offset..)?;
+        let trimmed = rest.trim_start();
+
+        if trimmed.is_empty() {
+            self.offset = self.document.len();
+            return None;
+        }
+
+        let result = self.parse_link(self.offset + rest.len() - trimmed.len());
+        if result.is_err() {
+            self.offset = self.document.len();
+        }
+
+        Some(result)
+    }
+}
+
+/// A single link of a [`LinkFormat`] document, made of a target URI and its attributes.
+///
+/// Source: [RFC 6690 2](https://datatracker.ietf.org/doc/html/rfc6690#section-2)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Link<'a> {
+    /// Target URI of the link, without the enclosing `<` and `>`
+    pub target: &'a str,
+    params: &'a str,
+}
+
+impl<'a> Link<'a> {
+    /// Returns an iterator over the attributes of the link, in the order they appear.
+    pub fn attributes(&self) -> AttributeIterator<'a> {
+        AttributeIterator {
+            params: self.params,
+        }
+    }
+
+    /// Returns the value of the first attribute with the given name. Attributes without a value
+    /// return an empty string.
+    pub fn attribute(&self, name: &str) -> Option<&'a str> {
+        self.attributes()
+            .find(|attribute| attribute.name == name)
+            .map(|attribute| attribute.value.unwrap_or(""))
+    }
+
+    /// Returns the space-separated values of every attribute with the given name, so that both
+    /// `rt="a b"` and `rt=a;rt=b` yield `a` and `b`.
+    pub fn values(&self, name: &'a str) -> impl Iterator<Item = &'a str> {
+        self.attributes()
+            .filter(move |attribute| attribute.name == name)
+            .filter_map(|attribute| attribute.value)
+            .flat_map(str::split_ascii_whitespace)
+    }
+
+    /// Returns the resource types (`rt`) of the link.
+    ///
+    /// Source: [RFC 6690 3.1](https://datatracker.ietf.org/doc/html/rfc6690#section-3.1)
+    pub fn resource_types(&self) -> impl Iterator<Item = &'a str> {
+        self.values("rt")
+    }
+
+    /// Returns the interface descriptions (`if`) of the link.
+    ///
+    /// Source: [RFC 6690 3.2](https://datatracker.ietf.org/doc/html/rfc6690#section-3.2)
+    pub fn interfaces(&self) -> impl Iterator<Item = &'a str> {
+        self.values("if")
+    }
+
+    /// Returns the content formats (`ct`) the target can be requested in. Values that are not
+    /// valid Content-Format numbers are skipped.
+    ///
+    /// Source: [RFC 7252 7.2.1](https://datatracker.ietf.org/doc/html/rfc7252#section-7.2.1)
+    pub fn content_formats(&self) -> impl Iterator<Item = u16> {
+        self.values("ct").filter_map(|value| value.parse().ok())
+    }
+
+    /// Returns the estimated size (`sz`) of the target resource in bytes.
+    ///
+    /// Source: [RFC 6690 3.3](https://datatracker.ietf.org/doc/html/rfc6690#section-3.3)
+    pub fn size(&self) -> Option<u32> {
+        self.attribute("sz").and_then(|value| value.parse().ok())
+    }
+
+    /// Returns the human-readable title of the link.
+    ///
+    /// Source: [RFC 8288 3.4.1](https://datatracker.ietf.org/doc/html/rfc8288#section-3.4.1)
+    pub fn title(&self) -> Option<&'a str> {
+        self.attribute("title")
+    }
+
+    /// Check if the target resource can be observed (`obs`).
+    ///
+    /// Source: [RFC 7641 6](https://datatracker.ietf.org/doc/html/rfc7641#section-6)
+    pub fn is_observable(&self) -> bool {
+        self.attribute("obs").is_some()
+    }
+}
+
+/// A single attribute of a [`Link`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LinkAttribute<'a> {
+    /// Name of the attribute
+    pub name: &'a str,
+    /// Value of the attribute, without enclosing quotes. Escape sequences inside quoted values are
+    /// left as they are.
+    pub value: Option<&'a str>,
+}
+
+/// Iterator over the attributes of a [`Link`].
+#[derive(Debug, Clone)]
+pub struct AttributeIterator<'a> {
+    params: &'a str,
+}
+
+impl<'a> Iterator for AttributeIterator<'a> {
+    type Item = LinkAttribute<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let rest = self.params.trim_start().strip_prefix(';')?;
+
+            // Quotes were checked when the link was parsed.
+            let end = find_unquoted(rest, b';').unwrap_or(rest.len());
+            let param = rest[..end].trim();
+            self.params = &rest[end..];
+
+            if param.is_empty() {
+                continue;
+            }
+
+            let Some((name, value)) = param.split_once('=') else {
+                return Some(LinkAttribute {
+                    name: param,
+                    value: None,
+                });
+            };
+
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|value| value.strip_suffix('"'))
+                .unwrap_or(value);
+
+            return Some(LinkAttribute {
+                name: name.trim_end(),
+                value: Some(value),
+            });
+        }
+    }
+}
+
+/// Writer for CoRE Link Format documents into a caller-provided buffer.
+///
+/// The finished document can be passed directly to
+/// [`MessageBuilder::payload`](crate::MessageBuilder::payload).
+///
+/// Source: [RFC 6690 2](https://datatracker.ietf.org/doc/html/rfc6690#section-2)
+pub struct LinkWriter<'buf> {
+    buffer: &'buf mut [u8],
+    offset: usize,
+    has_link: bool,
+}
+
+impl<'buf> LinkWriter<'buf> {
+    /// Create a writer for an empty document.
+    pub fn new(buffer: &'buf mut [u8]) -> Self {
+        Self {
+            buffer,
+            offset: 0,
+            has_link: false,
+        }
+    }
+
+    /// Returns the length of the document written so far.
+    pub fn len(&self) -> usize {
+        self.offset
+    }
+
+    /// Check if no links have been written.
+    pub fn is_empty(&self) -> bool {
+        !self.has_link
+    }
+
+    /// Start a new link to `target`. Attributes written afterwards belong to this link.
+    pub fn link(&mut self, target: &str) -> LinkFormatResult<&mut Self> {
+        let separator = if self.has_link { 1 } else { 0 };
+        self.reserve(separator + 2 + target.len())?;
+
+        if self.has_link {
+            self.write(b",");
+        }
+        self.write(b"<");
+        self.write(target.as_bytes());
+        self.write(b">");
+        self.has_link = true;
+
+        Ok(self)
+    }
+
+    /// Add an attribute with a quoted string value to the current link. Quotes and backslashes
+    /// in the value are escaped.
+    pub fn attribute(&mut self, name: &str, value: &str) -> LinkFormatResult<&mut Self> {
+        self.start_attribute(name, 3 + escaped_len(value))?;
+
+        self.write(b"=\"");
+        self.write_escaped(value);
+        self.write(b"\"");
+
+        Ok(self)
+    }
+
+    /// Add an attribute with several space-separated values to the current link, such as
+    /// multiple resource types. Quotes and backslashes in the values are escaped.
+    pub fn attribute_values(&mut self, name: &str, values: &[&str]) -> LinkFormatResult<&mut Self> {
+        let length = values.iter().map(|value| escaped_len(value)).sum::<usize>()
+            + values.len().saturating_sub(1);
+        self.start_attribute(name, 3 + length)?;
+
+        self.write(b"=\"");
+        for (i, value) in values.iter().enumerate() {
+            if i > 0 {
+                self.write(b" ");
+            }
+            self.write_escaped(value);
+        }
+        self.write(b"\"");
+
+        Ok(self)
+    }
+
+    fn write_escaped(&mut self, value: &str) {
+        for &byte in value.as_bytes() {
+            if byte == b'"' || byte == b'\\' {
+                self.write(b"\\");
+            }
+            self.write(&[byte]);
+        }
+    }
+
+    /// Add an attribute with an unquoted integer value to the current link, such as `ct` or `sz`.
+    pub fn attribute_uint(&mut self, name: &str, value: u32) -> LinkFormatResult<&mut Self> {
+        let mut digits = [0; 10];
+        let digits = format_uint(value, &mut digits);
+
+        self.start_attribute(name, 1 + digits.len())?;
+        self.write(b"=");
+        self.write(digits);
+
+        Ok(self)
+    }
+
+    /// Add an attribute without a value to the current link, such as `obs`.
+    pub fn flag(&mut self, name: &str) -> LinkFormatResult<&mut Self> {
+        self.start_attribute(name, 0)?;
+
+        Ok(self)
+    }
+
+    /// Finish the document, returning the written bytes.
+    pub fn finish(self) -> &'buf [u8] {
+        &self.buffer[..self.offset]
+    }
+
+    /// Write `;name` after checking that it and `value_len` more bytes fit in the buffer.
+    fn start_attribute(&mut self, name: &str, value_len: usize) -> LinkFormatResult<()> {
+        if !self.has_link {
+            return Err(CoapLinkFormatError::AttributeWithoutLink);
+        }
+
+        self.reserve(1 + name.len() + value_len)?;
+        self.write(b";");
+        self.write(name.as_bytes());
+
+        Ok(())
+    }
+
+    fn reserve(&self, len: usize) -> LinkFormatResult<()> {
+        if self.offset + len > self.buffer.len() {
+            return Err(CoapLinkFormatError::BufferTooSmall);
+        }
+
+        Ok(())
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.buffer[self.offset..self.offset + bytes.len()].copy_from_slice(bytes);
+        self.offset += bytes.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_rfc_example() {
+        let document = LinkFormat::new(
+            "</sensors/temp>;rt=\"temperature-c\";if=\"sensor\",\n\
+             </sensors/light>;rt=\"light-lux\";if=\"sensor\"",
+        );
+
+        let mut links = document.iter();
+
+        let temp = links.next().unwrap().unwrap();
+        assert_eq!(temp.target, "/sensors/temp");
+        assert_eq!(temp.attribute("rt"), Some("temperature-c"));
+        assert_eq!(temp.interfaces().next(), Some("sensor"));
+
+        let light = links.next().unwrap().unwrap();
+        assert_eq!(light.target, "/sensors/light");
+        assert_eq!(light.resource_types().next(), Some("light-lux"));
+
+        assert!(links.next().is_none());
+    }
+
+    #[test]
+    fn parse_multiple_values_and_quotes() {
+        let document = LinkFormat::parse(
+            b"</s>;rt=\"core.s temp\";rt=extra;ct=\"0 40\";obs;sz=1024;title=\"a, b; c\",</t>",
+        )
+        .unwrap();
+
+        let mut links = document.into_iter();
+        let link = links.next().unwrap().unwrap();
+
+        let mut types = link.resource_types();
+        assert_eq!(types.next(), Some("core.s"));
+        assert_eq!(types.next(), Some("temp"));
+        assert_eq!(types.next(), Some("extra"));
+        assert_eq!(types.next(), None);
+
+        let mut formats = link.content_formats();
+        assert_eq!(formats.next(), Some(0));
+        assert_eq!(formats.next(), Some(40));
+        assert_eq!(formats.next(), None);
+
+        assert!(link.is_observable());
+        assert_eq!(link.size(), Some(1024));
+        assert_eq!(link.title(), Some("a, b; c"));
+
+        let last = links.next().unwrap().unwrap();
+        assert_eq!(last.target, "/t");
+        assert_eq!(last.attributes().next(), None);
+        assert!(links.next().is_none());
+    }
+
+    #[test]
+    fn parse_errors() {
+        let mut links = LinkFormat::new("</a>,/b").iter();
+        assert!(links.next().unwrap().is_ok());
+        assert_eq!(
+            links.next(),
+            Some(Err(CoapLinkFormatError::ExpectedLink(5)))
+        );
+        assert!(links.next().is_none());
+
+        assert_eq!(
+            LinkFormat::new("</a").iter().next(),
+            Some(Err(CoapLinkFormatError::UnterminatedTarget(0)))
+        );
+        assert_eq!(
+            LinkFormat::new("</a>;title=\"x").iter().next(),
+            Some(Err(CoapLinkFormatError::UnterminatedQuote(11)))
+        );
+        assert_eq!(
+            LinkFormat::new("</a>x").iter().next(),
+            Some(Err(CoapLinkFormatError::UnexpectedCharacter(4)))
+        );
+        assert_eq!(
+            LinkFormat::parse(&[0xFF]),
+            Err(CoapLinkFormatError::InvalidUtf8)
+        );
+    }
+
+    #[test]
+    fn write_roundtrip() -> Result<(), CoapLinkFormatError> {
+        let mut buffer = [0; 128];
+        let mut writer = LinkWriter::new(&mut buffer);
+
+        writer
+            .link("/sensors/temp")?
+            .attribute_values("rt", &["temperature-c", "core.s"])?
+            .attribute_uint("ct", 0)?
+            .flag("obs")?;
+        writer.link("/name")?.attribute("title", "say \"hi\"")?;
+
+        let document = writer.finish();
+        assert_eq!(
+            document,
+            b"</sensors/temp>;rt=\"temperature-c core.s\";ct=0;obs,</name>;title=\"say \\\"hi\\\"\""
+        );
+
+        let mut links = LinkFormat::parse(document)?.iter();
+        let temp = links.next().unwrap()?;
+        assert_eq!(temp.resource_types().count(), 2);
+        assert_eq!(temp.content_formats().next(), Some(0));
+        assert!(temp.is_observable());
+
+        let name = links.next().unwrap()?;
+        assert_eq!(name.title(), Some("say \\\"hi\\\""));
+
+        Ok(())
+    }
+
+    #[test]
+    fn escape_attribute_values() -> Result<(), CoapLinkFormatError> {
+        let mut buffer = [0; 18];
+        let mut writer = LinkWriter::new(&mut buffer);
+        writer
+            .link("/a")?
+            .attribute_values("rt", &["x\"y", "z\\"])?;
+
+        let document = writer.finish();
+        assert_eq!(document, b"</a>;rt=\"x\\\"y z\\\\\"");
+        assert_eq!(LinkFormat::parse(document)?.iter().count(), 1);
+
+        let mut buffer = [0; 17];
+        let mut writer = LinkWriter::new(&mut buffer);
+        writer.link("/a")?;
+        assert_eq!(
+            writer.attribute_values("rt", &["x\"y", "z\\"]).err(),
+            Some(CoapLinkFormatError::BufferTooSmall)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn write_errors() {
+        let mut buffer = [0; 8];
+        let mut writer = LinkWriter::new(&mut buffer);
+
+        assert_eq!(
+            writer.flag("obs").err(),
+            Some(CoapLinkFormatError::AttributeWithoutLink)
+        );
+        assert!(writer.link("/a").is_ok());
+        assert_eq!(
+            writer.link("/toolong").err(),
+            Some(CoapLinkFormatError::BufferTooSmall)
+        );
+        assert_eq!(writer.finish(), b"</a>");
+    }
+}
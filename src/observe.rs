@@ -0,0 +1,382 @@
+use core::time::Duration;
+
+use crate::error::{CoapBuildError, CoapObserveError};
+use crate::{Message, MessageBuilder, MessageType, NeedsPayload, RequestCode, ResponseCode};
+
+/// Observe option value used in a GET request to register as an observer.
+pub(crate) const OBSERVE_REGISTER: u32 = 0;
+/// Observe option value used in a GET request to deregister as an observer.
+pub(crate) const OBSERVE_DEREGISTER: u32 = 1;
+
+/// Observe sequence numbers are 24 bits wide and wrap around.
+pub(crate) const SEQUENCE_MASK: u32 = 0x00FF_FFFF;
+
+/// Half of the sequence number space, used to detect wraparound.
+const SEQUENCE_HALF: u32 = 1 << 23;
+
+/// Notifications older than this are considered stale regardless of their sequence number.
+const NOTIFICATION_MAX_AGE: Duration = Duration::from_secs(128);
+
+/// Determines whether a notification with sequence number `new` is fresher than a previously
+/// received notification with sequence number `last`, given the time elapsed between the two
+/// notifications arriving.
+///
+/// Source: [RFC 7641 3.4](https://datatracker.ietf.org/doc/html/rfc7641#section-3.4)
+pub(crate) fn is_fresher(last: u32, new: u32, since_last: Duration) -> bool {
+    let last = last & SEQUENCE_MASK;
+    let new = new & SEQUENCE_MASK;
+
+    (last < new && new - last < SEQUENCE_HALF)
+        || (last > new && last - new > SEQUENCE_HALF)
+        || since_last > NOTIFICATION_MAX_AGE
+}
+
+/// Outcome of passing a request to [`ObserverRegistry::handle_request`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ObserveRequest {
+    /// The client was added to (or refreshed in) the list of observers. The response should carry
+    /// an Observe option with the contained sequence number.
+    Registered(u32),
+    /// The client was removed from the list of observers.
+    Deregistered,
+    /// The request is not an Observe registration or deregistration.
+    NotObserve,
+}
+
+/// A client observing a resource.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Observer<E> {
+    endpoint: E,
+    token: [u8; 8],
+    token_len: u8,
+    last_sequence: u32,
+    last_message_id: Option<u16>,
+    last_confirmable: bool,
+}
+
+impl<E> Observer<E> {
+    /// Returns the endpoint (e.g. socket address) of the observer.
+    pub fn endpoint(&self) -> &E {
+        &self.endpoint
+    }
+
+    /// Returns the token the observer registered with.
+    pub fn token(&self) -> &[u8] {
+        &self.token[..self.token_len as usize]
+    }
+
+    /// Returns the sequence number of the last notification sent to this observer.
+    pub fn last_sequence(&self) -> u32 {
+        self.last_sequence
+    }
+
+    /// Returns the message ID of the last notification sent to this observer, if any.
+    pub fn last_message_id(&self) -> Option<u16> {
+        self.last_message_id
+    }
+
+    /// Check if the last notification sent to this observer was confirmable.
+    ///
+    /// A server must send a confirmable notification at least every 24 hours to find out if the
+    /// client is still interested.
+    ///
+    /// Source: [RFC 7641 4.5](https://datatracker.ietf.org/doc/html/rfc7641#section-4.5)
+    pub fn last_confirmable(&self) -> bool {
+        self.last_confirmable
+    }
+
+    /// Start building a notification for this observer. The returned builder already contains the
+    /// token and Observe option, so only options with a number greater than 6 may be added.
+    pub fn notification<'buf>(
+        &mut self,
+        buffer: &'buf mut [u8],
+        msg_type: MessageType,
+        code: ResponseCode,
+        message_id: u16,
+        sequence: u32,
+    ) -> Result<MessageBuilder<'buf, NeedsPayload>, CoapBuildError> {
+        let builder = MessageBuilder::new(buffer)?
+            .response(msg_type, code)
+            .message_id(message_id)
+            .token(&self.token[..self.token_len as usize])?
+            .observe_sequence(sequence)?;
+
+        self.last_sequence = sequence & SEQUENCE_MASK;
+        self.last_message_id = Some(message_id);
+        self.last_confirmable = msg_type == MessageType::Confirmable;
+
+        Ok(builder)
+    }
+}
+
+/// Fixed-capacity list of the observers of a single resource.
+///
+/// Source: [RFC 7641 4](https://datatracker.ietf.org/doc/html/rfc7641#section-4)
+pub struct ObserverRegistry<E, const N: usize> {
+    observers: [Option<Observer<E>>; N],
+    sequence: u32,
+}
+
+impl<E, const N: usize> Default for ObserverRegistry<E, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E, const N: usize> ObserverRegistry<E, N> {
+    /// Create an empty registry.
+    pub const fn new() -> Self {
+        Self {
+            observers: [const { None }; N],
+            sequence: 0,
+        }
+    }
+
+    /// Returns the current sequence number of the resource.
+    pub fn sequence(&self) -> u32 {
+        self.sequence
+    }
+
+    /// Advance the sequence number of the resource, to be called whenever its state changes.
+    /// Returns the new sequence number, to be passed to [`Observer::notification`].
+    pub fn next_sequence(&mut self) -> u32 {
+        self.sequence = (self.sequence + 1) & SEQUENCE_MASK;
+        self.sequence
+    }
+
+    /// Returns the number of registered observers.
+    pub fn len(&self) -> usize {
+        self.observers.iter().flatten().count()
+    }
+
+    /// Check if there are no registered observers.
+    pub fn is_empty(&self) -> bool {
+        self.observers.iter().all(Option::is_none)
+    }
+
+    /// Iterate over the registered observers.
+    pub fn iter(&self) -> impl Iterator<Item = &Observer<E>> {
+        self.observers.iter().flatten()
+    }
+
+    /// Iterate mutably over the registered observers, e.g. to send notifications.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Observer<E>> {
+        self.observers.iter_mut().flatten()
+    }
+}
+
+impl<E: PartialEq, const N: usize> ObserverRegistry<E, N> {
+    /// Process a request received from `endpoint`, registering or deregistering the client if it
+    /// is a GET or FETCH request carrying an Observe option.
+    ///
+    /// Source: [RFC 7641 4.1](https://datatracker.ietf.org/doc/html/rfc7641#section-4.1)
+    pub fn handle_request(
+        &mut self,
+        endpoint: E,
+        request: &Message<'_>,
+    ) -> Result<ObserveRequest, CoapObserveError> {
+        let is_get = request.code == u8::from(RequestCode::Get)
+            || request.code == u8::from(RequestCode::Fetch);
+
+        let Some(value) = request.observe().filter(|_| is_get) else {
+            return Ok(ObserveRequest::NotObserve);
+        };
+
+        match value {
+            OBSERVE_REGISTER => {
+                self.register(endpoint, request.token)?;
+                Ok(ObserveRequest::Registered(self.sequence))
+            }
+            OBSERVE_DEREGISTER => {
+                self.remove(&endpoint, request.token);
+                Ok(ObserveRequest::Deregistered)
+            }
+            other => Err(CoapObserveError::InvalidObserveValue(other)),
+        }
+    }
+
+    /// Remove the observer that a Reset message from `endpoint` refers to, matched by the message
+    /// ID of the last notification. Returns `true` if an observer was removed.
+    ///
+    /// Source: [RFC 7641 3.6](https://datatracker.ietf.org/doc/html/rfc7641#section-3.6)
+    pub fn handle_reset(&mut self, endpoint: &E, reset: &Message<'_>) -> bool {
+        if reset.message_type != MessageType::Reset {
+            return false;
+        }
+
+        self.remove_by_message_id(endpoint, reset.message_id)
+    }
+
+    /// Remove the observer that failed to acknowledge the confirmable notification with the given
+    /// message ID. Returns `true` if an observer was removed.
+    ///
+    /// Source: [RFC 7641 4.5](https://datatracker.ietf.org/doc/html/rfc7641#section-4.5)
+    pub fn notification_failed(&mut self, endpoint: &E, message_id: u16) -> bool {
+        self.remove_by_message_id(endpoint, message_id)
+    }
+
+    fn register(&mut self, endpoint: E, token: &[u8]) -> Result<(), CoapObserveError> {
+        // An existing entry with the same endpoint and token is replaced, not duplicated.
+        self.remove(&endpoint, token);
+
+        let slot = self
+            .observers
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(CoapObserveError::RegistryFull)?;
+
+        let mut stored_token = [0; 8];
+        stored_token[..token.len()].copy_from_slice(token);
+
+        *slot = Some(Observer {
+            endpoint,
+            token: stored_token,
+            token_len: token.len() as u8,
+            last_sequence: self.sequence,
+            last_message_id: None,
+            last_confirmable: false,
+        });
+
+        Ok(())
+    }
+
+    fn remove(&mut self, endpoint: &E, token: &[u8]) -> bool {
+        self.remove_where(|observer| &observer.endpoint == endpoint && observer.token() == token)
+    }
+
+    fn remove_by_message_id(&mut self, endpoint: &E, message_id: u16) -> bool {
+        self.remove_where(|observer| {
+            &observer.endpoint == endpoint && observer.last_message_id == Some(message_id)
+        })
+    }
+
+    fn remove_where(&mut self, predicate: impl Fn(&Observer<E>) -> bool) -> bool {
+        let mut removed = false;
+
+        for slot in self.observers.iter_mut() {
+            if slot.as_ref().is_some_and(&predicate) {
+                *slot = None;
+                removed = true;
+            }
+        }
+
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fresher_in_order() {
+        assert!(is_fresher(1, 2, Duration::ZERO));
+        assert!(!is_fresher(2, 1, Duration::ZERO));
+        assert!(!is_fresher(5, 5, Duration::ZERO));
+    }
+
+    #[test]
+    fn fresher_across_wraparound() {
+        assert!(is_fresher(SEQUENCE_MASK, 0, Duration::ZERO));
+        assert!(is_fresher(SEQUENCE_MASK - 10, 3, Duration::ZERO));
+        assert!(!is_fresher(3, SEQUENCE_MASK - 10, Duration::ZERO));
+    }
+
+    #[test]
+    fn fresher_after_max_age() {
+        assert!(!is_fresher(10, 9, Duration::from_secs(128)));
+        assert!(is_fresher(10, 9, Duration::from_secs(129)));
+    }
+
+    fn observe_request<'a>(buffer: &'a mut [u8], token: &[u8], observe: u32) -> Message<'a> {
+        let packet = MessageBuilder::new(buffer)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(0x0001)
+            .token(token)
+            .unwrap()
+            .option_uint(crate::OptionNumber::Observe, observe)
+            .unwrap()
+            .no_payload()
+            .build();
+
+        Message::parse(packet).unwrap()
+    }
+
+    #[test]
+    fn register_and_deregister() {
+        let mut registry = ObserverRegistry::<u8, 2>::new();
+        let mut buffer = [0; 32];
+
+        let request = observe_request(&mut buffer, &[0xAA], OBSERVE_REGISTER);
+        assert_eq!(
+            registry.handle_request(1, &request),
+            Ok(ObserveRequest::Registered(0))
+        );
+        // Registering again with the same token refreshes the entry.
+        assert_eq!(
+            registry.handle_request(1, &request),
+            Ok(ObserveRequest::Registered(0))
+        );
+        assert_eq!(registry.len(), 1);
+
+        let mut buffer = [0; 32];
+        let request = observe_request(&mut buffer, &[0xAA], OBSERVE_DEREGISTER);
+        assert_eq!(
+            registry.handle_request(1, &request),
+            Ok(ObserveRequest::Deregistered)
+        );
+        assert!(registry.is_empty());
+    }
+
+    #[test]
+    fn registry_full() {
+        let mut registry = ObserverRegistry::<u8, 1>::new();
+        let mut buffer = [0; 32];
+
+        let request = observe_request(&mut buffer, &[0xAA], OBSERVE_REGISTER);
+        assert!(registry.handle_request(1, &request).is_ok());
+        assert_eq!(
+            registry.handle_request(2, &request),
+            Err(CoapObserveError::RegistryFull)
+        );
+    }
+
+    #[test]
+    fn notify_and_remove_on_reset() {
+        let mut registry = ObserverRegistry::<u8, 2>::new();
+        let mut buffer = [0; 32];
+
+        let request = observe_request(&mut buffer, &[0xAA, 0xBB], OBSERVE_REGISTER);
+        registry.handle_request(7, &request).unwrap();
+
+        let sequence = registry.next_sequence();
+        let mut tx_buf = [0; 32];
+        let observer = registry.iter_mut().next().unwrap();
+        let packet = observer
+            .notification(
+                &mut tx_buf,
+                MessageType::Confirmable,
+                ResponseCode::Content,
+                0x0042,
+                sequence,
+            )
+            .unwrap()
+            .payload(b"1")
+            .unwrap()
+            .build();
+
+        let notification = Message::parse(packet).unwrap();
+        assert_eq!(notification.token, &[0xAA, 0xBB]);
+        assert_eq!(notification.observe(), Some(1));
+        assert!(observer.last_confirmable());
+
+        let reset = [0x70, 0x00, 0x00, 0x42];
+        let reset = Message::parse(&reset).unwrap();
+        assert!(!registry.handle_reset(&8, &reset));
+        assert!(registry.handle_reset(&7, &reset));
+        assert!(registry.is_empty());
+    }
+}
@@ -0,0 +1,1114 @@
+use aes::Aes128;
+use ccm::Ccm;
+use ccm::aead::generic_array::GenericArray;
+use ccm::aead::{AeadInPlace, KeyInit};
+use ccm::consts::{U8, U13};
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+use crate::builder::encode_option_header;
+use crate::cbor::{self, Encoder};
+use crate::error::CoapOscoreError;
+use crate::parser::parse_options_and_payload;
+use crate::{CoapOption, Message, MessageBuilder, OptionNumber, RequestCode, ResponseCode};
+
+type AesCcm = Ccm<Aes128, U8, U13>;
+type OscoreResult<T> = core::result::Result<T, CoapOscoreError>;
+
+/// COSE algorithm identifier of AES-CCM-16-64-128.
+const AES_CCM_16_64_128: i64 = 10;
+const KEY_LEN: usize = 16;
+const NONCE_LEN: usize = 13;
+const TAG_LEN: usize = 8;
+/// Longest Sender or Recipient ID that fits in the nonce.
+const MAX_ID_LEN: usize = NONCE_LEN - 6;
+const MAX_PIV_LEN: usize = 5;
+/// Longest ID Context supported.
+const MAX_ID_CONTEXT_LEN: usize = 16;
+/// Largest Sender Sequence Number, which must fit in the Partial IV.
+const MAX_SEQUENCE_NUMBER: u64 = (1 << 40) - 1;
+/// Number of Partial IVs up to the highest one received that are checked for replays.
+const REPLAY_WINDOW_SIZE: u64 = 32;
+
+/// Flag bits of the first byte of the OSCORE option value.
+const FLAG_RESERVED: u8 = 0xE0;
+const FLAG_KID_CONTEXT: u8 = 0x10;
+const FLAG_KID: u8 = 0x08;
+const FLAG_PIV_LEN: u8 = 0x07;
+
+/// Bytes of at most `N` length, stored inline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+struct InlineBytes<const N: usize> {
+    bytes: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> InlineBytes<N> {
+    fn new(value: &[u8]) -> Option<Self> {
+        let mut bytes = [0; N];
+        bytes.get_mut(..value.len())?.copy_from_slice(value);
+
+        Some(InlineBytes {
+            bytes,
+            len: value.len(),
+        })
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+/// Returns the Partial IV encoding a sequence number, which is at least one byte long.
+fn partial_iv(sequence_number: u64) -> InlineBytes<MAX_PIV_LEN> {
+    let bytes = sequence_number.to_be_bytes();
+    let len = (8 - sequence_number.leading_zeros() as usize / 8).clamp(1, MAX_PIV_LEN);
+
+    let mut piv = InlineBytes {
+        bytes: [0; MAX_PIV_LEN],
+        len,
+    };
+    piv.bytes[..len].copy_from_slice(&bytes[8 - len..]);
+    piv
+}
+
+/// Identifies a protected request, which binds the response to it.
+///
+/// Returned when protecting a request on the client and unprotecting it on the server, and needed
+/// to protect and unprotect the response.
+///
+/// Source: [RFC 8613 5.4](https://datatracker.ietf.org/doc/html/rfc8613#section-5.4)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RequestBinding {
+    kid: InlineBytes<MAX_ID_LEN>,
+    piv: InlineBytes<MAX_PIV_LEN>,
+    /// Sequence number of the latest notification verified for this request.
+    notification: Option<u64>,
+}
+
+impl RequestBinding {
+    /// Returns the kid of the request, which is the Sender ID of the client.
+    pub fn kid(&self) -> &[u8] {
+        self.kid.as_slice()
+    }
+
+    /// Returns the Partial IV of the request.
+    pub fn partial_iv(&self) -> &[u8] {
+        self.piv.as_slice()
+    }
+}
+
+/// The decoded value of an OSCORE option.
+///
+/// Source: [RFC 8613 6.1](https://datatracker.ietf.org/doc/html/rfc8613#section-6.1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct OscoreOption<'a> {
+    piv: &'a [u8],
+    kid_context: Option<&'a [u8]>,
+    kid: Option<&'a [u8]>,
+}
+
+impl<'a> OscoreOption<'a> {
+    fn parse(value: &'a [u8]) -> OscoreResult<Self> {
+        let mut option = OscoreOption {
+            piv: &[],
+            kid_context: None,
+            kid: None,
+        };
+
+        let Some((&flags, rest)) = value.split_first() else {
+            return Ok(option);
+        };
+
+        let piv_len = (flags & FLAG_PIV_LEN) as usize;
+        if flags & FLAG_RESERVED != 0 || piv_len > MAX_PIV_LEN {
+            return Err(CoapOscoreError::InvalidOption);
+        }
+
+        let (piv, mut rest) = rest
+            .split_at_checked(piv_len)
+            .ok_or(CoapOscoreError::InvalidOption)?;
+        option.piv = piv;
+
+        if flags & FLAG_KID_CONTEXT != 0 {
+            let (&len, context) = rest.split_first().ok_or(CoapOscoreError::InvalidOption)?;
+            let (context, remaining) = context
+                .split_at_checked(len as usize)
+                .ok_or(CoapOscoreError::InvalidOption)?;
+            option.kid_context = Some(context);
+            rest = remaining;
+        }
+
+        if flags & FLAG_KID != 0 {
+            option.kid = Some(rest);
+        } else if !rest.is_empty() {
+            return Err(CoapOscoreError::InvalidOption);
+        }
+
+        Ok(option)
+    }
+
+    /// Encode the option value into `buffer`. The value is empty when no field is present.
+    fn encode<'b>(&self, buffer: &'b mut [u8]) -> OscoreResult<&'b [u8]> {
+        let mut flags = self.piv.len() as u8;
+        let mut len = 1 + self.piv.len();
+        if let Some(context) = self.kid_context {
+            flags |= FLAG_KID_CONTEXT;
+            len += 1 + context.len();
+        }
+        if let Some(kid) = self.kid {
+            flags |= FLAG_KID;
+            len += kid.len();
+        }
+
+        if flags == 0 {
+            return Ok(&[]);
+        }
+
+        let value = buffer
+            .get_mut(..len)
+            .ok_or(CoapOscoreError::InvalidOption)?;
+        value[0] = flags;
+        let (piv, rest) = value[1..].split_at_mut(self.piv.len());
+        piv.copy_from_slice(self.piv);
+
+        let kid = match self.kid_context {
+            Some(context) => {
+                rest[0] = context.len() as u8;
+                let (dst, kid) = rest[1..].split_at_mut(context.len());
+                dst.copy_from_slice(context);
+                kid
+            }
+            None => rest,
+        };
+        kid.copy_from_slice(self.kid.unwrap_or_default());
+
+        Ok(value)
+    }
+}
+
+/// How OSCORE protects an option.
+///
+/// Source: [RFC 8613 4.1](https://datatracker.ietf.org/doc/html/rfc8613#section-4.1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OptionClass {
+    /// Class E: encrypted in the plaintext.
+    Inner,
+    /// Class U: visible to proxies in the outer message.
+    Outer,
+    /// Classes E and U: present in both messages.
+    Both,
+}
+
+fn option_class(number: OptionNumber) -> OptionClass {
+    match number {
+        OptionNumber::UriHost
+        | OptionNumber::UriPort
+        | OptionNumber::ProxyUri
+        | OptionNumber::ProxyScheme
+        | OptionNumber::HopLimit
+        | OptionNumber::Oscore => OptionClass::Outer,
+        OptionNumber::Observe | OptionNumber::NoResponse => OptionClass::Both,
+        _ => OptionClass::Inner,
+    }
+}
+
+fn is_inner(option: &CoapOption<'_>) -> bool {
+    option_class(option.number) != OptionClass::Outer
+}
+
+fn is_outer(option: &CoapOption<'_>) -> bool {
+    option_class(option.number) != OptionClass::Inner
+}
+
+/// Returns the AEAD nonce for a Partial IV generated by the endpoint with Sender ID `id_piv`.
+///
+/// Source: [RFC 8613 5.2](https://datatracker.ietf.org/doc/html/rfc8613#section-5.2)
+fn nonce(common_iv: &[u8; NONCE_LEN], id_piv: &[u8], piv: &[u8]) -> [u8; NONCE_LEN] {
+    let mut nonce = [0; NONCE_LEN];
+    nonce[0] = id_piv.len() as u8;
+    nonce[1 + MAX_ID_LEN - id_piv.len()..1 + MAX_ID_LEN].copy_from_slice(id_piv);
+    nonce[NONCE_LEN - piv.len()..].copy_from_slice(piv);
+
+    for (byte, iv) in nonce.iter_mut().zip(common_iv) {
+        *byte ^= iv;
+    }
+
+    nonce
+}
+
+/// Encode the additional authenticated data of a request or its response into `buffer`.
+///
+/// Source: [RFC 8613 5.4](https://datatracker.ietf.org/doc/html/rfc8613#section-5.4)
+fn aad<'b>(
+    request_kid: &[u8],
+    request_piv: &[u8],
+    buffer: &'b mut [u8; 40],
+) -> OscoreResult<&'b [u8]> {
+    // external_aad = [oscore_version, [alg_aead], request_kid, request_piv, options]
+    let mut external_aad = [0; 24];
+    let mut encoder = Encoder::new(&mut external_aad);
+    encoder.head(cbor::ARRAY, 5)?;
+    encoder.int(1)?;
+    encoder.head(cbor::ARRAY, 1)?;
+    encoder.int(AES_CCM_16_64_128)?;
+    encoder.bytes(request_kid)?;
+    encoder.bytes(request_piv)?;
+    encoder.bytes(&[])?;
+    let external_aad = encoder.finish();
+
+    // Enc_structure = ["Encrypt0", protected, external_aad]
+    let mut encoder = Encoder::new(buffer);
+    encoder.head(cbor::ARRAY, 3)?;
+    encoder.text("Encrypt0")?;
+    encoder.bytes(&[])?;
+    encoder.bytes(external_aad)?;
+
+    Ok(encoder.finish())
+}
+
+/// Derive a key or the Common IV of a security context.
+///
+/// Source: [RFC 8613 3.2.1](https://datatracker.ietf.org/doc/html/rfc8613#section-3.2.1)
+fn derive(
+    master_secret: &[u8],
+    master_salt: &[u8],
+    id: &[u8],
+    id_context: Option<&[u8]>,
+    kind: &str,
+    output: &mut [u8],
+) -> OscoreResult<()> {
+    // info = [id, id_context, alg_aead, type, L]
+    let mut info = [0; 48];
+    let mut encoder = Encoder::new(&mut info);
+    encoder.head(cbor::ARRAY, 5)?;
+    encoder.bytes(id)?;
+    match id_context {
+        Some(context) => encoder.bytes(context)?,
+        None => encoder.null()?,
+    }
+    encoder.int(AES_CCM_16_64_128)?;
+    encoder.text(kind)?;
+    encoder.int(output.len() as i64)?;
+
+    Hkdf::<Sha256>::new(Some(master_salt), master_secret)
+        .expand(encoder.finish(), output)
+        .map_err(|_| CoapOscoreError::InvalidId)
+}
+
+/// Returns the length of the plaintext of a message: its code, inner options and payload.
+fn plaintext_len(message: &Message<'_>) -> OscoreResult<usize> {
+    let mut len = 1 + message.payload.map_or(0, |payload| 1 + payload.len());
+    let mut last = 0;
+
+    for option in message.options.into_iter().filter(is_inner) {
+        let number = u16::from(option.number);
+        let mut header = [0; 5];
+        len += encode_option_header(number - last, option.value.len(), &mut header)?;
+        len += option.value.len();
+        last = number;
+    }
+
+    Ok(len)
+}
+
+/// Write the plaintext of a message into `dst`, which is [`plaintext_len`] bytes long.
+///
+/// Source: [RFC 8613 5.3](https://datatracker.ietf.org/doc/html/rfc8613#section-5.3)
+fn write_plaintext(message: &Message<'_>, dst: &mut [u8]) -> OscoreResult<()> {
+    dst[0] = message.code;
+    let mut offset = 1;
+    let mut last = 0;
+
+    for option in message.options.into_iter().filter(is_inner) {
+        let number = u16::from(option.number);
+        let mut header = [0; 5];
+        let header_len = encode_option_header(number - last, option.value.len(), &mut header)?;
+
+        dst[offset..offset + header_len].copy_from_slice(&header[..header_len]);
+        offset += header_len;
+        dst[offset..offset + option.value.len()].copy_from_slice(option.value);
+        offset += option.value.len();
+        last = number;
+    }
+
+    if let Some(payload) = message.payload {
+        dst[offset] = 0xFF;
+        dst[offset + 1..].copy_from_slice(payload);
+    }
+
+    Ok(())
+}
+
+/// Protect `message` into `buffer`: the outer message keeps the header, token and outer options,
+/// gains the OSCORE option with value `option`, and carries the encrypted plaintext as payload.
+///
+/// Source: [RFC 8613 8.1](https://datatracker.ietf.org/doc/html/rfc8613#section-8.1)
+fn seal<'b>(
+    key: &[u8; KEY_LEN],
+    nonce: &[u8; NONCE_LEN],
+    aad: &[u8],
+    message: &Message<'_>,
+    outer_code: u8,
+    option: &[u8],
+    buffer: &'b mut [u8],
+) -> OscoreResult<&'b [u8]> {
+    // Proxy-Uri would have to be split to keep the path and query confidential.
+    let unsupported = message
+        .options
+        .into_iter()
+        .any(|opt| matches!(opt.number, OptionNumber::ProxyUri | OptionNumber::Oscore));
+    if unsupported {
+        return Err(CoapOscoreError::InvalidMessage);
+    }
+
+    // AES-CCM with a 13 byte nonce encodes the plaintext length in 2 bytes.
+    let plaintext_len = plaintext_len(message)?;
+    if plaintext_len > u16::MAX as usize {
+        return Err(CoapOscoreError::InvalidMessage);
+    }
+
+    let mut builder = MessageBuilder::new(buffer)?
+        .header(message.message_type, outer_code)
+        .message_id(message.message_id)
+        .token(message.token)?;
+
+    let mut option_written = false;
+    for outer in message.options.into_iter().filter(is_outer) {
+        if !option_written && u16::from(outer.number) > u16::from(OptionNumber::Oscore) {
+            builder = builder.option(OptionNumber::Oscore, option)?;
+            option_written = true;
+        }
+        builder = builder.option(outer.number, outer.value)?;
+    }
+    if !option_written {
+        builder = builder.option(OptionNumber::Oscore, option)?;
+    }
+
+    let cipher = AesCcm::new(GenericArray::from_slice(key));
+    let mut sealed = Ok(());
+    let builder = builder.payload_with(plaintext_len + TAG_LEN, |dst| {
+        let (plaintext, tag) = dst.split_at_mut(plaintext_len);
+        sealed = write_plaintext(message, plaintext).and_then(|()| {
+            let computed = cipher
+                .encrypt_in_place_detached(GenericArray::from_slice(nonce), aad, plaintext)
+                .map_err(|_| CoapOscoreError::InvalidMessage)?;
+            tag.copy_from_slice(&computed);
+            Ok(())
+        });
+    })?;
+    sealed?;
+
+    Ok(builder.build())
+}
+
+/// Decrypt the payload of `message` and reassemble the original message into `buffer` from the
+/// outer header, token and options, and the decrypted code, inner options and payload.
+///
+/// Source: [RFC 8613 8.2](https://datatracker.ietf.org/doc/html/rfc8613#section-8.2)
+fn open<'b>(
+    key: &[u8; KEY_LEN],
+    nonce: &[u8; NONCE_LEN],
+    aad: &[u8],
+    message: &Message<'_>,
+    buffer: &'b mut [u8],
+) -> OscoreResult<Message<'b>> {
+    let payload = message.payload.unwrap_or_default();
+    let Some(ciphertext_len) = payload.len().checked_sub(TAG_LEN).filter(|&len| len > 0) else {
+        return Err(CoapOscoreError::DecryptionFailed);
+    };
+    let (ciphertext, tag) = payload.split_at(ciphertext_len);
+
+    // Decrypt at the end of the buffer, and reassemble the message in front of the plaintext.
+    let split = buffer
+        .len()
+        .checked_sub(ciphertext_len)
+        .ok_or(CoapOscoreError::Build(
+            crate::CoapBuildError::BufferTooSmall,
+        ))?;
+    let (output, plaintext) = buffer.split_at_mut(split);
+    plaintext.copy_from_slice(ciphertext);
+
+    AesCcm::new(GenericArray::from_slice(key))
+        .decrypt_in_place_detached(
+            GenericArray::from_slice(nonce),
+            aad,
+            plaintext,
+            GenericArray::from_slice(tag),
+        )
+        .map_err(|_| CoapOscoreError::DecryptionFailed)?;
+
+    let (inner_options, payload) =
+        parse_options_and_payload(plaintext, 1).map_err(|_| CoapOscoreError::InvalidMessage)?;
+
+    let mut builder = MessageBuilder::new(output)?
+        .header(message.message_type, plaintext[0])
+        .message_id(message.message_id)
+        .token(message.token)?;
+
+    let mut outer_options = message
+        .options
+        .into_iter()
+        .filter(|opt| option_class(opt.number) == OptionClass::Outer)
+        .filter(|opt| opt.number != OptionNumber::Oscore)
+        .peekable();
+    for inner in inner_options {
+        while let Some(outer) =
+            outer_options.next_if(|outer| u16::from(outer.number) < u16::from(inner.number))
+        {
+            builder = builder.option(outer.number, outer.value)?;
+        }
+        builder = builder.option(inner.number, inner.value)?;
+    }
+    for outer in outer_options {
+        builder = builder.option(outer.number, outer.value)?;
+    }
+
+    let builder = match payload {
+        Some(payload) => builder.payload(payload)?,
+        None => builder.no_payload(),
+    };
+
+    Message::parse(builder.build()).map_err(|_| CoapOscoreError::InvalidMessage)
+}
+
+fn oscore_option<'a>(message: &Message<'a>) -> OscoreResult<OscoreOption<'a>> {
+    let value = message
+        .options
+        .into_iter()
+        .find(|opt| opt.number == OptionNumber::Oscore)
+        .ok_or(CoapOscoreError::NotProtected)?
+        .value;
+
+    OscoreOption::parse(value)
+}
+
+fn has_observe(message: &Message<'_>) -> bool {
+    message
+        .options
+        .into_iter()
+        .any(|opt| opt.number == OptionNumber::Observe)
+}
+
+fn sequence_number(piv: &[u8]) -> u64 {
+    piv.iter().fold(0, |acc, &byte| (acc << 8) | byte as u64)
+}
+
+/// Sliding window of the Partial IVs received in requests.
+///
+/// Source: [RFC 8613 7.4](https://datatracker.ietf.org/doc/html/rfc8613#section-7.4)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+struct ReplayWindow {
+    highest: Option<u64>,
+    /// Bit `i` is set when `highest - i` was received.
+    received: u32,
+}
+
+impl ReplayWindow {
+    fn accepts(&self, sequence_number: u64) -> bool {
+        match self.highest {
+            None => true,
+            Some(highest) if sequence_number > highest => true,
+            Some(highest) => {
+                let age = highest - sequence_number;
+                age < REPLAY_WINDOW_SIZE && self.received & (1 << age) == 0
+            }
+        }
+    }
+
+    fn insert(&mut self, sequence_number: u64) {
+        match self.highest {
+            Some(highest) if sequence_number <= highest => {
+                self.received |= 1 << (highest - sequence_number);
+            }
+            highest => {
+                let shift = highest.map_or(REPLAY_WINDOW_SIZE, |highest| sequence_number - highest);
+                let received = u32::try_from(shift)
+                    .ok()
+                    .and_then(|shift| self.received.checked_shl(shift))
+                    .unwrap_or(0);
+                self.received = received | 1;
+                self.highest = Some(sequence_number);
+            }
+        }
+    }
+}
+
+/// An OSCORE security context shared by a client and a server, using AES-CCM-16-64-128 and
+/// HKDF SHA-256. It protects requests and responses end-to-end, so that proxies only see the
+/// options they need to forward the messages.
+///
+/// Each endpoint creates its context with its own Sender ID and the other endpoint's ID as its
+/// Recipient ID. The Sender Sequence Number must never be reused with the same keys, so it should
+/// be persisted and restored with [`SecurityContext::set_sender_sequence_number`].
+///
+/// Source: [RFC 8613 3](https://datatracker.ietf.org/doc/html/rfc8613#section-3)
+#[derive(Clone)]
+pub struct SecurityContext {
+    sender_id: InlineBytes<MAX_ID_LEN>,
+    recipient_id: InlineBytes<MAX_ID_LEN>,
+    id_context: Option<InlineBytes<MAX_ID_CONTEXT_LEN>>,
+    sender_key: [u8; KEY_LEN],
+    recipient_key: [u8; KEY_LEN],
+    common_iv: [u8; NONCE_LEN],
+    sender_sequence_number: u64,
+    replay_window: ReplayWindow,
+}
+
+impl core::fmt::Debug for SecurityContext {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        // The keys are left out.
+        f.debug_struct("SecurityContext")
+            .field("sender_id", &self.sender_id.as_slice())
+            .field("recipient_id", &self.recipient_id.as_slice())
+            .field("sender_sequence_number", &self.sender_sequence_number)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SecurityContext {
+    /// Derive a security context from the master secret and salt, which may be empty. Sender and
+    /// Recipient IDs are at most 7 bytes long, and the ID Context at most 16 bytes.
+    ///
+    /// Source: [RFC 8613 3.2](https://datatracker.ietf.org/doc/html/rfc8613#section-3.2)
+    pub fn new(
+        master_secret: &[u8],
+        master_salt: &[u8],
+        sender_id: &[u8],
+        recipient_id: &[u8],
+        id_context: Option<&[u8]>,
+    ) -> OscoreResult<Self> {
+        let mut context = SecurityContext {
+            sender_id: InlineBytes::new(sender_id).ok_or(CoapOscoreError::InvalidId)?,
+            recipient_id: InlineBytes::new(recipient_id).ok_or(CoapOscoreError::InvalidId)?,
+            id_context: id_context
+                .map(|context| InlineBytes::new(context).ok_or(CoapOscoreError::InvalidId))
+                .transpose()?,
+            sender_key: [0; KEY_LEN],
+            recipient_key: [0; KEY_LEN],
+            common_iv: [0; NONCE_LEN],
+            sender_sequence_number: 0,
+            replay_window: ReplayWindow::default(),
+        };
+
+        let derive = |id: &[u8], kind: &str, output: &mut [u8]| {
+            derive(master_secret, master_salt, id, id_context, kind, output)
+        };
+        derive(sender_id, "Key", &mut context.sender_key)?;
+        derive(recipient_id, "Key", &mut context.recipient_key)?;
+        derive(&[], "IV", &mut context.common_iv)?;
+
+        Ok(context)
+    }
+
+    /// Returns the Sender ID of this endpoint.
+    pub fn sender_id(&self) -> &[u8] {
+        self.sender_id.as_slice()
+    }
+
+    /// Returns the Recipient ID, which is the Sender ID of the other endpoint.
+    pub fn recipient_id(&self) -> &[u8] {
+        self.recipient_id.as_slice()
+    }
+
+    /// Returns the Sender Sequence Number the next protected message will use.
+    pub fn sender_sequence_number(&self) -> u64 {
+        self.sender_sequence_number
+    }
+
+    /// Restore the Sender Sequence Number, such as after a reboot. It must be higher than any
+    /// number used before with this context.
+    ///
+    /// Source: [RFC 8613 B.1.1](https://datatracker.ietf.org/doc/html/rfc8613#appendix-B.1.1)
+    pub fn set_sender_sequence_number(&mut self, sequence_number: u64) {
+        self.sender_sequence_number = sequence_number;
+    }
+
+    fn next_partial_iv(&mut self) -> OscoreResult<InlineBytes<MAX_PIV_LEN>> {
+        if self.sender_sequence_number > MAX_SEQUENCE_NUMBER {
+            return Err(CoapOscoreError::SequenceNumberExhausted);
+        }
+
+        let piv = partial_iv(self.sender_sequence_number);
+        self.sender_sequence_number += 1;
+
+        Ok(piv)
+    }
+
+    /// Protect a request into `buffer`. Its code is encrypted, and the outer code is POST, or
+    /// FETCH for an Observe request. Returns the protected request and the binding needed to
+    /// unprotect the response.
+    ///
+    /// Requests with a Proxy-Uri option are not supported; use Proxy-Scheme and the Uri options
+    /// instead.
+    ///
+    /// Source: [RFC 8613 8.1](https://datatracker.ietf.org/doc/html/rfc8613#section-8.1)
+    pub fn protect_request<'b>(
+        &mut self,
+        request: &Message<'_>,
+        buffer: &'b mut [u8],
+    ) -> OscoreResult<(&'b [u8], RequestBinding)> {
+        if !request.is_request() {
+            return Err(CoapOscoreError::InvalidMessage);
+        }
+
+        let piv = self.next_partial_iv()?;
+        let binding = RequestBinding {
+            kid: self.sender_id,
+            piv,
+            notification: None,
+        };
+
+        let mut option = [0; 1 + MAX_PIV_LEN + MAX_ID_LEN];
+        let option = OscoreOption {
+            piv: piv.as_slice(),
+            kid_context: None,
+            kid: Some(self.sender_id()),
+        }
+        .encode(&mut option)?;
+
+        let outer_code = match has_observe(request) {
+            true => RequestCode::Fetch,
+            false => RequestCode::Post,
+        };
+
+        let nonce = nonce(&self.common_iv, self.sender_id(), piv.as_slice());
+        let mut aad_buffer = [0; 40];
+        let aad = aad(self.sender_id(), piv.as_slice(), &mut aad_buffer)?;
+
+        let protected = seal(
+            &self.sender_key,
+            &nonce,
+            aad,
+            request,
+            outer_code.into(),
+            option,
+            buffer,
+        )?;
+
+        Ok((protected, binding))
+    }
+
+    /// Verify and decrypt a protected request into `buffer`, rejecting replayed requests. Returns
+    /// the original request and the binding needed to protect the response.
+    ///
+    /// Source: [RFC 8613 8.2](https://datatracker.ietf.org/doc/html/rfc8613#section-8.2)
+    pub fn unprotect_request<'b>(
+        &mut self,
+        request: &Message<'_>,
+        buffer: &'b mut [u8],
+    ) -> OscoreResult<(Message<'b>, RequestBinding)> {
+        let option = oscore_option(request)?;
+
+        let kid = option.kid.ok_or(CoapOscoreError::InvalidOption)?;
+        let id_context = self.id_context.as_ref().map(InlineBytes::as_slice);
+        let context_matches = option
+            .kid_context
+            .is_none_or(|context| Some(context) == id_context);
+        if kid != self.recipient_id() || !context_matches {
+            return Err(CoapOscoreError::UnknownKid);
+        }
+
+        if option.piv.is_empty() {
+            return Err(CoapOscoreError::InvalidOption);
+        }
+        let sequence_number = sequence_number(option.piv);
+        if !self.replay_window.accepts(sequence_number) {
+            return Err(CoapOscoreError::Replay);
+        }
+
+        let nonce = nonce(&self.common_iv, kid, option.piv);
+        let mut aad_buffer = [0; 40];
+        let aad = aad(kid, option.piv, &mut aad_buffer)?;
+
+        let message = open(&self.recipient_key, &nonce, aad, request, buffer)?;
+        self.replay_window.insert(sequence_number);
+
+        let binding = RequestBinding {
+            kid: self.recipient_id,
+            piv: InlineBytes::new(option.piv).ok_or(CoapOscoreError::InvalidOption)?,
+            notification: None,
+        };
+
+        Ok((message, binding))
+    }
+
+    /// Protect a response to the request identified by `request` into `buffer`. Its code is
+    /// encrypted, and the outer code is 2.04 (Changed), or 2.05 (Content) for an Observe
+    /// notification.
+    ///
+    /// Notifications use a new Partial IV; other responses reuse the nonce of the request.
+    ///
+    /// Source: [RFC 8613 8.3](https://datatracker.ietf.org/doc/html/rfc8613#section-8.3)
+    pub fn protect_response<'b>(
+        &mut self,
+        response: &Message<'_>,
+        request: &RequestBinding,
+        buffer: &'b mut [u8],
+    ) -> OscoreResult<&'b [u8]> {
+        if !response.is_response() {
+            return Err(CoapOscoreError::InvalidMessage);
+        }
+
+        let observe = has_observe(response);
+        let piv = match observe {
+            true => Some(self.next_partial_iv()?),
+            false => None,
+        };
+
+        let mut option = [0; 1 + MAX_PIV_LEN];
+        let option = OscoreOption {
+            piv: piv.as_ref().map_or(&[], InlineBytes::as_slice),
+            kid_context: None,
+            kid: None,
+        }
+        .encode(&mut option)?;
+
+        let nonce = match &piv {
+            Some(piv) => nonce(&self.common_iv, self.sender_id(), piv.as_slice()),
+            None => nonce(&self.common_iv, request.kid(), request.partial_iv()),
+        };
+        let mut aad_buffer = [0; 40];
+        let aad = aad(request.kid(), request.partial_iv(), &mut aad_buffer)?;
+
+        let outer_code = match observe {
+            true => ResponseCode::Content,
+            false => ResponseCode::Changed,
+        };
+
+        seal(
+            &self.sender_key,
+            &nonce,
+            aad,
+            response,
+            outer_code.into(),
+            option,
+            buffer,
+        )
+    }
+
+    /// Verify and decrypt a protected response to the request identified by `request` into
+    /// `buffer`, returning the original response.
+    ///
+    /// Notifications must arrive with increasing Partial IVs; a notification that is not newer
+    /// than the last one verified for `request` is rejected as a replay.
+    ///
+    /// Source: [RFC 8613 8.4](https://datatracker.ietf.org/doc/html/rfc8613#section-8.4)
+    pub fn unprotect_response<'b>(
+        &self,
+        response: &Message<'_>,
+        request: &mut RequestBinding,
+        buffer: &'b mut [u8],
+    ) -> OscoreResult<Message<'b>> {
+        let option = oscore_option(response)?;
+
+        let notification = match option.piv {
+            [] => None,
+            piv => Some(sequence_number(piv)),
+        };
+        if notification.is_some() && notification <= request.notification {
+            return Err(CoapOscoreError::Replay);
+        }
+
+        let nonce = match option.piv {
+            [] => nonce(&self.common_iv, request.kid(), request.partial_iv()),
+            piv => nonce(&self.common_iv, self.recipient_id(), piv),
+        };
+        let mut aad_buffer = [0; 40];
+        let aad = aad(request.kid(), request.partial_iv(), &mut aad_buffer)?;
+
+        let message = open(&self.recipient_key, &nonce, aad, response, buffer)?;
+        if notification.is_some() {
+            request.notification = notification;
+        }
+
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MessageType;
+
+    fn hex<'a>(s: &str, buffer: &'a mut [u8]) -> &'a [u8] {
+        let len = s.len() / 2;
+        for (i, byte) in buffer[..len].iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&s[2 * i..2 * i + 2], 16).unwrap();
+        }
+        &buffer[..len]
+    }
+
+    // Test vectors from RFC 8613 Appendix C.1.1 and C.4 to C.7.
+    const MASTER_SECRET: [u8; 16] = [
+        0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
+        0x10,
+    ];
+    const MASTER_SALT: [u8; 8] = [0x9e, 0x7c, 0xa9, 0x22, 0x23, 0x78, 0x63, 0x40];
+    const REQUEST: &str = "44015d1f00003974396c6f63616c686f737483747631";
+    const PROTECTED_REQUEST: &str =
+        "44025d1f00003974396c6f63616c686f7374620914ff612f1092f1776f1c1668b3825e";
+    const RESPONSE: &str = "64455d1f00003974ff48656c6c6f20576f726c6421";
+    const PROTECTED_RESPONSE: &str =
+        "64445d1f0000397490ffdbaad1e9a7e7b2a813d3c31524378303cdafae119106";
+
+    fn client() -> SecurityContext {
+        SecurityContext::new(&MASTER_SECRET, &MASTER_SALT, &[], &[0x01], None).unwrap()
+    }
+
+    fn server() -> SecurityContext {
+        SecurityContext::new(&MASTER_SECRET, &MASTER_SALT, &[0x01], &[], None).unwrap()
+    }
+
+    #[test]
+    fn derive_context() {
+        let context = client();
+        let mut expected = [0; 16];
+        assert_eq!(
+            &context.sender_key[..],
+            hex("f0910ed7295e6ad4b54fc793154302ff", &mut expected)
+        );
+        assert_eq!(
+            &context.recipient_key[..],
+            hex("ffb14e093c94c9cac9471648b4f98710", &mut expected)
+        );
+        assert_eq!(
+            &context.common_iv[..],
+            hex("4622d4dd6d944168eefb54987c", &mut expected)
+        );
+
+        assert_eq!(
+            SecurityContext::new(&MASTER_SECRET, &[], &[0; 8], &[], None).unwrap_err(),
+            CoapOscoreError::InvalidId
+        );
+    }
+
+    #[test]
+    fn protect_request_and_response() {
+        let mut client = client();
+        client.set_sender_sequence_number(20);
+        let mut server = server();
+
+        let mut buf = [0; 64];
+        let request = Message::parse(hex(REQUEST, &mut buf)).unwrap();
+        let mut protected = [0; 64];
+        let (protected, mut binding) = client.protect_request(&request, &mut protected).unwrap();
+        let mut expected = [0; 64];
+        assert_eq!(protected, hex(PROTECTED_REQUEST, &mut expected));
+        assert_eq!(binding.partial_iv(), &[0x14]);
+        assert_eq!(client.sender_sequence_number(), 21);
+
+        let mut unprotected = [0; 64];
+        let (unprotected, server_binding) = server
+            .unprotect_request(&Message::parse(protected).unwrap(), &mut unprotected)
+            .unwrap();
+        assert_eq!(unprotected, request);
+        assert_eq!(server_binding, binding);
+
+        let mut buf = [0; 64];
+        let response = Message::parse(hex(RESPONSE, &mut buf)).unwrap();
+        let mut protected = [0; 64];
+        let protected = server
+            .protect_response(&response, &server_binding, &mut protected)
+            .unwrap();
+        let mut expected = [0; 64];
+        assert_eq!(protected, hex(PROTECTED_RESPONSE, &mut expected));
+
+        let mut unprotected = [0; 64];
+        let unprotected = client
+            .unprotect_response(
+                &Message::parse(protected).unwrap(),
+                &mut binding,
+                &mut unprotected,
+            )
+            .unwrap();
+        assert_eq!(unprotected, response);
+    }
+
+    #[test]
+    fn reject_replayed_and_tampered_requests() {
+        let mut client = client();
+        let mut server = server();
+
+        let mut buf = [0; 64];
+        let request = Message::parse(hex(REQUEST, &mut buf)).unwrap();
+        let mut first = [0; 64];
+        let (first, _) = client.protect_request(&request, &mut first).unwrap();
+        let mut second = [0; 64];
+        let (second, _) = client.protect_request(&request, &mut second).unwrap();
+
+        let mut out = [0; 64];
+        assert!(
+            server
+                .unprotect_request(&Message::parse(second).unwrap(), &mut out)
+                .is_ok()
+        );
+        // An older request is accepted once, within the window.
+        assert!(
+            server
+                .unprotect_request(&Message::parse(first).unwrap(), &mut out)
+                .is_ok()
+        );
+        assert_eq!(
+            server
+                .unprotect_request(&Message::parse(first).unwrap(), &mut out)
+                .unwrap_err(),
+            CoapOscoreError::Replay
+        );
+
+        let mut tampered = [0; 64];
+        tampered[..second.len()].copy_from_slice(second);
+        tampered[second.len() - 1] ^= 1;
+        let tampered = Message::parse(&tampered[..second.len()]).unwrap();
+        let mut server = self::server();
+        let error = server.unprotect_request(&tampered, &mut out).unwrap_err();
+        assert_eq!(error, CoapOscoreError::DecryptionFailed);
+        assert_eq!(error.response_code(), ResponseCode::BadRequest);
+
+        // A request from another client.
+        let mut other =
+            SecurityContext::new(&MASTER_SECRET, &MASTER_SALT, &[0x02], &[0x01], None).unwrap();
+        let mut buf = [0; 64];
+        let (protected, _) = other.protect_request(&request, &mut buf).unwrap();
+        assert_eq!(
+            server
+                .unprotect_request(&Message::parse(protected).unwrap(), &mut out)
+                .unwrap_err(),
+            CoapOscoreError::UnknownKid
+        );
+    }
+
+    #[test]
+    fn protect_observe_notifications() {
+        let mut client = client();
+        let mut server = server();
+
+        let mut buf = [0; 64];
+        let request = MessageBuilder::new(&mut buf)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(1)
+            .token(&[0xAA])
+            .unwrap()
+            .observe_register()
+            .unwrap()
+            .option_string(OptionNumber::UriPath, "temp")
+            .unwrap()
+            .no_payload()
+            .build();
+        let request = Message::parse(request).unwrap();
+
+        let mut protected = [0; 64];
+        let (protected, mut binding) = client.protect_request(&request, &mut protected).unwrap();
+        let outer = Message::parse(protected).unwrap();
+        assert_eq!(outer.code, u8::from(RequestCode::Fetch));
+        assert_eq!(outer.observe(), Some(0));
+
+        let mut unprotected = [0; 64];
+        let (_, binding_at_server) = server.unprotect_request(&outer, &mut unprotected).unwrap();
+
+        let mut buf = [0; 64];
+        let notification = MessageBuilder::new(&mut buf)
+            .unwrap()
+            .response(MessageType::NonConfirmable, ResponseCode::Content)
+            .message_id(2)
+            .token(&[0xAA])
+            .unwrap()
+            .observe_sequence(7)
+            .unwrap()
+            .payload(b"22.5")
+            .unwrap()
+            .build();
+        let notification = Message::parse(notification).unwrap();
+
+        let mut protected = [0; 64];
+        let protected = server
+            .protect_response(&notification, &binding_at_server, &mut protected)
+            .unwrap();
+        let outer = Message::parse(protected).unwrap();
+        assert_eq!(outer.code, u8::from(ResponseCode::Content));
+        assert_eq!(outer.observe(), Some(7));
+
+        let mut unprotected = [0; 64];
+        let unprotected = client
+            .unprotect_response(&outer, &mut binding, &mut unprotected)
+            .unwrap();
+        assert_eq!(unprotected, notification);
+
+        // The same notification is rejected when it is replayed.
+        let mut out = [0; 64];
+        assert_eq!(
+            client
+                .unprotect_response(&outer, &mut binding, &mut out)
+                .unwrap_err(),
+            CoapOscoreError::Replay
+        );
+
+        let mut newer = [0; 64];
+        let newer = server
+            .protect_response(&notification, &binding_at_server, &mut newer)
+            .unwrap();
+        let mut out = [0; 64];
+        assert!(
+            client
+                .unprotect_response(&Message::parse(newer).unwrap(), &mut binding, &mut out)
+                .is_ok()
+        );
+        let mut out = [0; 64];
+        assert_eq!(
+            client
+                .unprotect_response(&outer, &mut binding, &mut out)
+                .unwrap_err(),
+            CoapOscoreError::Replay
+        );
+    }
+
+    #[test]
+    fn option_encoding() {
+        let mut buffer = [0; 32];
+        let option = OscoreOption {
+            piv: &[0x05],
+            kid_context: Some(&[0x37, 0xcb]),
+            kid: Some(&[0x01]),
+        };
+        let encoded = option.encode(&mut buffer).unwrap();
+        assert_eq!(encoded, &[0x19, 0x05, 0x02, 0x37, 0xcb, 0x01]);
+        assert_eq!(OscoreOption::parse(encoded), Ok(option));
+
+        let empty = OscoreOption {
+            piv: &[],
+            kid_context: None,
+            kid: None,
+        };
+        assert_eq!(empty.encode(&mut buffer), Ok(&[][..]));
+        assert_eq!(OscoreOption::parse(&[]), Ok(empty));
+
+        assert_eq!(
+            OscoreOption::parse(&[0x06, 0, 0, 0, 0, 0, 0]),
+            Err(CoapOscoreError::InvalidOption)
+        );
+        assert_eq!(
+            OscoreOption::parse(&[0x01, 0x05, 0x01]),
+            Err(CoapOscoreError::InvalidOption)
+        );
+        assert_eq!(partial_iv(0).as_slice(), &[0x00]);
+        assert_eq!(partial_iv(0x1234).as_slice(), &[0x12, 0x34]);
+    }
+
+    #[test]
+    fn replay_window_large_jumps() {
+        let mut window = ReplayWindow::default();
+        window.insert(0);
+        window.insert(1);
+
+        // A jump of 2^32 must clear the window rather than keep the old bitmap.
+        window.insert(1 + (1 << 32));
+        assert!(!window.accepts(1 + (1 << 32)));
+        assert!(window.accepts(1 << 32));
+        window.insert(1 << 32);
+        assert!(!window.accepts(1 << 32));
+    }
+}
@@ -0,0 +1,237 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::error::{CoapBuildError, CoapParseError};
+use crate::{Message, MessageBuilder, MessageType, OptionNumber};
+
+/// An option of an [`OwnedMessage`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct OwnedOption {
+    /// Option number
+    pub number: OptionNumber,
+    /// Option value
+    pub value: Vec<u8>,
+}
+
+/// A CoAP message that owns its token, options and payload, so that it can outlive the buffer it
+/// was received in.
+///
+/// Convert a [`Message`] with [`From`], and encode the message back with
+/// [`OwnedMessage::encode`] or [`OwnedMessage::to_vec`], which can be parsed with
+/// [`Message::parse`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct OwnedMessage {
+    /// Message type (Confirmable, Non-confirmable, Acknowledgement, Reset)
+    pub message_type: MessageType,
+    /// Message code (method for requests, response code for responses)
+    pub code: u8,
+    /// Message ID for duplicate detection and message correlation
+    pub message_id: u16,
+    /// Token bytes for request/response matching
+    pub token: Vec<u8>,
+    /// Options of the message, ordered by option number
+    pub options: Vec<OwnedOption>,
+    /// Payload of the message, empty if there is none
+    pub payload: Vec<u8>,
+}
+
+impl OwnedMessage {
+    /// Create a message without a token, options or payload.
+    pub fn new(message_type: MessageType, code: impl Into<u8>, message_id: u16) -> Self {
+        OwnedMessage {
+            message_type,
+            code: code.into(),
+            message_id,
+            token: Vec::new(),
+            options: Vec::new(),
+            payload: Vec::new(),
+        }
+    }
+
+    /// Parse a CoAP message from a byte buffer into an owned message.
+    pub fn parse(buffer: &[u8]) -> Result<Self, CoapParseError> {
+        Message::parse(buffer).map(|message| OwnedMessage::from(&message))
+    }
+
+    /// Add an option after any options with the same or a lower number, keeping the options
+    /// ordered.
+    pub fn add_option(&mut self, number: impl Into<u16>, value: impl Into<Vec<u8>>) {
+        let number = number.into();
+        let index = self
+            .options
+            .partition_point(|option| u16::from(option.number) <= number);
+
+        self.options.insert(
+            index,
+            OwnedOption {
+                number: OptionNumber::from(number),
+                value: value.into(),
+            },
+        );
+    }
+
+    /// Returns the value of the first option with the given number.
+    pub fn option(&self, number: OptionNumber) -> Option<&[u8]> {
+        self.options_with(number).next()
+    }
+
+    /// Returns the values of every option with the given number, in order.
+    pub fn options_with(&self, number: OptionNumber) -> impl Iterator<Item = &[u8]> {
+        self.options
+            .iter()
+            .filter(move |option| option.number == number)
+            .map(|option| option.value.as_slice())
+    }
+
+    /// Remove every option with the given number.
+    pub fn remove_option(&mut self, number: OptionNumber) {
+        self.options.retain(|option| option.number != number);
+    }
+
+    /// Encode the message into `buffer`.
+    ///
+    /// Fails with [`CoapBuildError::OptionNumberOutOfOrder`] if [`OwnedMessage::options`] was
+    /// modified directly and is no longer ordered by option number.
+    pub fn encode<'buf>(&self, buffer: &'buf mut [u8]) -> Result<&'buf [u8], CoapBuildError> {
+        let mut builder = MessageBuilder::new(buffer)?
+            .header(self.message_type, self.code)
+            .message_id(self.message_id)
+            .token(&self.token)?;
+
+        for option in &self.options {
+            builder = builder.option(option.number, &option.value)?;
+        }
+
+        let builder = if self.payload.is_empty() {
+            builder.no_payload()
+        } else {
+            builder.payload(&self.payload)?
+        };
+
+        Ok(builder.build())
+    }
+
+    /// Encode the message into a newly allocated vector.
+    pub fn to_vec(&self) -> Result<Vec<u8>, CoapBuildError> {
+        // An option header takes at most 5 bytes.
+        let capacity = 4
+            + self.token.len()
+            + self
+                .options
+                .iter()
+                .map(|option| 5 + option.value.len())
+                .sum::<usize>()
+            + 1
+            + self.payload.len();
+
+        let mut buffer = vec![0; capacity];
+        let len = self.encode(&mut buffer)?.len();
+        buffer.truncate(len);
+
+        Ok(buffer)
+    }
+}
+
+impl From<&Message<'_>> for OwnedMessage {
+    fn from(message: &Message<'_>) -> Self {
+        OwnedMessage {
+            message_type: message.message_type,
+            code: message.code,
+            message_id: message.message_id,
+            token: message.token.to_vec(),
+            options: message
+                .options
+                .into_iter()
+                .map(|option| OwnedOption {
+                    number: option.number,
+                    value: option.value.to_vec(),
+                })
+                .collect(),
+            payload: message.payload.unwrap_or_default().to_vec(),
+        }
+    }
+}
+
+impl From<Message<'_>> for OwnedMessage {
+    fn from(message: Message<'_>) -> Self {
+        OwnedMessage::from(&message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RequestCode, ResponseCode};
+
+    #[test]
+    fn round_trip_through_message() {
+        let mut buffer = [0; 64];
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Post)
+            .message_id(0x1234)
+            .token(&[1, 2, 3])
+            .unwrap()
+            .option_string(OptionNumber::UriPath, "sensors")
+            .unwrap()
+            .option_string(OptionNumber::UriPath, "temp")
+            .unwrap()
+            .option_uint(OptionNumber::ContentFormat, 0u8)
+            .unwrap()
+            .payload(b"22.5")
+            .unwrap()
+            .build();
+
+        let owned = OwnedMessage::parse(packet).unwrap();
+        assert_eq!(owned.token, [1, 2, 3]);
+        assert_eq!(owned.options.len(), 3);
+        assert_eq!(
+            owned
+                .options_with(OptionNumber::UriPath)
+                .collect::<Vec<_>>(),
+            [&b"sensors"[..], b"temp"]
+        );
+        assert_eq!(owned.payload, b"22.5");
+
+        assert_eq!(owned.to_vec().unwrap(), packet);
+        let mut small = [0; 8];
+        assert_eq!(
+            owned.encode(&mut small),
+            Err(CoapBuildError::BufferTooSmall)
+        );
+    }
+
+    #[test]
+    fn add_options_in_any_order() {
+        let mut owned = OwnedMessage::new(MessageType::Acknowledgement, ResponseCode::Content, 7);
+        owned.add_option(OptionNumber::ContentFormat, [40]);
+        owned.add_option(OptionNumber::Etag, [1]);
+        owned.add_option(OptionNumber::Etag, [2]);
+        owned.add_option(OptionNumber::MaxAge, []);
+
+        let bytes = owned.to_vec().unwrap();
+        let message = Message::parse(&bytes).unwrap();
+        let numbers = message
+            .options
+            .into_iter()
+            .map(|option| option.number)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            numbers,
+            [
+                OptionNumber::Etag,
+                OptionNumber::Etag,
+                OptionNumber::ContentFormat,
+                OptionNumber::MaxAge
+            ]
+        );
+        assert_eq!(owned.option(OptionNumber::Etag), Some(&[1][..]));
+        assert_eq!(message.payload, None);
+
+        owned.remove_option(OptionNumber::Etag);
+        assert_eq!(owned.options.len(), 2);
+        assert_eq!(OwnedMessage::from(message).message_id, 7);
+    }
+}
@@ -1,4 +1,8 @@
+use core::time::Duration;
+
+use crate::block::BlockValue;
 use crate::error::CoapParseError;
+use crate::observe::{self, SEQUENCE_MASK};
 use crate::{MessageType, OptionNumber, Version};
 
 type ParseResult<T> = core::result::Result<T, CoapParseError>;
@@ -60,81 +64,13 @@ impl<'a> Message<'a> {
 
         let token = &buffer[4..4 + token_len];
 
-        let mut offset = 4 + token_len;
+        let offset = 4 + token_len;
 
         if code == 0 && buffer.len() > offset {
             return Err(CoapParseError::EmptyMessageWithData);
         }
 
-        let options_start = offset;
-        let mut options_end = offset;
-        let mut payload_start = None;
-
-        while offset < buffer.len() {
-            if buffer[offset] == 0xFF {
-                payload_start = Some(offset + 1);
-                options_end = offset;
-                break;
-            }
-
-            let delta = (buffer[offset] >> 4) & 0x0F;
-            let length = buffer[offset] & 0x0F;
-
-            if delta == 15 {
-                return Err(CoapParseError::InvalidOptionDelta);
-            }
-
-            offset += 1;
-
-            let delta_ext_len = match delta {
-                13 => 1,
-                14 => 2,
-                _ => 0,
-            };
-
-            let length_ext_len = match length {
-                13 => 1,
-                14 => 2,
-                15 => return Err(CoapParseError::InvalidOptionLength),
-                _ => 0,
-            };
-
-            if offset + delta_ext_len + length_ext_len > buffer.len() {
-                return Err(CoapParseError::MessageTooShort);
-            }
-
-            offset += delta_ext_len;
-
-            let value_len = match length {
-                0..=12 => length as usize,
-                13 => buffer[offset] as usize + 13,
-                14 => u16::from_be_bytes([buffer[offset], buffer[offset + 1]]) as usize + 269,
-                _ => return Err(CoapParseError::InvalidOptionLength),
-            };
-
-            offset += length_ext_len;
-
-            if offset + value_len > buffer.len() {
-                return Err(CoapParseError::MessageTooShort);
-            }
-
-            offset += value_len;
-            options_end = offset;
-        }
-
-        let options = CoapOptions {
-            data: &buffer[options_start..options_end],
-        };
-
-        let payload = payload_start
-            .map(|start| {
-                if start >= buffer.len() {
-                    return Err(CoapParseError::PayloadMarkerWithoutPayload);
-                }
-
-                Ok(&buffer[start..])
-            })
-            .transpose()?;
+        let (options, payload) = parse_options_and_payload(buffer, offset)?;
 
         Ok(Message {
             version,
@@ -171,6 +107,116 @@ impl<'a> Message<'a> {
     pub fn is_empty(&self) -> bool {
         self.code == 0
     }
+
+    /// Returns the value of the Observe option, if present.
+    ///
+    /// In a GET request this is 0 (register) or 1 (deregister); in a notification it is the
+    /// 24-bit sequence number. Values longer than 3 bytes are rejected.
+    ///
+    /// Source: [RFC 7641 2](https://datatracker.ietf.org/doc/html/rfc7641#section-2)
+    pub fn observe(&self) -> Option<u32> {
+        self.options
+            .into_iter()
+            .find(|opt| opt.number == OptionNumber::Observe)
+            .filter(|opt| opt.value.len() <= 3)
+            .and_then(|opt| opt.as_uint())
+            .map(|value| value as u32 & SEQUENCE_MASK)
+    }
+
+    /// Check if this notification is fresher than the last notification seen for the same
+    /// observation, which carried the sequence number `last_sequence` and arrived `since_last`
+    /// before this one.
+    ///
+    /// Returns `None` if this message does not carry an Observe option.
+    ///
+    /// Source: [RFC 7641 3.4](https://datatracker.ietf.org/doc/html/rfc7641#section-3.4)
+    pub fn is_fresher_notification(
+        &self,
+        last_sequence: u32,
+        since_last: Duration,
+    ) -> Option<bool> {
+        self.observe()
+            .map(|sequence| observe::is_fresher(last_sequence, sequence, since_last))
+    }
+}
+
+/// Parse the options and payload of a message, which start at `offset` and extend to the end of
+/// the buffer. Shared between all message framings.
+pub(crate) fn parse_options_and_payload(
+    buffer: &[u8],
+    mut offset: usize,
+) -> ParseResult<(CoapOptions<'_>, Option<&[u8]>)> {
+    let options_start = offset;
+    let mut options_end = offset;
+    let mut payload_start = None;
+
+    while offset < buffer.len() {
+        if buffer[offset] == 0xFF {
+            payload_start = Some(offset + 1);
+            options_end = offset;
+            break;
+        }
+
+        let delta = (buffer[offset] >> 4) & 0x0F;
+        let length = buffer[offset] & 0x0F;
+
+        if delta == 15 {
+            return Err(CoapParseError::InvalidOptionDelta);
+        }
+
+        offset += 1;
+
+        let delta_ext_len = match delta {
+            13 => 1,
+            14 => 2,
+            _ => 0,
+        };
+
+        let length_ext_len = match length {
+            13 => 1,
+            14 => 2,
+            15 => return Err(CoapParseError::InvalidOptionLength),
+            _ => 0,
+        };
+
+        if offset + delta_ext_len + length_ext_len > buffer.len() {
+            return Err(CoapParseError::MessageTooShort);
+        }
+
+        offset += delta_ext_len;
+
+        let value_len = match length {
+            0..=12 => length as usize,
+            13 => buffer[offset] as usize + 13,
+            14 => u16::from_be_bytes([buffer[offset], buffer[offset + 1]]) as usize + 269,
+            _ => return Err(CoapParseError::InvalidOptionLength),
+        };
+
+        offset += length_ext_len;
+
+        if offset + value_len > buffer.len() {
+            return Err(CoapParseError::MessageTooShort);
+        }
+
+        offset += value_len;
+        options_end = offset;
+    }
+
+    let options = CoapOptions {
+        data: &buffer[options_start..options_end],
+    };
+
+    let payload = payload_start
+        .map(|start| {
+            if start >= buffer.len() {
+                return Err(CoapParseError::PayloadMarkerWithoutPayload);
+            }
+
+            Ok(&buffer[start..])
+        })
+        .transpose()?;
+
+    Ok((options, payload))
 }
 
 /// Collection of CoAP options
@@ -274,6 +320,24 @@ impl<'a> CoapOption<'a> {
         }
     }
 
+    /// Interpret the option value as a Block1/Block2 option value.
+    ///
+    /// The size exponent 7 is reserved outside of BERT and rejected; use
+    /// [`as_bert_block`](CoapOption::as_bert_block) on reliable transports.
+    ///
+    /// Source: [RFC 7959 2.2](https://datatracker.ietf.org/doc/html/rfc7959#section-2.2)
+    pub fn as_block(&self) -> Result<BlockValue, CoapParseError> {
+        BlockValue::decode(self.value, false)
+    }
+
+    /// Interpret the option value as a Block1/Block2 option value, accepting the BERT size
+    /// exponent 7.
+    ///
+    /// Source: [RFC 8323 6](https://datatracker.ietf.org/doc/html/rfc8323#section-6)
+    pub fn as_bert_block(&self) -> Result<BlockValue, CoapParseError> {
+        BlockValue::decode(self.value, true)
+    }
+
     /// Interpret the option value as a UTF-8 string
     pub fn as_str(&self) -> Result<&'a str, core::str::Utf8Error> {
         core::str::from_utf8(self.value)
@@ -744,4 +808,109 @@ mod tests {
         let cf: ContentFormat = (content_format_opt.as_uint().unwrap() as u16).into();
         assert_eq!(cf, ContentFormat::Unknown(99));
     }
+
+    #[test]
+    fn parse_observe_notification() {
+        let mut buffer = [0; 128];
+
+        use crate::ResponseCode;
+
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .response(MessageType::NonConfirmable, ResponseCode::Content)
+            .message_id(0x0001)
+            .token(&[0xAA])
+            .unwrap()
+            .observe_sequence(42)
+            .unwrap()
+            .payload(b"22.5")
+            .unwrap()
+            .build();
+
+        let message = Message::parse(packet).unwrap();
+
+        assert_eq!(message.observe(), Some(42));
+        assert_eq!(
+            message.is_fresher_notification(41, Duration::from_secs(1)),
+            Some(true)
+        );
+        assert_eq!(
+            message.is_fresher_notification(43, Duration::from_secs(1)),
+            Some(false)
+        );
+        assert_eq!(
+            message.is_fresher_notification(43, Duration::from_secs(200)),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn parse_observe_register() {
+        let mut buffer = [0; 128];
+
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(0x0001)
+            .token(&[0xAA])
+            .unwrap()
+            .observe_register()
+            .unwrap()
+            .option(OptionNumber::UriPath, b"temp")
+            .unwrap()
+            .no_payload()
+            .build();
+
+        let message = Message::parse(packet).unwrap();
+
+        assert_eq!(message.observe(), Some(0));
+        assert_eq!(
+            message.options.into_iter().next().unwrap().number,
+            OptionNumber::Observe
+        );
+    }
+
+    #[test]
+    fn parse_without_observe() {
+        let buffer = [0x40, 0x01, 0x00, 0x00];
+        let message = Message::parse(&buffer).unwrap();
+
+        assert_eq!(message.observe(), None);
+        assert_eq!(message.is_fresher_notification(0, Duration::ZERO), None);
+    }
+
+    #[test]
+    fn parse_option_as_block() {
+        let mut buffer = [0; 128];
+
+        use crate::{BlockSize, BlockValue};
+
+        let block = BlockValue {
+            num: 3,
+            more: true,
+            size: BlockSize::S64,
+        };
+
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Put)
+            .message_id(0x0001)
+            .no_token()
+            .unwrap()
+            .option_block(OptionNumber::Block1, block)
+            .unwrap()
+            .payload(&[0; 64])
+            .unwrap()
+            .build();
+
+        let message = Message::parse(packet).unwrap();
+        let block1 = message
+            .options
+            .into_iter()
+            .find(|opt| opt.number == OptionNumber::Block1)
+            .unwrap();
+
+        assert_eq!(block1.value, &[0x3A]);
+        assert_eq!(block1.as_block(), Ok(block));
+    }
 }
@@ -0,0 +1,269 @@
+use crate::cbor::{self, Decoder, Encoder, Item};
+use crate::error::{CoapBuildError, CoapProblemError};
+use crate::{Complete, ContentFormat, MessageBuilder, NeedsPayload, OptionNumber};
+
+const TITLE: i64 = -1;
+const DETAIL: i64 = -2;
+const INSTANCE: i64 = -3;
+const RESPONSE_CODE: i64 = -4;
+const BASE_URI: i64 = -5;
+
+/// Concise problem details describing why a request failed, carried in the payload of an error
+/// response with the `application/concise-problem-details+cbor` content format.
+///
+/// Only the standard problem detail entries are represented. Custom entries and the
+/// `base-lang` and `base-rtl` entries are skipped when decoding.
+///
+/// Source: [RFC 9290 2](https://datatracker.ietf.org/doc/html/rfc9290#section-2)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ProblemDetails<'a> {
+    /// Short, human-readable summary of the problem shape
+    pub title: Option<&'a str>,
+    /// Human-readable explanation specific to this occurrence of the problem
+    pub detail: Option<&'a str>,
+    /// URI reference identifying this occurrence of the problem
+    pub instance: Option<&'a str>,
+    /// CoAP response code the problem details were generated for
+    pub response_code: Option<u8>,
+    /// Base URI for resolving relative URI references in the problem details
+    pub base_uri: Option<&'a str>,
+}
+
+impl<'a> ProblemDetails<'a> {
+    /// Create problem details without any entries.
+    pub const fn new() -> Self {
+        ProblemDetails {
+            title: None,
+            detail: None,
+            instance: None,
+            response_code: None,
+            base_uri: None,
+        }
+    }
+
+    fn text_entries(&self) -> [(i64, Option<&'a str>); 4] {
+        [
+            (TITLE, self.title),
+            (DETAIL, self.detail),
+            (INSTANCE, self.instance),
+            (BASE_URI, self.base_uri),
+        ]
+    }
+
+    fn entry_count(&self) -> usize {
+        let texts = self.text_entries();
+        texts.iter().filter(|(_, value)| value.is_some()).count()
+            + self.response_code.is_some() as usize
+    }
+
+    /// Returns the length of the encoded problem details.
+    pub fn encoded_len(&self) -> usize {
+        let texts = self
+            .text_entries()
+            .into_iter()
+            .filter_map(|(key, value)| Some(cbor::int_len(key) + cbor::string_len(value?.len())))
+            .sum::<usize>();
+        let response_code = self.response_code.map_or(0, |code| {
+            cbor::int_len(RESPONSE_CODE) + cbor::int_len(code as i64)
+        });
+
+        cbor::head_len(self.entry_count() as u64) + texts + response_code
+    }
+
+    /// Encode the problem details as a CBOR map into `buffer`.
+    ///
+    /// Source: [RFC 9290 2](https://datatracker.ietf.org/doc/html/rfc9290#section-2)
+    pub fn encode<'b>(&self, buffer: &'b mut [u8]) -> Result<&'b [u8], CoapProblemError> {
+        let mut encoder = Encoder::new(buffer);
+        encoder.head(cbor::MAP, self.entry_count() as u64)?;
+
+        // Entries are written in the deterministic order of their encoded keys.
+        let [title, detail, instance, base_uri] = self.text_entries();
+        for (key, value) in [title, detail, instance] {
+            if let Some(value) = value {
+                encoder.int(key)?;
+                encoder.text(value)?;
+            }
+        }
+        if let Some(code) = self.response_code {
+            encoder.int(RESPONSE_CODE)?;
+            encoder.int(code as i64)?;
+        }
+        if let (key, Some(value)) = base_uri {
+            encoder.int(key)?;
+            encoder.text(value)?;
+        }
+
+        Ok(encoder.finish())
+    }
+
+    /// Decode problem details from a CBOR map, skipping entries other than the standard ones.
+    ///
+    /// Source: [RFC 9290 2](https://datatracker.ietf.org/doc/html/rfc9290#section-2)
+    pub fn decode(data: &'a [u8]) -> Result<Self, CoapProblemError> {
+        let mut decoder = Decoder::new(data);
+        let Item::Map(len) = decoder.item()? else {
+            return Err(CoapProblemError::UnexpectedType);
+        };
+
+        let mut details = ProblemDetails::new();
+
+        for _ in 0..len {
+            let key = decoder.item()?;
+
+            let slot = match key.as_int() {
+                Some(TITLE) => &mut details.title,
+                Some(DETAIL) => &mut details.detail,
+                Some(INSTANCE) => &mut details.instance,
+                Some(BASE_URI) => &mut details.base_uri,
+                Some(RESPONSE_CODE) => {
+                    let code = match decoder.item()? {
+                        Item::Unsigned(code) => u8::try_from(code).ok(),
+                        _ => None,
+                    };
+                    details.response_code = Some(code.ok_or(CoapProblemError::UnexpectedType)?);
+                    continue;
+                }
+                _ => {
+                    decoder.skip_contents(key)?;
+                    decoder.skip()?;
+                    continue;
+                }
+            };
+
+            let Item::Text(value) = decoder.item()? else {
+                return Err(CoapProblemError::UnexpectedType);
+            };
+            *slot = Some(value);
+        }
+
+        if !decoder.is_finished() {
+            return Err(CoapProblemError::InvalidCbor);
+        }
+
+        Ok(details)
+    }
+}
+
+impl<'buf> MessageBuilder<'buf, NeedsPayload> {
+    /// Add a Content-Format option for concise problem details, and the encoded problem details
+    /// as the payload. Only options numbered up to 12 (Content-Format) may be added beforehand.
+    ///
+    /// Source: [RFC 9290 6.3](https://datatracker.ietf.org/doc/html/rfc9290#section-6.3)
+    pub fn problem_details(
+        self,
+        details: &ProblemDetails<'_>,
+    ) -> Result<MessageBuilder<'buf, Complete>, CoapBuildError> {
+        let len = details.encoded_len();
+        let mut encoded = Ok(());
+        let builder = self
+            .option_uint(
+                OptionNumber::ContentFormat,
+                u16::from(ContentFormat::ApplicationConciseProblemDetailsCbor),
+            )?
+            .payload_with(len, |dst| {
+                // The payload is sized to fit the encoding exactly.
+                encoded = match details.encode(dst) {
+                    Ok(bytes) if bytes.len() == len => Ok(()),
+                    _ => Err(CoapBuildError::BufferTooSmall),
+                };
+            })?;
+        encoded?;
+
+        Ok(builder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Message, MessageType, ResponseCode};
+
+    #[test]
+    fn encode_and_decode() {
+        let details = ProblemDetails {
+            title: Some("Unknown sensor"),
+            detail: Some("No sensor with ID 7"),
+            response_code: Some(ResponseCode::NotFound.into()),
+            base_uri: Some("coap://example.com"),
+            ..ProblemDetails::new()
+        };
+
+        let mut buffer = [0; 96];
+        let encoded = details.encode(&mut buffer).unwrap();
+        assert_eq!(encoded.len(), details.encoded_len());
+        assert_eq!(&encoded[..4], &[0xA4, 0x20, 0x6E, b'U']);
+        assert_eq!(ProblemDetails::decode(encoded), Ok(details));
+
+        assert_eq!(ProblemDetails::new().encode(&mut buffer), Ok(&[0xA0][..]));
+
+        let mut buffer = [0; 8];
+        assert_eq!(
+            details.encode(&mut buffer),
+            Err(CoapProblemError::BufferTooSmall)
+        );
+    }
+
+    #[test]
+    fn decode_skips_custom_entries() {
+        // {-2: "d", 4711: {"x": [1, 2]}, -6: 38(["en"]), "ext": h'00', [1]: 2, -4: 160}
+        let data = [
+            0xA6, 0x21, 0x61, b'd', 0x19, 0x12, 0x67, 0xA1, 0x61, b'x', 0x82, 0x01, 0x02, 0x25,
+            0xD8, 0x26, 0x81, 0x62, b'e', b'n', 0x63, b'e', b'x', b't', 0x41, 0x00, 0x81, 0x01,
+            0x02, 0x23, 0x18, 0xA0,
+        ];
+        let details = ProblemDetails::decode(&data).unwrap();
+        assert_eq!(details.detail, Some("d"));
+        assert_eq!(details.response_code, Some(0xA0));
+        assert_eq!(details.title, None);
+
+        assert_eq!(
+            ProblemDetails::decode(&[0x80]),
+            Err(CoapProblemError::UnexpectedType)
+        );
+        assert_eq!(
+            ProblemDetails::decode(&[0xA1, 0x20, 0x01]),
+            Err(CoapProblemError::UnexpectedType)
+        );
+        assert_eq!(
+            ProblemDetails::decode(&[0xA1, 0x20]),
+            Err(CoapProblemError::InvalidCbor)
+        );
+        assert_eq!(
+            ProblemDetails::decode(&[0xA0, 0x00]),
+            Err(CoapProblemError::InvalidCbor)
+        );
+    }
+
+    #[test]
+    fn error_response_with_problem_details() -> Result<(), CoapBuildError> {
+        let details = ProblemDetails {
+            title: Some("Sensor offline"),
+            response_code: Some(ResponseCode::ServiceUnavailable.into()),
+            ..ProblemDetails::new()
+        };
+
+        let mut buffer = [0; 64];
+        let packet = MessageBuilder::new(&mut buffer)?
+            .response(
+                MessageType::Acknowledgement,
+                ResponseCode::ServiceUnavailable,
+            )
+            .message_id(0x1234)
+            .token(&[1])?
+            .problem_details(&details)?
+            .build();
+
+        let message = Message::parse(packet).unwrap();
+        let option = message.options.into_iter().next().unwrap();
+        assert_eq!(option.number, OptionNumber::ContentFormat);
+        assert_eq!(option.as_uint(), Some(257));
+        assert_eq!(
+            ProblemDetails::decode(message.payload.unwrap()),
+            Ok(details)
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,945 @@
+use crate::error::{CoapBuildError, CoapProxyError};
+use crate::observe::OBSERVE_REGISTER;
+use crate::{
+    CoapUri, Message, MessageBuilder, MessageType, NeedsHeader, NeedsPayload, OptionNumber,
+    ResponseCode, UriScheme,
+};
+
+type BuilderResult<'buf, T> = core::result::Result<MessageBuilder<'buf, T>, CoapBuildError>;
+
+/// Options a forward-proxy replaces with those of the target URI.
+const FORWARD_URI_OPTIONS: [OptionNumber; 4] = [
+    OptionNumber::UriHost,
+    OptionNumber::UriPort,
+    OptionNumber::UriPath,
+    OptionNumber::UriQuery,
+];
+
+/// Options a reverse-proxy replaces with those of the origin server.
+const REVERSE_URI_OPTIONS: [OptionNumber; 2] = [OptionNumber::UriHost, OptionNumber::UriPort];
+
+/// Returns whether an option may be forwarded by a proxy that does not recognize it.
+///
+/// Source: [RFC 7252 5.7.1](https://datatracker.ietf.org/doc/html/rfc7252#section-5.7.1)
+fn is_forwardable(number: OptionNumber) -> bool {
+    !number.is_unsafe() || number.definition().is_some()
+}
+
+impl<'a> Message<'a> {
+    /// Returns whether the request carries a Proxy-Uri or Proxy-Scheme option, asking a
+    /// forward-proxy to perform it on its behalf.
+    ///
+    /// Source: [RFC 7252 5.10.2](https://datatracker.ietf.org/doc/html/rfc7252#section-5.10.2)
+    pub fn is_proxy_request(&self) -> bool {
+        self.options.into_iter().any(|opt| {
+            opt.number == OptionNumber::ProxyUri || opt.number == OptionNumber::ProxyScheme
+        })
+    }
+
+    /// Resolve the URI a forward-proxy should perform the request on into `buffer`, from its
+    /// Proxy-Uri option, or its Proxy-Scheme and Uri-* options. Returns `None` for a request
+    /// without either, which targets the proxy itself.
+    ///
+    /// `destination` is the IP literal of the address the request was received on, used as the
+    /// host when the request has Proxy-Scheme but no Uri-Host option.
+    ///
+    /// Source: [RFC 7252 5.10.2](https://datatracker.ietf.org/doc/html/rfc7252#section-5.10.2)
+    pub fn proxy_target<'b>(
+        &self,
+        destination: &str,
+        buffer: &'b mut [u8],
+    ) -> Result<Option<CoapUri<'b>>, CoapProxyError> {
+        if !self.is_proxy_request() {
+            return Ok(None);
+        }
+
+        let uri = self.write_uri(UriScheme::Coap, destination, buffer)?;
+        Ok(Some(CoapUri::parse(uri)?))
+    }
+}
+
+/// Rewrite `request` into a request to the origin server with the given token and message ID.
+///
+/// The Proxy-Uri and Proxy-Scheme options and the `rewritten` options of the request are
+/// replaced by those of `target`, and the Hop-Limit is decremented. Unrecognized unsafe options
+/// are dropped if elective, and reject the request if critical.
+fn write_origin_request<'buf>(
+    request: &Message<'_>,
+    target: &CoapUri<'_>,
+    rewritten: &[OptionNumber],
+    token: &[u8],
+    message_id: u16,
+    buffer: &'buf mut [u8],
+) -> Result<&'buf [u8], CoapProxyError> {
+    let hop_limit = request
+        .forwarded_hop_limit()
+        .ok_or(CoapProxyError::HopLimitReached)?;
+
+    let mut builder = MessageBuilder::new(buffer)?
+        .header(request.message_type, request.code)
+        .message_id(message_id)
+        .token(token)?;
+
+    let mut pending = rewritten
+        .iter()
+        .copied()
+        .chain([OptionNumber::HopLimit])
+        .peekable();
+    let write_pending = |builder: MessageBuilder<'buf, NeedsPayload>, number| match number {
+        OptionNumber::HopLimit => builder.hop_limit(hop_limit).map_err(CoapProxyError::from),
+        _ => target
+            .write_option(number, builder)
+            .map_err(CoapProxyError::from),
+    };
+
+    for option in request.options {
+        let replaced = matches!(
+            option.number,
+            OptionNumber::ProxyUri | OptionNumber::ProxyScheme | OptionNumber::HopLimit
+        ) || rewritten.contains(&option.number);
+        if replaced {
+            continue;
+        }
+
+        if !is_forwardable(option.number) {
+            if option.is_critical() {
+                return Err(CoapProxyError::BadOption(option.number.into()));
+            }
+            continue;
+        }
+
+        let number = u16::from(option.number);
+        while let Some(next) = pending.next_if(|&next| u16::from(next) <= number) {
+            builder = write_pending(builder, next)?;
+        }
+        builder = builder.option(option.number, option.value)?;
+    }
+
+    for next in pending {
+        builder = write_pending(builder, next)?;
+    }
+
+    let complete = match request.payload {
+        Some(payload) => builder.payload(payload)?,
+        None => builder.no_payload(),
+    };
+
+    Ok(complete.build())
+}
+
+/// Identifies a request forwarded to an origin server, by the server it is sent to and the token
+/// and message ID the proxy chose for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct OriginRequest<'a, P> {
+    /// The origin server.
+    pub server: P,
+    /// The token of the request, which must not be empty or already used by another request
+    /// forwarded to the same server.
+    pub token: &'a [u8],
+    /// The message ID of the request.
+    pub message_id: u16,
+}
+
+/// A request forwarded to an origin server, awaiting its response.
+struct Forwarded<P> {
+    client: P,
+    client_token: [u8; 8],
+    client_token_len: u8,
+    client_message_id: u16,
+    origin: P,
+    origin_token: [u8; 8],
+    origin_token_len: u8,
+    origin_message_id: u16,
+    piggyback: bool,
+    observe: bool,
+}
+
+impl<P> Forwarded<P> {
+    fn client_token(&self) -> &[u8] {
+        &self.client_token[..self.client_token_len as usize]
+    }
+
+    fn origin_token(&self) -> &[u8] {
+        &self.origin_token[..self.origin_token_len as usize]
+    }
+
+    /// Start the reply to the client: piggybacked on the Acknowledgement of a Confirmable request
+    /// the first time, and Non-confirmable with `message_id` otherwise.
+    fn reply<'buf>(
+        &mut self,
+        builder: MessageBuilder<'buf, NeedsHeader>,
+        code: impl Into<u8>,
+        message_id: u16,
+    ) -> BuilderResult<'buf, NeedsPayload> {
+        let (msg_type, message_id) = match core::mem::take(&mut self.piggyback) {
+            true => (MessageType::Acknowledgement, self.client_message_id),
+            false => (MessageType::NonConfirmable, message_id),
+        };
+
+        builder
+            .header(msg_type, code)
+            .message_id(message_id)
+            .token(self.client_token())
+    }
+}
+
+/// CoAP-to-CoAP proxy relaying up to `N` requests between clients and origin servers, both
+/// identified by `P`.
+///
+/// A forward-proxy performs requests carrying Proxy-Uri or Proxy-Scheme on the URI they name,
+/// resolved with [`Message::proxy_target`], while a reverse-proxy performs every request on a
+/// fixed origin server. Either way, the request is rewritten with a token and message ID chosen
+/// by the proxy, and the responses of the origin server are relayed back with the token of the
+/// client. Requests registering an observation are kept open so every notification is relayed.
+///
+/// Sending the rewritten requests, as well as acknowledging and retransmitting messages, is left
+/// to an [`Endpoint`](crate::Endpoint).
+///
+/// Source: [RFC 7252 5.7](https://datatracker.ietf.org/doc/html/rfc7252#section-5.7)
+pub struct Proxy<P, const N: usize> {
+    exchanges: [Option<Forwarded<P>>; N],
+}
+
+impl<P, const N: usize> Default for Proxy<P, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P, const N: usize> Proxy<P, N> {
+    /// Create a proxy without forwarded requests.
+    pub const fn new() -> Self {
+        Self {
+            exchanges: [const { None }; N],
+        }
+    }
+
+    /// Returns the number of forwarded requests awaiting a response.
+    pub fn len(&self) -> usize {
+        self.exchanges.iter().flatten().count()
+    }
+
+    /// Check if no forwarded requests are awaiting a response.
+    pub fn is_empty(&self) -> bool {
+        self.exchanges.iter().all(Option::is_none)
+    }
+}
+
+impl<P: PartialEq, const N: usize> Proxy<P, N> {
+    /// Rewrite a request carrying Proxy-Uri or Proxy-Scheme from `client` into the request
+    /// `origin` for `target`, as returned by [`Message::proxy_target`]. The returned datagram
+    /// must be sent to the origin server, which `target` names.
+    ///
+    /// On error, the request is not forwarded and should be answered with the
+    /// [response code](CoapProxyError::response_code) of the error.
+    ///
+    /// Source: [RFC 7252 5.7.2](https://datatracker.ietf.org/doc/html/rfc7252#section-5.7.2)
+    pub fn forward<'buf>(
+        &mut self,
+        client: P,
+        request: &Message<'_>,
+        target: &CoapUri<'_>,
+        origin: OriginRequest<'_, P>,
+        buffer: &'buf mut [u8],
+    ) -> Result<&'buf [u8], CoapProxyError> {
+        let slot = self.free_slot(&origin)?;
+        let datagram = write_origin_request(
+            request,
+            target,
+            &FORWARD_URI_OPTIONS,
+            origin.token,
+            origin.message_id,
+            buffer,
+        )?;
+        self.track(slot, client, request, origin);
+
+        Ok(datagram)
+    }
+
+    /// Rewrite a request from `client` into the request `origin` for the origin server at `uri`.
+    /// The Uri-Host and Uri-Port options are replaced by those of `uri`, while the path and query
+    /// of the request are kept.
+    ///
+    /// A request carrying Proxy-Uri or Proxy-Scheme is rejected with
+    /// [`CoapProxyError::NotSupported`].
+    ///
+    /// Source: [RFC 7252 5.7.3](https://datatracker.ietf.org/doc/html/rfc7252#section-5.7.3)
+    pub fn reverse<'buf>(
+        &mut self,
+        client: P,
+        request: &Message<'_>,
+        uri: &CoapUri<'_>,
+        origin: OriginRequest<'_, P>,
+        buffer: &'buf mut [u8],
+    ) -> Result<&'buf [u8], CoapProxyError> {
+        if request.is_proxy_request() {
+            return Err(CoapProxyError::NotSupported);
+        }
+
+        let slot = self.free_slot(&origin)?;
+        let datagram = write_origin_request(
+            request,
+            uri,
+            &REVERSE_URI_OPTIONS,
+            origin.token,
+            origin.message_id,
+            buffer,
+        )?;
+        self.track(slot, client, request, origin);
+
+        Ok(datagram)
+    }
+
+    fn position(&self, predicate: impl Fn(&Forwarded<P>) -> bool) -> Option<usize> {
+        self.exchanges
+            .iter()
+            .position(|slot| slot.as_ref().is_some_and(&predicate))
+    }
+
+    fn free_slot(&self, origin: &OriginRequest<'_, P>) -> Result<usize, CoapProxyError> {
+        let in_use = self
+            .position(|f| f.origin == origin.server && f.origin_token() == origin.token)
+            .is_some();
+        if origin.token.is_empty() || in_use {
+            return Err(CoapProxyError::InvalidToken);
+        }
+
+        self.exchanges
+            .iter()
+            .position(Option::is_none)
+            .ok_or(CoapProxyError::TooManyExchanges)
+    }
+
+    fn track(
+        &mut self,
+        slot: usize,
+        client: P,
+        request: &Message<'_>,
+        origin: OriginRequest<'_, P>,
+    ) {
+        let mut client_token = [0; 8];
+        client_token[..request.token.len()].copy_from_slice(request.token);
+        let mut origin_token = [0; 8];
+        origin_token[..origin.token.len()].copy_from_slice(origin.token);
+
+        self.exchanges[slot] = Some(Forwarded {
+            client,
+            client_token,
+            client_token_len: request.token.len() as u8,
+            client_message_id: request.message_id,
+            origin: origin.server,
+            origin_token,
+            origin_token_len: origin.token.len() as u8,
+            origin_message_id: origin.message_id,
+            piggyback: request.message_type == MessageType::Confirmable,
+            observe: request.observe() == Some(OBSERVE_REGISTER),
+        });
+    }
+
+    /// Stop relaying responses to the request forwarded to `origin` with the given token, e.g.
+    /// when the client cancels an observation. Returns `true` if a request was removed.
+    pub fn remove(&mut self, origin: &P, token: &[u8]) -> bool {
+        self.position(|f| &f.origin == origin && f.origin_token() == token)
+            .and_then(|index| self.exchanges[index].take())
+            .is_some()
+    }
+}
+
+impl<P: PartialEq + Clone, const N: usize> Proxy<P, N> {
+    /// Relay a response from the origin server `origin` to the client of the forwarded request
+    /// it answers, using `message_id` if the response cannot be piggybacked. Returns the client
+    /// and the datagram to send it, or `None` if the message is not a response to any forwarded
+    /// request. Empty messages and requests are never relayed.
+    ///
+    /// Unrecognized elective options that are unsafe to forward are dropped. A response carrying
+    /// an unrecognized critical option, or a message with a reserved code, is answered with
+    /// 5.02 (Bad Gateway) instead.
+    ///
+    /// Source: [RFC 7252 5.7.1](https://datatracker.ietf.org/doc/html/rfc7252#section-5.7.1)
+    pub fn relay<'buf>(
+        &mut self,
+        origin: &P,
+        response: &Message<'_>,
+        message_id: u16,
+        buffer: &'buf mut [u8],
+    ) -> Result<Option<(P, &'buf [u8])>, CoapBuildError> {
+        if response.is_empty() || response.is_request() {
+            return Ok(None);
+        }
+
+        let Some(index) =
+            self.position(|f| &f.origin == origin && f.origin_token() == response.token)
+        else {
+            return Ok(None);
+        };
+        let slot = &mut self.exchanges[index];
+        let Some(forwarded) = slot.as_mut() else {
+            return Ok(None);
+        };
+
+        let valid = response.is_response()
+            && response
+                .first_unrecognized_critical(is_forwardable)
+                .is_none();
+
+        let builder = MessageBuilder::new(buffer)?;
+        let datagram = if valid {
+            let mut builder = forwarded.reply(builder, response.code, message_id)?;
+            for option in response.options {
+                if is_forwardable(option.number) {
+                    builder = builder.option(option.number, option.value)?;
+                }
+            }
+
+            match response.payload {
+                Some(payload) => builder.payload(payload)?,
+                None => builder.no_payload(),
+            }
+        } else {
+            forwarded
+                .reply(builder, ResponseCode::BadGateway, message_id)?
+                .no_payload()
+        };
+
+        let client = forwarded.client.clone();
+        if !(valid && forwarded.observe && response.observe().is_some()) {
+            *slot = None;
+        }
+
+        Ok(Some((client, datagram.build())))
+    }
+
+    /// Give up on the request forwarded to `origin` with message ID `request_id`, answering its
+    /// client with `code` using `message_id` if the answer cannot be piggybacked: 5.02 (Bad
+    /// Gateway) if the origin server rejected the request with a Reset, or 5.04 (Gateway Timeout)
+    /// if it did not answer in time. Returns `None` if no such request was forwarded.
+    ///
+    /// Source: [RFC 7252 5.7.1](https://datatracker.ietf.org/doc/html/rfc7252#section-5.7.1)
+    pub fn fail<'buf>(
+        &mut self,
+        origin: &P,
+        request_id: u16,
+        code: ResponseCode,
+        message_id: u16,
+        buffer: &'buf mut [u8],
+    ) -> Result<Option<(P, &'buf [u8])>, CoapBuildError> {
+        let Some(mut forwarded) = self
+            .position(|f| &f.origin == origin && f.origin_message_id == request_id)
+            .and_then(|index| self.exchanges[index].take())
+        else {
+            return Ok(None);
+        };
+
+        let datagram = forwarded
+            .reply(MessageBuilder::new(buffer)?, code, message_id)?
+            .no_payload()
+            .build();
+
+        Ok(Some((forwarded.client, datagram)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RequestCode;
+
+    fn options<'a>(message: &Message<'a>) -> impl Iterator<Item = (u16, &'a [u8])> {
+        message
+            .options
+            .into_iter()
+            .map(|opt| (u16::from(opt.number), opt.value))
+    }
+
+    #[test]
+    fn forward_proxy_uri() -> Result<(), CoapProxyError> {
+        let mut rx_buf = [0; 96];
+        let packet = MessageBuilder::new(&mut rx_buf)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(0x1234)
+            .token(&[0xC1])
+            .unwrap()
+            .option(OptionNumber::Etag, b"v1")
+            .unwrap()
+            .option(
+                OptionNumber::ProxyUri,
+                b"coap://Sensor.example:5684/temp?unit=c",
+            )
+            .unwrap()
+            .no_payload()
+            .build();
+        let req = Message::parse(packet).unwrap();
+
+        let mut uri_buf = [0; 64];
+        let target = req.proxy_target("192.0.2.1", &mut uri_buf)?.unwrap();
+        assert_eq!(target.host, "Sensor.example");
+
+        let mut proxy = Proxy::<u8, 2>::new();
+        let mut tx_buf = [0; 96];
+        let packet = proxy.forward(
+            1,
+            &req,
+            &target,
+            OriginRequest {
+                server: 10,
+                token: &[0x0F, 0x01],
+                message_id: 0x0A0A,
+            },
+            &mut tx_buf,
+        )?;
+        assert_eq!(proxy.len(), 1);
+
+        let origin = Message::parse(packet).unwrap();
+        assert_eq!(origin.message_type, MessageType::Confirmable);
+        assert_eq!(origin.code, u8::from(RequestCode::Get));
+        assert_eq!(origin.message_id, 0x0A0A);
+        assert_eq!(origin.token, &[0x0F, 0x01]);
+        assert!(options(&origin).eq([
+            (3, &b"sensor.example"[..]),
+            (4, b"v1"),
+            (7, &[0x16, 0x34]),
+            (11, b"temp"),
+            (15, b"unit=c"),
+            (16, &[16]),
+        ]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn forward_proxy_scheme_and_unsafe_options() -> Result<(), CoapProxyError> {
+        let mut rx_buf = [0; 64];
+        let packet = MessageBuilder::new(&mut rx_buf)
+            .unwrap()
+            .request(MessageType::NonConfirmable, RequestCode::Get)
+            .message_id(0x1234)
+            .token(&[0xC1])
+            .unwrap()
+            .option(OptionNumber::UriHost, b"origin.example")
+            .unwrap()
+            .option(OptionNumber::UriPath, b"temp")
+            .unwrap()
+            .option(OptionNumber::HopLimit, &[4])
+            .unwrap()
+            .option(OptionNumber::ProxyScheme, b"coap")
+            .unwrap()
+            // Elective and unsafe, dropped.
+            .option(OptionNumber::UnknownOption(2050), b"x")
+            .unwrap()
+            // Elective and safe to forward, kept.
+            .option(OptionNumber::UnknownOption(2052), b"y")
+            .unwrap()
+            .no_payload()
+            .build();
+        let req = Message::parse(packet).unwrap();
+
+        let mut uri_buf = [0; 64];
+        let target = req.proxy_target("192.0.2.1", &mut uri_buf)?.unwrap();
+
+        let mut proxy = Proxy::<u8, 1>::new();
+        let mut tx_buf = [0; 64];
+        let packet = proxy.forward(
+            1,
+            &req,
+            &target,
+            OriginRequest {
+                server: 10,
+                token: &[0x0F],
+                message_id: 7,
+            },
+            &mut tx_buf,
+        )?;
+        let origin = Message::parse(packet).unwrap();
+        assert_eq!(origin.message_type, MessageType::NonConfirmable);
+        assert!(options(&origin).eq([
+            (3, &b"origin.example"[..]),
+            (11, b"temp"),
+            (16, &[3]),
+            (2052, b"y"),
+        ]));
+
+        // Critical and unsafe options reject the request.
+        let mut rx_buf = [0; 64];
+        let packet = MessageBuilder::new(&mut rx_buf)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(0x1234)
+            .token(&[0xC1])
+            .unwrap()
+            .option(OptionNumber::ProxyUri, b"coap://origin.example/")
+            .unwrap()
+            .option(OptionNumber::UnknownOption(2051), b"x")
+            .unwrap()
+            .no_payload()
+            .build();
+        let req = Message::parse(packet).unwrap();
+        let mut uri_buf = [0; 64];
+        let target = req.proxy_target("192.0.2.1", &mut uri_buf)?.unwrap();
+        let mut proxy = Proxy::<u8, 1>::new();
+        let err = proxy.forward(
+            1,
+            &req,
+            &target,
+            OriginRequest {
+                server: 10,
+                token: &[0x0F],
+                message_id: 7,
+            },
+            &mut tx_buf,
+        );
+        assert_eq!(err, Err(CoapProxyError::BadOption(2051)));
+        assert_eq!(err.unwrap_err().response_code(), ResponseCode::BadOption);
+        assert!(proxy.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn reject_unsupported_requests() {
+        let mut rx_buf = [0; 64];
+        let packet = MessageBuilder::new(&mut rx_buf)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(0x1234)
+            .token(&[0xC1])
+            .unwrap()
+            .option(OptionNumber::ProxyUri, b"http://origin.example/")
+            .unwrap()
+            .no_payload()
+            .build();
+        let req = Message::parse(packet).unwrap();
+        let mut uri_buf = [0; 64];
+        let err = req.proxy_target("192.0.2.1", &mut uri_buf).unwrap_err();
+        assert_eq!(err.response_code(), ResponseCode::ProxyingNotSupported);
+
+        let origin = CoapUri::parse("coap://origin.example/").unwrap();
+        let mut proxy = Proxy::<u8, 1>::new();
+        let mut tx_buf = [0; 64];
+        let err = proxy
+            .reverse(
+                1,
+                &req,
+                &origin,
+                OriginRequest {
+                    server: 10,
+                    token: &[0x0F],
+                    message_id: 7,
+                },
+                &mut tx_buf,
+            )
+            .unwrap_err();
+        assert_eq!(err, CoapProxyError::NotSupported);
+        assert_eq!(err.response_code(), ResponseCode::ProxyingNotSupported);
+
+        let mut rx_buf = [0; 64];
+        let packet = MessageBuilder::new(&mut rx_buf)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(0x1234)
+            .token(&[0xC1])
+            .unwrap()
+            .option(OptionNumber::UriPath, b"temp")
+            .unwrap()
+            .no_payload()
+            .build();
+        let req = Message::parse(packet).unwrap();
+        let mut uri_buf = [0; 64];
+        assert_eq!(req.proxy_target("192.0.2.1", &mut uri_buf), Ok(None));
+    }
+
+    #[test]
+    fn reverse_proxy_and_relay() -> Result<(), CoapProxyError> {
+        let mut rx_buf = [0; 64];
+        let packet = MessageBuilder::new(&mut rx_buf)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(0x1234)
+            .token(&[0xC1])
+            .unwrap()
+            .option(OptionNumber::UriHost, b"proxy.example")
+            .unwrap()
+            .option(OptionNumber::UriPath, b"temp")
+            .unwrap()
+            .no_payload()
+            .build();
+        let req = Message::parse(packet).unwrap();
+
+        let origin = CoapUri::parse("coap://[2001:db8::1]:5684/ignored").unwrap();
+        let mut proxy = Proxy::<u8, 2>::new();
+        let mut tx_buf = [0; 64];
+        let packet = proxy.reverse(
+            1,
+            &req,
+            &origin,
+            OriginRequest {
+                server: 10,
+                token: &[0x0F],
+                message_id: 7,
+            },
+            &mut tx_buf,
+        )?;
+        let forwarded = Message::parse(packet).unwrap();
+        assert!(options(&forwarded).eq([(7, &[0x16, 0x34][..]), (11, b"temp"), (16, &[16])]));
+
+        let mut proxy_full = Proxy::<u8, 0>::new();
+        let err = proxy_full.reverse(
+            1,
+            &req,
+            &origin,
+            OriginRequest {
+                server: 10,
+                token: &[0x0F],
+                message_id: 7,
+            },
+            &mut tx_buf,
+        );
+        assert_eq!(err, Err(CoapProxyError::TooManyExchanges));
+
+        // Origin tokens must be non-empty and unique per origin server.
+        let err = proxy.reverse(
+            2,
+            &req,
+            &origin,
+            OriginRequest {
+                server: 10,
+                token: &[0x0F],
+                message_id: 8,
+            },
+            &mut tx_buf,
+        );
+        assert_eq!(err, Err(CoapProxyError::InvalidToken));
+        let err = proxy.reverse(
+            2,
+            &req,
+            &origin,
+            OriginRequest {
+                server: 10,
+                token: &[],
+                message_id: 8,
+            },
+            &mut tx_buf,
+        );
+        assert_eq!(err, Err(CoapProxyError::InvalidToken));
+        assert_eq!(proxy.len(), 1);
+
+        // Responses to other requests or from other servers are not relayed.
+        let mut rx_buf = [0; 64];
+        let packet = MessageBuilder::new(&mut rx_buf)
+            .unwrap()
+            .response(MessageType::Acknowledgement, ResponseCode::Content)
+            .message_id(0x0A0A)
+            .token(&[0x0E])
+            .unwrap()
+            .payload(b"22.5")
+            .unwrap()
+            .build();
+        let unrelated = Message::parse(packet).unwrap();
+        assert_eq!(proxy.relay(&10, &unrelated, 9, &mut tx_buf)?, None);
+        let mut rx_buf = [0; 64];
+        let packet = MessageBuilder::new(&mut rx_buf)
+            .unwrap()
+            .response(MessageType::Acknowledgement, ResponseCode::Content)
+            .message_id(0x0A0A)
+            .token(&[0x0F])
+            .unwrap()
+            .payload(b"22.5")
+            .unwrap()
+            .build();
+        let unrelated = Message::parse(packet).unwrap();
+        assert_eq!(proxy.relay(&11, &unrelated, 9, &mut tx_buf)?, None);
+
+        // Neither are empty messages or requests carrying the token.
+        let mut rx_buf = [0; 64];
+        let empty = MessageBuilder::new(&mut rx_buf)?
+            .header(MessageType::Acknowledgement, 0)
+            .message_id(7)
+            .token(&[0x0F])?
+            .no_payload()
+            .build();
+        let empty = Message::parse(empty).unwrap();
+        assert_eq!(proxy.relay(&10, &empty, 9, &mut tx_buf)?, None);
+        let mut rx_buf = [0; 64];
+        let loopback = MessageBuilder::new(&mut rx_buf)?
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(8)
+            .token(&[0x0F])?
+            .no_payload()
+            .build();
+        let loopback = Message::parse(loopback).unwrap();
+        assert_eq!(proxy.relay(&10, &loopback, 9, &mut tx_buf)?, None);
+        assert_eq!(proxy.len(), 1);
+
+        let mut rx_buf = [0; 64];
+        let packet = MessageBuilder::new(&mut rx_buf)
+            .unwrap()
+            .response(MessageType::Acknowledgement, ResponseCode::Content)
+            .message_id(0x0A0A)
+            .token(&[0x0F])
+            .unwrap()
+            .option(OptionNumber::MaxAge, &[30])
+            .unwrap()
+            .option(OptionNumber::UnknownOption(2050), b"x")
+            .unwrap()
+            .payload(b"22.5")
+            .unwrap()
+            .build();
+        let res = Message::parse(packet).unwrap();
+        let (client, packet) = proxy.relay(&10, &res, 9, &mut tx_buf)?.unwrap();
+        assert_eq!(client, 1);
+        assert!(proxy.is_empty());
+
+        let relayed = Message::parse(packet).unwrap();
+        assert_eq!(relayed.message_type, MessageType::Acknowledgement);
+        assert_eq!(relayed.message_id, 0x1234);
+        assert_eq!(relayed.token, &[0xC1]);
+        assert_eq!(relayed.code, u8::from(ResponseCode::Content));
+        assert!(options(&relayed).eq([(14, &[30][..])]));
+        assert_eq!(relayed.payload, Some(&b"22.5"[..]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn bad_gateway_and_timeout() -> Result<(), CoapProxyError> {
+        let origin = CoapUri::parse("coap://origin.example/").unwrap();
+        let mut proxy = Proxy::<u8, 2>::new();
+
+        let mut rx_buf = [0; 64];
+        let packet = MessageBuilder::new(&mut rx_buf)
+            .unwrap()
+            .request(MessageType::NonConfirmable, RequestCode::Get)
+            .message_id(0x1234)
+            .token(&[0xC1])
+            .unwrap()
+            .no_payload()
+            .build();
+        let req = Message::parse(packet).unwrap();
+        let mut tx_buf = [0; 64];
+        proxy.reverse(
+            1,
+            &req,
+            &origin,
+            OriginRequest {
+                server: 10,
+                token: &[0x0F],
+                message_id: 7,
+            },
+            &mut tx_buf,
+        )?;
+        proxy.reverse(
+            2,
+            &req,
+            &origin,
+            OriginRequest {
+                server: 10,
+                token: &[0x0E],
+                message_id: 8,
+            },
+            &mut tx_buf,
+        )?;
+
+        // A response with an unrecognized critical option is answered with 5.02.
+        let mut rx_buf = [0; 64];
+        let packet = MessageBuilder::new(&mut rx_buf)
+            .unwrap()
+            .response(MessageType::Acknowledgement, ResponseCode::Content)
+            .message_id(0x0A0A)
+            .token(&[0x0F])
+            .unwrap()
+            .option(OptionNumber::UnknownOption(2051), b"x")
+            .unwrap()
+            .payload(b"22.5")
+            .unwrap()
+            .build();
+        let res = Message::parse(packet).unwrap();
+        let (client, packet) = proxy.relay(&10, &res, 9, &mut tx_buf)?.unwrap();
+        assert_eq!(client, 1);
+        let relayed = Message::parse(packet).unwrap();
+        assert_eq!(relayed.message_type, MessageType::NonConfirmable);
+        assert_eq!(relayed.message_id, 9);
+        assert_eq!(relayed.code, u8::from(ResponseCode::BadGateway));
+        assert_eq!(relayed.payload, None);
+
+        assert_eq!(
+            proxy.fail(&10, 7, ResponseCode::GatewayTimeout, 10, &mut tx_buf)?,
+            None
+        );
+        assert_eq!(
+            proxy.fail(&11, 8, ResponseCode::GatewayTimeout, 10, &mut tx_buf)?,
+            None
+        );
+        let (client, packet) = proxy
+            .fail(&10, 8, ResponseCode::GatewayTimeout, 10, &mut tx_buf)?
+            .unwrap();
+        assert_eq!(client, 2);
+        let relayed = Message::parse(packet).unwrap();
+        assert_eq!(relayed.code, u8::from(ResponseCode::GatewayTimeout));
+        assert_eq!(relayed.token, &[0xC1]);
+        assert!(proxy.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn relay_notifications() -> Result<(), CoapProxyError> {
+        let origin = CoapUri::parse("coap://origin.example/").unwrap();
+        let mut proxy = Proxy::<u8, 1>::new();
+
+        let mut rx_buf = [0; 64];
+        let packet = MessageBuilder::new(&mut rx_buf)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(0x1234)
+            .token(&[0xC1])
+            .unwrap()
+            .option(OptionNumber::Observe, &[])
+            .unwrap()
+            .no_payload()
+            .build();
+        let req = Message::parse(packet).unwrap();
+        let mut tx_buf = [0; 64];
+        proxy.reverse(
+            1,
+            &req,
+            &origin,
+            OriginRequest {
+                server: 10,
+                token: &[0x0F],
+                message_id: 7,
+            },
+            &mut tx_buf,
+        )?;
+
+        let mut rx_buf = [0; 64];
+        let packet = MessageBuilder::new(&mut rx_buf)
+            .unwrap()
+            .response(MessageType::Acknowledgement, ResponseCode::Content)
+            .message_id(0x0A0A)
+            .token(&[0x0F])
+            .unwrap()
+            .option(OptionNumber::Observe, &[1])
+            .unwrap()
+            .payload(b"22.5")
+            .unwrap()
+            .build();
+        let res = Message::parse(packet).unwrap();
+        let (_, packet) = proxy.relay(&10, &res, 9, &mut tx_buf)?.unwrap();
+        let relayed = Message::parse(packet).unwrap();
+        assert_eq!(relayed.message_type, MessageType::Acknowledgement);
+        assert_eq!(relayed.observe(), Some(1));
+
+        let (_, packet) = proxy.relay(&10, &res, 10, &mut tx_buf)?.unwrap();
+        let relayed = Message::parse(packet).unwrap();
+        assert_eq!(relayed.message_type, MessageType::NonConfirmable);
+        assert_eq!(relayed.message_id, 10);
+        assert_eq!(proxy.len(), 1);
+
+        assert!(!proxy.remove(&11, &[0x0F]));
+        assert!(proxy.remove(&10, &[0x0F]));
+        assert!(proxy.is_empty());
+
+        Ok(())
+    }
+}
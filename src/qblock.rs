@@ -0,0 +1,514 @@
+use core::ops::Range;
+
+use crate::block::MAX_BLOCK_NUMBER;
+use crate::cbor::{self, Decoder, Encoder, Item};
+use crate::error::{CoapBlockError, CoapBuildError, CoapParseError};
+use crate::{
+    BlockSize, BlockStatus, BlockValue, BlockwiseSender, Complete, ContentFormat, Message,
+    MessageBuilder, NeedsPayload, OptionNumber, ResponseCode,
+};
+
+/// Number of blocks sent in a burst before waiting for the peer to catch up.
+///
+/// Source: [RFC 9177 7.2](https://datatracker.ietf.org/doc/html/rfc9177#section-7.2)
+pub const DEFAULT_MAX_PAYLOADS: u32 = 10;
+
+/// Sends a body in bursts of blocks for a Q-Block1 or Q-Block2 transfer, without waiting for
+/// each block to be acknowledged.
+///
+/// Each burst of blocks is sent as Non-confirmable messages. The sender then waits for a 2.31
+/// (Continue) response to a Q-Block1 transfer, or for NON_TIMEOUT, before sending the next
+/// burst. Blocks the peer reports as [missing](MissingBlocks) are sent again with
+/// [`QBlockSender::write_block`].
+///
+/// Source: [RFC 9177 4](https://datatracker.ietf.org/doc/html/rfc9177#section-4)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct QBlockSender<'a> {
+    blocks: BlockwiseSender<'a>,
+    max_payloads: u32,
+    next: u32,
+}
+
+impl<'a> QBlockSender<'a> {
+    /// Create a sender for `body` with blocks of `size`, sending at most `max_payloads` blocks per
+    /// burst (usually [`DEFAULT_MAX_PAYLOADS`]).
+    pub fn new(body: &'a [u8], size: BlockSize, max_payloads: u32) -> Self {
+        Self {
+            blocks: BlockwiseSender::new(body, size),
+            max_payloads: max_payloads.max(1),
+            next: 0,
+        }
+    }
+
+    /// Returns the number of blocks in the body.
+    pub fn block_count(&self) -> u32 {
+        self.blocks.block_count()
+    }
+
+    /// Returns whether every block was sent at least once.
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.block_count()
+    }
+
+    /// Returns the numbers of the blocks to send in the next burst, which is empty once every
+    /// block was sent.
+    pub fn next_burst(&mut self) -> Range<u32> {
+        let start = self.next;
+        self.next = start
+            .saturating_add(self.max_payloads)
+            .min(self.block_count());
+
+        start..self.next
+    }
+
+    /// Add the block with number `num` to a message, together with the matching Size1/Size2
+    /// option on the first block.
+    ///
+    /// `option_number` must be [`OptionNumber::QBlock1`] or [`OptionNumber::QBlock2`], and only
+    /// options numbered below 19 (Q-Block1) or 28 (Size2) may be added to the builder beforehand.
+    pub fn write_block<'buf>(
+        &self,
+        builder: MessageBuilder<'buf, NeedsPayload>,
+        option_number: OptionNumber,
+        num: u32,
+    ) -> Result<MessageBuilder<'buf, Complete>, CoapBlockError> {
+        self.blocks.write_block(builder, option_number, num)
+    }
+}
+
+/// The block numbers listed in the payload of a 4.08 (Request Entity Incomplete) response to a
+/// Q-Block1 transfer, in the order they were listed.
+///
+/// Source: [RFC 9177 5](https://datatracker.ietf.org/doc/html/rfc9177#section-5)
+#[derive(Debug, Clone)]
+pub struct MissingBlocks<'a> {
+    decoder: Decoder<'a>,
+}
+
+impl<'a> MissingBlocks<'a> {
+    /// Decode a CBOR sequence of block numbers.
+    pub fn decode(data: &'a [u8]) -> Result<Self, CoapBlockError> {
+        let mut decoder = Decoder::new(data);
+
+        while !decoder.is_finished() {
+            match decoder.item() {
+                Ok(Item::Unsigned(num)) if num <= MAX_BLOCK_NUMBER as u64 => {}
+                _ => return Err(CoapBlockError::InvalidMissingBlocks),
+            }
+        }
+
+        Ok(MissingBlocks {
+            decoder: Decoder::new(data),
+        })
+    }
+
+    /// Decode the missing blocks listed by a 4.08 (Request Entity Incomplete) response with the
+    /// `application/missing-blocks+cbor-seq` content format.
+    pub fn from_response(response: &Message<'a>) -> Result<Self, CoapBlockError> {
+        let content_format = response
+            .options
+            .into_iter()
+            .find(|opt| opt.number == OptionNumber::ContentFormat)
+            .and_then(|opt| opt.as_uint());
+
+        let expected = u16::from(ContentFormat::ApplicationMissingBlocksCborSeq) as u64;
+        if response.code != u8::from(ResponseCode::RequestEntityIncomplete)
+            || content_format != Some(expected)
+        {
+            return Err(CoapBlockError::InvalidMissingBlocks);
+        }
+
+        Self::decode(response.payload.unwrap_or_default())
+    }
+}
+
+impl Iterator for MissingBlocks<'_> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Every item was checked to be a block number when decoding.
+        match self.decoder.item().ok()? {
+            Item::Unsigned(num) => Some(num as u32),
+            _ => None,
+        }
+    }
+}
+
+/// Reassembles the blocks of a Q-Block1 or Q-Block2 transfer into a caller-supplied buffer.
+/// Blocks may arrive in any order and more than once, and bodies of up to `N` blocks are
+/// tracked.
+///
+/// All blocks of a body must have the size of the first block received. BERT blocks are not
+/// supported.
+///
+/// Source: [RFC 9177 4](https://datatracker.ietf.org/doc/html/rfc9177#section-4)
+pub struct QBlockReceiver<'buf, const N: usize> {
+    buffer: &'buf mut [u8],
+    received: [bool; N],
+    size: Option<BlockSize>,
+    /// Number of blocks in the body, known once the last block was received.
+    block_count: Option<u32>,
+    /// Length of the body, known once the last block was received.
+    len: usize,
+    /// Number of the block following the highest one received.
+    end: u32,
+}
+
+impl<'buf, const N: usize> QBlockReceiver<'buf, N> {
+    /// Create a receiver reassembling into `buffer`.
+    pub fn new(buffer: &'buf mut [u8]) -> Self {
+        Self {
+            buffer,
+            received: [false; N],
+            size: None,
+            block_count: None,
+            len: 0,
+            end: 0,
+        }
+    }
+
+    /// Returns the largest body the receiver can reassemble.
+    pub fn max_size(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Returns whether every block of the body was received.
+    pub fn is_complete(&self) -> bool {
+        self.block_count
+            .is_some_and(|count| self.received[..count as usize].iter().all(|&r| r))
+    }
+
+    /// Returns the body once every block was received.
+    pub fn body(&self) -> Option<&[u8]> {
+        self.is_complete().then(|| &self.buffer[..self.len])
+    }
+
+    /// Discard any partially received body.
+    pub fn reset(&mut self) {
+        self.received = [false; N];
+        self.size = None;
+        self.block_count = None;
+        self.len = 0;
+        self.end = 0;
+    }
+
+    /// Returns the numbers of the blocks not received yet, in ascending order.
+    ///
+    /// Until the last block was received, the size of the body is unknown, and only the block
+    /// following the highest one received is listed after the gaps.
+    pub fn missing_blocks(&self) -> impl Iterator<Item = u32> + '_ {
+        let end = match self.block_count {
+            Some(count) => count,
+            None => (self.end + 1).min(N as u32),
+        };
+
+        (0..end).filter(|&num| !self.received[num as usize])
+    }
+
+    /// Receive a single block.
+    ///
+    /// Returns [`BlockStatus::More`] with the Q-Block1 value a server echoes in its 2.31
+    /// (Continue) response after the last block of a burst, until the body is complete.
+    pub fn receive(
+        &mut self,
+        block: BlockValue,
+        payload: &[u8],
+    ) -> Result<BlockStatus, CoapBlockError> {
+        if block.size == BlockSize::Bert {
+            return Err(CoapBlockError::InvalidBlockOption(
+                CoapParseError::ReservedBlockSize,
+            ));
+        }
+
+        let size = self.size.unwrap_or(block.size);
+        let valid_length = match block.more {
+            true => payload.len() == size.size(),
+            false => payload.len() <= size.size(),
+        };
+        if block.size != size || !valid_length {
+            return Err(CoapBlockError::UnexpectedBlockLength(payload.len()));
+        }
+
+        // A final block must not come before a block that was already received.
+        let beyond_last = self
+            .block_count
+            .is_some_and(|count| block.num >= count || (!block.more && block.num + 1 != count))
+            || (!block.more && block.num.saturating_add(1) < self.end);
+        if beyond_last {
+            return Err(CoapBlockError::BlockOutOfRange(block.num));
+        }
+
+        let start = block.offset();
+        let end = start + payload.len();
+        if block.num as usize >= N || end > self.buffer.len() {
+            return Err(CoapBlockError::TooLarge(self.buffer.len()));
+        }
+
+        self.buffer[start..end].copy_from_slice(payload);
+        self.received[block.num as usize] = true;
+        self.size = Some(size);
+        self.end = self.end.max(block.num + 1);
+        if !block.more {
+            self.block_count = Some(block.num + 1);
+            self.len = end;
+        }
+
+        if self.is_complete() {
+            return Ok(BlockStatus::Complete(self.len));
+        }
+
+        Ok(BlockStatus::More(BlockValue {
+            num: block.num,
+            more: true,
+            size,
+        }))
+    }
+
+    /// Receive the block carried by a parsed message in its Q-Block1 or Q-Block2 option (as given
+    /// by `option_number`). A message without the block option is treated as a complete body.
+    ///
+    /// A Size1/Size2 option announcing a body larger than the buffer is rejected up front.
+    pub fn receive_message(
+        &mut self,
+        message: &Message<'_>,
+        option_number: OptionNumber,
+    ) -> Result<BlockStatus, CoapBlockError> {
+        let size_option = match option_number {
+            OptionNumber::QBlock1 => OptionNumber::Size1,
+            _ => OptionNumber::Size2,
+        };
+
+        let mut block = None;
+        for option in message.options {
+            if option.number == option_number {
+                block = Some(
+                    option
+                        .as_block()
+                        .map_err(CoapBlockError::InvalidBlockOption)?,
+                );
+            } else if option.number == size_option
+                && option
+                    .as_uint()
+                    .is_some_and(|size| size > self.buffer.len() as u64)
+            {
+                return Err(CoapBlockError::TooLarge(self.buffer.len()));
+            }
+        }
+
+        let payload = message.payload.unwrap_or_default();
+        if let Some(block) = block {
+            return self.receive(block, payload);
+        }
+
+        self.reset();
+        let max_size = self.buffer.len();
+        let dst = self
+            .buffer
+            .get_mut(..payload.len())
+            .ok_or(CoapBlockError::TooLarge(max_size))?;
+        dst.copy_from_slice(payload);
+        self.block_count = Some(0);
+        self.len = payload.len();
+
+        Ok(BlockStatus::Complete(self.len))
+    }
+
+    /// Add a Content-Format option and the [missing blocks](Self::missing_blocks) as payload to a
+    /// 4.08 (Request Entity Incomplete) response. Only options numbered up to 12
+    /// (Content-Format) may be added beforehand.
+    ///
+    /// Source: [RFC 9177 5](https://datatracker.ietf.org/doc/html/rfc9177#section-5)
+    pub fn write_missing_blocks<'b>(
+        &self,
+        builder: MessageBuilder<'b, NeedsPayload>,
+    ) -> Result<MessageBuilder<'b, Complete>, CoapBuildError> {
+        let builder = builder.option_uint(
+            OptionNumber::ContentFormat,
+            u16::from(ContentFormat::ApplicationMissingBlocksCborSeq),
+        )?;
+
+        let len = self
+            .missing_blocks()
+            .map(|num| cbor::int_len(num as i64))
+            .sum();
+        if len == 0 {
+            return Ok(builder.no_payload());
+        }
+
+        let mut encoded = Ok(());
+        let builder = builder.payload_with(len, |dst| {
+            // The payload is sized to fit the encoding exactly.
+            let mut encoder = Encoder::new(dst);
+            encoded = self
+                .missing_blocks()
+                .try_for_each(|num| encoder.int(num as i64))
+                .ok()
+                .filter(|()| encoder.finish().len() == len)
+                .ok_or(CoapBuildError::BufferTooSmall);
+        })?;
+        encoded?;
+
+        Ok(builder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MessageType, RequestCode};
+
+    fn block_request<'b>(sender: &QBlockSender<'_>, num: u32, buffer: &'b mut [u8]) -> Message<'b> {
+        let builder = MessageBuilder::new(buffer)
+            .unwrap()
+            .request(MessageType::NonConfirmable, RequestCode::Put)
+            .message_id(num as u16)
+            .token(&[1])
+            .unwrap()
+            .option_string(OptionNumber::UriPath, "fw")
+            .unwrap();
+        let packet = sender
+            .write_block(builder, OptionNumber::QBlock1, num)
+            .unwrap()
+            .build();
+
+        Message::parse(packet).unwrap()
+    }
+
+    #[test]
+    fn lossy_qblock1_transfer() {
+        let body: [u8; 200] = core::array::from_fn(|i| i as u8);
+        let mut sender = QBlockSender::new(&body, BlockSize::S16, 4);
+        assert_eq!(sender.block_count(), 13);
+
+        let mut reassembly = [0; 256];
+        let mut receiver = QBlockReceiver::<16>::new(&mut reassembly);
+
+        // Blocks 1, 5 and 6 are lost in the first two bursts.
+        for burst in 0..2 {
+            let range = sender.next_burst();
+            assert_eq!(range, burst * 4..burst * 4 + 4);
+            for num in range.filter(|num| ![1, 5, 6].contains(num)) {
+                let mut buffer = [0; 64];
+                let request = block_request(&sender, num, &mut buffer);
+                let status = receiver
+                    .receive_message(&request, OptionNumber::QBlock1)
+                    .unwrap();
+                assert!(matches!(status, BlockStatus::More(block) if block.num == num));
+            }
+        }
+
+        let mut buffer = [0; 32];
+        let builder = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .response(
+                MessageType::NonConfirmable,
+                ResponseCode::RequestEntityIncomplete,
+            )
+            .message_id(100)
+            .token(&[1])
+            .unwrap();
+        let response = receiver.write_missing_blocks(builder).unwrap().build();
+        let response = Message::parse(response).unwrap();
+        assert_eq!(response.payload, Some(&[0x01, 0x05, 0x06, 0x08][..]));
+
+        let missing = MissingBlocks::from_response(&response).unwrap();
+        for num in missing
+            .chain(sender.next_burst())
+            .chain(sender.next_burst())
+        {
+            let mut buffer = [0; 64];
+            let request = block_request(&sender, num, &mut buffer);
+            receiver
+                .receive_message(&request, OptionNumber::QBlock1)
+                .unwrap();
+        }
+
+        // Block 8 was sent twice.
+        assert!(sender.is_finished());
+        assert!(sender.next_burst().is_empty());
+        assert!(receiver.is_complete());
+
+        let mut unbounded = QBlockSender::new(&body, BlockSize::S16, u32::MAX);
+        assert_eq!(unbounded.next_burst(), 0..13);
+        assert!(unbounded.next_burst().is_empty());
+        assert_eq!(receiver.body(), Some(&body[..]));
+        assert_eq!(receiver.missing_blocks().next(), None);
+    }
+
+    #[test]
+    fn qblock2_size_option_order() {
+        let body = [0; 40];
+        let sender = QBlockSender::new(&body, BlockSize::S32, DEFAULT_MAX_PAYLOADS);
+
+        let mut buffer = [0; 64];
+        let builder = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .response(MessageType::NonConfirmable, ResponseCode::Content)
+            .message_id(1)
+            .token(&[1])
+            .unwrap();
+        let packet = sender
+            .write_block(builder, OptionNumber::QBlock2, 0)
+            .unwrap()
+            .build();
+        let message = Message::parse_strict(packet).unwrap();
+
+        let mut options = message.options.into_iter();
+        let size2 = options.next().unwrap();
+        assert_eq!(size2.number, OptionNumber::Size2);
+        assert_eq!(size2.as_uint(), Some(40));
+        assert_eq!(options.next().unwrap().number, OptionNumber::QBlock2);
+
+        let mut reassembly = [0; 32];
+        let mut receiver = QBlockReceiver::<4>::new(&mut reassembly);
+        assert_eq!(
+            receiver.receive_message(&message, OptionNumber::QBlock2),
+            Err(CoapBlockError::TooLarge(32))
+        );
+    }
+
+    #[test]
+    fn receiver_rejects_inconsistent_blocks() {
+        let mut reassembly = [0; 64];
+        let mut receiver = QBlockReceiver::<4>::new(&mut reassembly);
+
+        let block = |num, more, size| BlockValue { num, more, size };
+        assert_eq!(
+            receiver.receive(block(2, false, BlockSize::S16), &[0; 10]),
+            Ok(BlockStatus::More(block(2, true, BlockSize::S16)))
+        );
+        assert!(receiver.missing_blocks().eq([0, 1]));
+        assert_eq!(
+            receiver.receive(block(0, true, BlockSize::S32), &[0; 32]),
+            Err(CoapBlockError::UnexpectedBlockLength(32))
+        );
+        assert_eq!(
+            receiver.receive(block(3, true, BlockSize::S16), &[0; 16]),
+            Err(CoapBlockError::BlockOutOfRange(3))
+        );
+        assert_eq!(
+            receiver.receive(block(1, true, BlockSize::S16), &[0; 8]),
+            Err(CoapBlockError::UnexpectedBlockLength(8))
+        );
+
+        // A final block before the highest block received so far is rejected.
+        let mut reassembly = [0; 128];
+        let mut receiver = QBlockReceiver::<8>::new(&mut reassembly);
+        receiver
+            .receive(block(5, true, BlockSize::S16), &[0; 16])
+            .unwrap();
+        assert_eq!(
+            receiver.receive(block(2, false, BlockSize::S16), &[0; 4]),
+            Err(CoapBlockError::BlockOutOfRange(2))
+        );
+        assert!(receiver.missing_blocks().eq([0, 1, 2, 3, 4, 6]));
+
+        assert_eq!(
+            MissingBlocks::decode(&[0x01, 0x20]).unwrap_err(),
+            CoapBlockError::InvalidMissingBlocks
+        );
+        let missing = MissingBlocks::decode(&[0x01, 0x19, 0x01, 0x00]).unwrap();
+        assert!(missing.eq([1, 256]));
+    }
+}
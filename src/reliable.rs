@@ -0,0 +1,288 @@
+use crate::error::CoapParseError;
+use crate::parser::{CoapOptions, parse_options_and_payload};
+
+type ParseResult<T> = core::result::Result<T, CoapParseError>;
+
+/// Progress of decoding a message from a TCP byte stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FrameStatus {
+    /// At least this many more bytes are needed before the message length is known or the
+    /// message is complete.
+    Incomplete(usize),
+    /// A complete message of this many bytes is at the start of the buffer.
+    Complete(usize),
+}
+
+/// Parsed CoAP message sent over a reliable transport (TCP, TLS or WebSockets).
+///
+/// Messages over reliable transports carry neither a type nor a message ID, as reliability is
+/// provided by the transport.
+///
+/// Source: [RFC 8323 3.2](https://datatracker.ietf.org/doc/html/rfc8323#section-3.2)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ReliableMessage<'a> {
+    /// Token bytes for request/response matching
+    pub token: &'a [u8],
+    /// Message code (method for requests, response code for responses, signal code for signaling)
+    pub code: u8,
+    /// Collection of options in the message
+    pub options: CoapOptions<'a>,
+    /// Optional payload data
+    pub payload: Option<&'a [u8]>,
+}
+
+impl<'a> ReliableMessage<'a> {
+    /// Determine the length of the TCP/TLS message at the start of `buffer`, which may contain a
+    /// partial message read from a byte stream.
+    ///
+    /// Source: [RFC 8323 3.2](https://datatracker.ietf.org/doc/html/rfc8323#section-3.2)
+    pub fn frame_length(buffer: &[u8]) -> ParseResult<FrameStatus> {
+        let Some(&first) = buffer.first() else {
+            return Ok(FrameStatus::Incomplete(1));
+        };
+
+        let token_len = (first & 0x0F) as usize;
+        if token_len > 8 {
+            return Err(CoapParseError::InvalidTokenLength(token_len));
+        }
+
+        let (ext_len, base) = match first >> 4 {
+            13 => (1, 13),
+            14 => (2, 269),
+            15 => (4, 65805),
+            len => (0, len as usize),
+        };
+
+        if buffer.len() < 1 + ext_len {
+            return Ok(FrameStatus::Incomplete(1 + ext_len - buffer.len()));
+        }
+
+        let ext = buffer[1..1 + ext_len]
+            .iter()
+            .fold(0u64, |acc, &byte| (acc << 8) | u64::from(byte));
+
+        // Length byte, extended length, code, token, options and payload.
+        let total = (1 + ext_len + 1 + token_len + base) as u64 + ext;
+        let total =
+            usize::try_from(total).map_err(|_| CoapParseError::InvalidFrameLength(first >> 4))?;
+
+        if buffer.len() < total {
+            Ok(FrameStatus::Incomplete(total - buffer.len()))
+        } else {
+            Ok(FrameStatus::Complete(total))
+        }
+    }
+
+    /// Parse a CoAP over TCP/TLS message from the start of a byte buffer. Any bytes after the end
+    /// of the message are ignored; use [`frame_length`](ReliableMessage::frame_length) to find
+    /// where the next message starts.
+    ///
+    /// Source: [RFC 8323 3.2](https://datatracker.ietf.org/doc/html/rfc8323#section-3.2)
+    pub fn parse(buffer: &'a [u8]) -> ParseResult<Self> {
+        let length = match Self::frame_length(buffer)? {
+            FrameStatus::Complete(length) => length,
+            FrameStatus::Incomplete(_) => return Err(CoapParseError::MessageTooShort),
+        };
+
+        let ext_len = match buffer[0] >> 4 {
+            13 => 1,
+            14 => 2,
+            15 => 4,
+            _ => 0,
+        };
+
+        Self::parse_after_length(&buffer[..length], 1 + ext_len)
+    }
+
+    /// Parse a CoAP over WebSockets message, which fills the whole buffer.
+    ///
+    /// Source: [RFC 8323 4.2](https://datatracker.ietf.org/doc/html/rfc8323#section-4.2)
+    pub fn parse_websocket(buffer: &'a [u8]) -> ParseResult<Self> {
+        let Some(&first) = buffer.first() else {
+            return Err(CoapParseError::MessageTooShort);
+        };
+
+        if first >> 4 != 0 {
+            return Err(CoapParseError::InvalidFrameLength(first >> 4));
+        }
+
+        Self::parse_after_length(buffer, 1)
+    }
+
+    /// Parse the code, token, options and payload following the length field.
+    fn parse_after_length(buffer: &'a [u8], code_offset: usize) -> ParseResult<Self> {
+        let token_len = (buffer[0] & 0x0F) as usize;
+        if token_len > 8 {
+            return Err(CoapParseError::InvalidTokenLength(token_len));
+        }
+
+        let token_start = code_offset + 1;
+        if buffer.len() < token_start + token_len {
+            return Err(CoapParseError::MessageTooShort);
+        }
+
+        let code = buffer[code_offset];
+        let token = &buffer[token_start..token_start + token_len];
+
+        let (options, payload) = parse_options_and_payload(buffer, token_start + token_len)?;
+
+        Ok(ReliableMessage {
+            token,
+            code,
+            options,
+            payload,
+        })
+    }
+
+    /// Extract the class portion of the code (upper 3 bits)
+    pub fn code_class(&self) -> u8 {
+        self.code >> 5
+    }
+
+    /// Extract the detail portion of the code (lower 5 bits)
+    pub fn code_detail(&self) -> u8 {
+        self.code & 0x1F
+    }
+
+    /// Check if this message is a request
+    pub fn is_request(&self) -> bool {
+        self.code_class() == 0 && self.code_detail() != 0
+    }
+
+    /// Check if this message is a response
+    pub fn is_response(&self) -> bool {
+        matches!(self.code_class(), 2 | 4 | 5)
+    }
+
+    /// Check if this message is empty (code 0.00). Empty messages over reliable transports carry
+    /// no meaning and must be ignored.
+    pub fn is_empty(&self) -> bool {
+        self.code == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MessageBuilder, OptionNumber, RequestCode, ResponseCode};
+
+    #[test]
+    fn tcp_roundtrip() {
+        let mut buffer = [0; 64];
+
+        let packet = MessageBuilder::new_tcp(&mut buffer)
+            .unwrap()
+            .request(RequestCode::Get)
+            .token(&[0x01, 0x02])
+            .unwrap()
+            .option(OptionNumber::UriPath, b"sensors")
+            .unwrap()
+            .no_payload()
+            .build();
+
+        // Length 8 (option), TKL 2, code, token, option
+        assert_eq!(packet[..4], [0x82, 0x01, 0x01, 0x02]);
+        assert_eq!(packet.len(), 12);
+        assert_eq!(
+            ReliableMessage::frame_length(packet),
+            Ok(FrameStatus::Complete(12))
+        );
+
+        let message = ReliableMessage::parse(packet).unwrap();
+        assert_eq!(message.token, &[0x01, 0x02]);
+        assert_eq!(message.code, u8::from(RequestCode::Get));
+        let path = message.options.into_iter().next().unwrap();
+        assert_eq!(path.as_str(), Ok("sensors"));
+    }
+
+    #[test]
+    fn tcp_extended_length() {
+        let mut buffer = [0; 400];
+        let payload = [0xAB; 300];
+
+        let builder = MessageBuilder::new_tcp(&mut buffer)
+            .unwrap()
+            .response(ResponseCode::Content)
+            .no_token()
+            .unwrap()
+            .payload(&payload)
+            .unwrap();
+        assert_eq!(builder.remaining_buffer(), 400 - 6 - 301);
+        let len = builder.len();
+        let packet = builder.build();
+
+        // 301 bytes of payload and marker are encoded as 14 with 2 extended bytes (301 - 269).
+        assert_eq!(packet[..4], [0xE0, 0x00, 0x20, 0x45]);
+        assert_eq!(packet.len(), len);
+        assert_eq!(
+            ReliableMessage::frame_length(&packet[..10]),
+            Ok(FrameStatus::Incomplete(packet.len() - 10))
+        );
+        assert_eq!(
+            ReliableMessage::frame_length(&packet[..1]),
+            Ok(FrameStatus::Incomplete(2))
+        );
+
+        let message = ReliableMessage::parse(packet).unwrap();
+        assert_eq!(message.payload, Some(&payload[..]));
+    }
+
+    #[test]
+    fn stream_with_multiple_messages() {
+        let mut first = [0; 16];
+        let first = MessageBuilder::new_tcp(&mut first)
+            .unwrap()
+            .request(RequestCode::Post)
+            .no_token()
+            .unwrap()
+            .payload(b"hi")
+            .unwrap()
+            .build();
+
+        let mut stream = [0; 32];
+        stream[..first.len()].copy_from_slice(first);
+        stream[first.len()..first.len() + 2].copy_from_slice(&[0x00, 0x00]);
+        let stream = &stream[..first.len() + 2];
+
+        let Ok(FrameStatus::Complete(len)) = ReliableMessage::frame_length(stream) else {
+            panic!("expected a complete message");
+        };
+        assert_eq!(
+            ReliableMessage::parse(stream).unwrap().payload,
+            Some(&b"hi"[..])
+        );
+
+        let rest = &stream[len..];
+        assert_eq!(
+            ReliableMessage::frame_length(rest),
+            Ok(FrameStatus::Complete(2))
+        );
+        assert!(ReliableMessage::parse(rest).unwrap().is_empty());
+    }
+
+    #[test]
+    fn websocket_roundtrip() {
+        let mut buffer = [0; 32];
+
+        let packet = MessageBuilder::new_websocket(&mut buffer)
+            .unwrap()
+            .request(RequestCode::Get)
+            .token(&[0x07])
+            .unwrap()
+            .no_payload()
+            .build();
+
+        assert_eq!(packet, &[0x01, 0x01, 0x07]);
+
+        let message = ReliableMessage::parse_websocket(packet).unwrap();
+        assert_eq!(message.token, &[0x07]);
+        assert!(message.is_request());
+
+        assert_eq!(
+            ReliableMessage::parse_websocket(&[0x11, 0x01, 0x07]),
+            Err(CoapParseError::InvalidFrameLength(1))
+        );
+    }
+}
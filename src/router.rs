@@ -0,0 +1,437 @@
+use crate::error::CoapBuildError;
+use crate::{
+    Complete, Message, MessageBuilder, NeedsHeader, NeedsPayload, OptionNumber, RequestCode,
+    ResponseCode,
+};
+
+type BuilderResult<'buf, T> = core::result::Result<MessageBuilder<'buf, T>, CoapBuildError>;
+
+/// Handler for requests matching a [`Route`]. It receives the shared context of the router, the
+/// request, and a builder for the response.
+pub type Handler<C> = for<'a, 'buf> fn(
+    &mut C,
+    &RouteRequest<'a>,
+    MessageBuilder<'buf, NeedsHeader>,
+) -> BuilderResult<'buf, Complete>;
+
+/// Returns the segments of a path pattern. An empty pattern or `/` has no segments.
+fn pattern_segments(pattern: &str) -> impl Iterator<Item = &str> {
+    let pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+    pattern.split('/').filter(move |_| !pattern.is_empty())
+}
+
+/// Returns the name of a `{name}` parameter segment.
+fn parameter_name(segment: &str) -> Option<&str> {
+    segment.strip_prefix('{')?.strip_suffix('}')
+}
+
+fn path_segments<'a>(message: &Message<'a>) -> impl Iterator<Item = &'a [u8]> {
+    message
+        .options
+        .into_iter()
+        .filter(|opt| opt.number == OptionNumber::UriPath)
+        .map(|opt| opt.value)
+}
+
+/// Check if the Uri-Path of a request matches a path pattern.
+fn matches_path(pattern: &str, message: &Message<'_>) -> bool {
+    let mut path = path_segments(message);
+
+    for segment in pattern_segments(pattern) {
+        if segment == "*" {
+            return true;
+        }
+
+        let Some(value) = path.next() else {
+            return false;
+        };
+
+        if parameter_name(segment).is_none() && segment.as_bytes() != value {
+            return false;
+        }
+    }
+
+    path.next().is_none()
+}
+
+/// A handler registered for a method and a path pattern.
+///
+/// Patterns are made of `/`-separated segments, which are either literal, a `{name}` parameter
+/// matching any single segment, or a final `*` matching all remaining segments. For example,
+/// `/sensors/{id}` matches `/sensors/temp` but not `/sensors` or `/sensors/temp/raw`.
+pub struct Route<'r, C> {
+    /// Method of the requests handled by this route
+    pub method: RequestCode,
+    /// Path pattern of the requests handled by this route
+    pub pattern: &'r str,
+    /// Function answering the requests
+    pub handler: Handler<C>,
+}
+
+/// A request matched to a [`Route`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RouteRequest<'a> {
+    /// The parsed request
+    pub message: Message<'a>,
+    pattern: &'a str,
+    message_id: u16,
+}
+
+impl<'a> RouteRequest<'a> {
+    /// Returns the path segment matched by the `{name}` parameter of the route pattern.
+    pub fn param(&self, name: &str) -> Option<&'a str> {
+        pattern_segments(self.pattern)
+            .zip(path_segments(&self.message))
+            .find(|(segment, _)| parameter_name(segment) == Some(name))
+            .and_then(|(_, value)| core::str::from_utf8(value).ok())
+    }
+
+    /// Returns the path segments matched by a final `*` in the route pattern.
+    pub fn wildcard(&self) -> impl Iterator<Item = &'a [u8]> {
+        let fixed = pattern_segments(self.pattern)
+            .take_while(|&segment| segment != "*")
+            .count();
+
+        path_segments(&self.message).skip(fixed)
+    }
+
+    /// Start the response to this request. See [`MessageBuilder::reply`].
+    pub fn reply<'buf>(
+        &self,
+        builder: MessageBuilder<'buf, NeedsHeader>,
+        code: ResponseCode,
+    ) -> BuilderResult<'buf, NeedsPayload> {
+        builder.reply(&self.message, code, self.message_id)
+    }
+}
+
+/// Dispatches requests to handlers by method and Uri-Path.
+///
+/// Requests that no route can handle are answered automatically: 4.02 (Bad Option) when a
+/// Confirmable request carries a critical option missing from the
+/// [option registry](OptionNumber::definition), 4.04 (Not Found) when no route matches its
+/// path, and 4.05 (Method Not Allowed) when routes match its path but not its method.
+///
+/// Source: [RFC 7252 5.8](https://datatracker.ietf.org/doc/html/rfc7252#section-5.8)
+pub struct Router<'r, C> {
+    routes: &'r [Route<'r, C>],
+}
+
+impl<'r, C> Router<'r, C> {
+    /// Create a router for a table of routes. When several routes match a request, the first one
+    /// is used.
+    pub const fn new(routes: &'r [Route<'r, C>]) -> Self {
+        Self { routes }
+    }
+
+    /// Returns the routes of the router.
+    pub fn routes(&self) -> &'r [Route<'r, C>] {
+        self.routes
+    }
+
+    /// Answer a request, either with a matching handler or with an error response.
+    ///
+    /// `message_id` is used for Non-confirmable responses; see [`MessageBuilder::reply`]. Returns
+    /// `None` without building anything if `request` is not a request, or if it is a
+    /// Non-confirmable request with an unrecognized critical option, which must be ignored.
+    pub fn dispatch<'buf>(
+        &self,
+        context: &mut C,
+        request: &Message<'_>,
+        builder: MessageBuilder<'buf, NeedsHeader>,
+        message_id: u16,
+    ) -> Result<Option<MessageBuilder<'buf, Complete>>, CoapBuildError> {
+        if !request.is_request() {
+            return Ok(None);
+        }
+
+        // Protected requests must be unprotected before they are routed.
+        let unrecognized = request.first_unrecognized_critical(|number| {
+            number != OptionNumber::Oscore && number.definition().is_some()
+        });
+        if unrecognized.is_some() {
+            return builder.bad_option_reply(request);
+        }
+
+        let mut path_found = false;
+
+        for route in self.routes {
+            if !matches_path(route.pattern, request) {
+                continue;
+            }
+
+            path_found = true;
+
+            if u8::from(route.method) == request.code {
+                let route_request = RouteRequest {
+                    message: *request,
+                    pattern: route.pattern,
+                    message_id,
+                };
+
+                return (route.handler)(context, &route_request, builder).map(Some);
+            }
+        }
+
+        let code = if path_found {
+            ResponseCode::MethodNotAllowed
+        } else {
+            ResponseCode::NotFound
+        };
+
+        Self::error(builder, request, code, message_id)
+    }
+
+    fn error<'buf>(
+        builder: MessageBuilder<'buf, NeedsHeader>,
+        request: &Message<'_>,
+        code: ResponseCode,
+        message_id: u16,
+    ) -> Result<Option<MessageBuilder<'buf, Complete>>, CoapBuildError> {
+        Ok(Some(builder.reply(request, code, message_id)?.no_payload()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MessageType;
+
+    #[derive(Default)]
+    struct State {
+        temperature: u8,
+        calls: usize,
+    }
+
+    fn get_sensor<'buf>(
+        state: &mut State,
+        request: &RouteRequest<'_>,
+        builder: MessageBuilder<'buf, NeedsHeader>,
+    ) -> BuilderResult<'buf, Complete> {
+        state.calls += 1;
+
+        let payload = match request.param("id") {
+            Some("temp") => &[state.temperature][..],
+            _ => b"?",
+        };
+
+        request
+            .reply(builder, ResponseCode::Content)?
+            .payload(payload)
+    }
+
+    fn put_sensor<'buf>(
+        state: &mut State,
+        request: &RouteRequest<'_>,
+        builder: MessageBuilder<'buf, NeedsHeader>,
+    ) -> BuilderResult<'buf, Complete> {
+        state.temperature = request.message.payload.map_or(0, |payload| payload[0]);
+
+        Ok(request.reply(builder, ResponseCode::Changed)?.no_payload())
+    }
+
+    fn get_file<'buf>(
+        _: &mut State,
+        request: &RouteRequest<'_>,
+        builder: MessageBuilder<'buf, NeedsHeader>,
+    ) -> BuilderResult<'buf, Complete> {
+        let depth = request.wildcard().count() as u8;
+
+        request
+            .reply(builder, ResponseCode::Content)?
+            .payload(&[depth])
+    }
+
+    static ROUTES: &[Route<State>] = &[
+        Route {
+            method: RequestCode::Get,
+            pattern: "/sensors/{id}",
+            handler: get_sensor,
+        },
+        Route {
+            method: RequestCode::Put,
+            pattern: "/sensors/{id}",
+            handler: put_sensor,
+        },
+        Route {
+            method: RequestCode::Get,
+            pattern: "/files/*",
+            handler: get_file,
+        },
+    ];
+
+    fn dispatch<'a>(state: &mut State, request: &Message<'_>, buffer: &'a mut [u8]) -> Message<'a> {
+        let builder = MessageBuilder::new(buffer).unwrap();
+        let response = Router::new(ROUTES)
+            .dispatch(state, request, builder, 0x9999)
+            .unwrap()
+            .unwrap();
+
+        Message::parse(response.build()).unwrap()
+    }
+
+    #[test]
+    fn dispatches_by_method_and_path() {
+        let mut state = State::default();
+
+        let mut req = [0; 64];
+        let packet = MessageBuilder::new(&mut req)
+            .unwrap()
+            .request(MessageType::NonConfirmable, RequestCode::Put)
+            .message_id(0x1234)
+            .token(&[0xAA])
+            .unwrap()
+            .option_string(OptionNumber::UriPath, "sensors")
+            .unwrap()
+            .option_string(OptionNumber::UriPath, "temp")
+            .unwrap()
+            .payload(&[21])
+            .unwrap()
+            .build();
+        let put = Message::parse(packet).unwrap();
+        let mut res = [0; 64];
+        let response = dispatch(&mut state, &put, &mut res);
+        assert_eq!(response.code, u8::from(ResponseCode::Changed));
+        assert_eq!(response.message_type, MessageType::NonConfirmable);
+        assert_eq!(response.message_id, 0x9999);
+        assert_eq!(response.token, &[0xAA]);
+
+        let mut req = [0; 64];
+        let packet = MessageBuilder::new(&mut req)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(0x1234)
+            .token(&[0xAA])
+            .unwrap()
+            .option_string(OptionNumber::UriPath, "sensors")
+            .unwrap()
+            .option_string(OptionNumber::UriPath, "temp")
+            .unwrap()
+            .no_payload()
+            .build();
+        let get = Message::parse(packet).unwrap();
+        let mut res = [0; 64];
+        let response = dispatch(&mut state, &get, &mut res);
+        assert_eq!(response.code, u8::from(ResponseCode::Content));
+        assert_eq!(response.message_type, MessageType::Acknowledgement);
+        assert_eq!(response.message_id, 0x1234);
+        assert_eq!(response.payload, Some(&[21][..]));
+        assert_eq!(state.calls, 1);
+    }
+
+    #[test]
+    fn wildcard_matches_remaining_segments() {
+        let mut state = State::default();
+
+        let mut req = [0; 64];
+        let packet = MessageBuilder::new(&mut req)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(0x1234)
+            .token(&[0xAA])
+            .unwrap()
+            .option_string(OptionNumber::UriPath, "files")
+            .unwrap()
+            .option_string(OptionNumber::UriPath, "a")
+            .unwrap()
+            .option_string(OptionNumber::UriPath, "b")
+            .unwrap()
+            .option_string(OptionNumber::UriPath, "c")
+            .unwrap()
+            .no_payload()
+            .build();
+        let get = Message::parse(packet).unwrap();
+        let mut res = [0; 64];
+        assert_eq!(dispatch(&mut state, &get, &mut res).payload, Some(&[3][..]));
+    }
+
+    #[test]
+    fn error_responses() {
+        let mut state = State::default();
+
+        let mut req = [0; 64];
+        let packet = MessageBuilder::new(&mut req)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(0x1234)
+            .token(&[0xAA])
+            .unwrap()
+            .option_string(OptionNumber::UriPath, "sensors")
+            .unwrap()
+            .no_payload()
+            .build();
+        let get = Message::parse(packet).unwrap();
+        let mut res = [0; 64];
+        let response = dispatch(&mut state, &get, &mut res);
+        assert_eq!(response.code, u8::from(ResponseCode::NotFound));
+        assert_eq!(response.payload, None);
+
+        let mut req = [0; 64];
+        let packet = MessageBuilder::new(&mut req)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Delete)
+            .message_id(0x1234)
+            .token(&[0xAA])
+            .unwrap()
+            .option_string(OptionNumber::UriPath, "sensors")
+            .unwrap()
+            .option_string(OptionNumber::UriPath, "temp")
+            .unwrap()
+            .no_payload()
+            .build();
+        let delete = Message::parse(packet).unwrap();
+        let mut res = [0; 64];
+        let response = dispatch(&mut state, &delete, &mut res);
+        assert_eq!(response.code, u8::from(ResponseCode::MethodNotAllowed));
+
+        // Option 9 is critical and not recognized.
+        let mut req = [0; 64];
+        let packet = MessageBuilder::new(&mut req)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(1)
+            .no_token()
+            .unwrap()
+            .option(9u16, b"x")
+            .unwrap()
+            .option_string(OptionNumber::UriPath, "files")
+            .unwrap()
+            .no_payload()
+            .build();
+        let mut res = [0; 64];
+        let response = dispatch(&mut state, &Message::parse(packet).unwrap(), &mut res);
+        assert_eq!(response.code, u8::from(ResponseCode::BadOption));
+        assert_eq!(state.calls, 0);
+    }
+
+    #[test]
+    fn ignores_non_confirmable_bad_options() {
+        let mut state = State::default();
+        let request = Message::parse(&[0x50, 0x01, 0x00, 0x01, 0x91, b'x']).unwrap();
+
+        let mut res = [0; 64];
+        let builder = MessageBuilder::new(&mut res).unwrap();
+        assert!(
+            Router::new(ROUTES)
+                .dispatch(&mut state, &request, builder, 1)
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn ignores_non_requests() {
+        let mut state = State::default();
+        let empty = Message::parse(&[0x40, 0x00, 0x00, 0x01]).unwrap();
+
+        let mut res = [0; 64];
+        let builder = MessageBuilder::new(&mut res).unwrap();
+        assert!(
+            Router::new(ROUTES)
+                .dispatch(&mut state, &empty, builder, 1)
+                .unwrap()
+                .is_none()
+        );
+    }
+}
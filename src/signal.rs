@@ -0,0 +1,248 @@
+use core::time::Duration;
+
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
+use crate::{CoapOption, ReliableMessage, coap_code};
+
+/// Max-Message-Size option of a CSM message.
+pub(crate) const MAX_MESSAGE_SIZE: u16 = 2;
+/// Block-Wise-Transfer option of a CSM message.
+pub(crate) const BLOCK_WISE_TRANSFER: u16 = 4;
+/// Custody option of a Ping or Pong message.
+pub(crate) const CUSTODY: u16 = 2;
+/// Alternative-Address option of a Release message.
+pub(crate) const ALTERNATIVE_ADDRESS: u16 = 2;
+/// Hold-Off option of a Release message.
+pub(crate) const HOLD_OFF: u16 = 4;
+/// Bad-CSM-Option option of an Abort message.
+pub(crate) const BAD_CSM_OPTION: u16 = 2;
+
+/// Max-Message-Size assumed until the peer's CSM message says otherwise.
+///
+/// Source: [RFC 8323 5.3.1](https://datatracker.ietf.org/doc/html/rfc8323#section-5.3.1)
+pub const DEFAULT_MAX_MESSAGE_SIZE: u32 = 1152;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, IntoPrimitive, TryFromPrimitive)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+/// Signaling codes for CoAP over reliable transports. Signaling messages control the connection
+/// rather than carrying requests or responses, and each code defines its own option numbers.
+///
+/// Source: [RFC 8323 5.1](https://datatracker.ietf.org/doc/html/rfc8323#section-5.1)
+pub enum SignalCode {
+    /// Capabilities and Settings Message. Each side must send a CSM as the first message on a new
+    /// connection, and may send further CSMs later to update its settings.
+    ///
+    /// Source: [RFC 8323 5.3](https://datatracker.ietf.org/doc/html/rfc8323#section-5.3)
+    Csm = coap_code!(7, 1),
+    /// Checks that the connection is alive. The receiver must answer with a Pong carrying the same
+    /// token.
+    ///
+    /// Source: [RFC 8323 5.4](https://datatracker.ietf.org/doc/html/rfc8323#section-5.4)
+    Ping = coap_code!(7, 2),
+    /// Answers a Ping.
+    ///
+    /// Source: [RFC 8323 5.4](https://datatracker.ietf.org/doc/html/rfc8323#section-5.4)
+    Pong = coap_code!(7, 3),
+    /// Announces that the sender will close the connection and asks the peer to stop sending new
+    /// requests on it.
+    ///
+    /// Source: [RFC 8323 5.5](https://datatracker.ietf.org/doc/html/rfc8323#section-5.5)
+    Release = coap_code!(7, 4),
+    /// Announces that the connection is being closed immediately because of an error, such as an
+    /// unsupported option in a CSM.
+    ///
+    /// Source: [RFC 8323 5.6](https://datatracker.ietf.org/doc/html/rfc8323#section-5.6)
+    Abort = coap_code!(7, 5),
+}
+
+impl<'a> ReliableMessage<'a> {
+    /// Returns the signaling code of this message, or `None` if it is not a signaling message.
+    ///
+    /// Source: [RFC 8323 5.1](https://datatracker.ietf.org/doc/html/rfc8323#section-5.1)
+    pub fn signal_code(&self) -> Option<SignalCode> {
+        SignalCode::try_from(self.code).ok()
+    }
+
+    /// Check if this message is a signaling message (class 7).
+    pub fn is_signal(&self) -> bool {
+        self.code_class() == 7
+    }
+
+    /// Returns the Max-Message-Size of a CSM message, in bytes. Without this option, the peer
+    /// accepts messages of up to [`DEFAULT_MAX_MESSAGE_SIZE`] bytes.
+    ///
+    /// Source: [RFC 8323 5.3.1](https://datatracker.ietf.org/doc/html/rfc8323#section-5.3.1)
+    pub fn max_message_size(&self) -> Option<u32> {
+        self.signal_option(SignalCode::Csm, MAX_MESSAGE_SIZE)
+            .filter(|option| option.value.len() <= 4)
+            .and_then(|option| option.as_uint())
+            .map(|value| value as u32)
+    }
+
+    /// Check if a CSM message announces support for block-wise transfers.
+    ///
+    /// Source: [RFC 8323 5.3.2](https://datatracker.ietf.org/doc/html/rfc8323#section-5.3.2)
+    pub fn block_wise_transfer(&self) -> bool {
+        self.signal_option(SignalCode::Csm, BLOCK_WISE_TRANSFER)
+            .is_some()
+    }
+
+    /// Check if a Ping or Pong message carries the Custody option, asking the receiver to answer
+    /// only once it has processed all requests received before it.
+    ///
+    /// Source: [RFC 8323 5.4.1](https://datatracker.ietf.org/doc/html/rfc8323#section-5.4.1)
+    pub fn custody(&self) -> bool {
+        self.signal_option(SignalCode::Ping, CUSTODY).is_some()
+            || self.signal_option(SignalCode::Pong, CUSTODY).is_some()
+    }
+
+    /// Returns the alternative addresses a Release message suggests reconnecting to, in the form
+    /// of URI authorities.
+    ///
+    /// Source: [RFC 8323 5.5.1](https://datatracker.ietf.org/doc/html/rfc8323#section-5.5.1)
+    pub fn alternative_addresses(&self) -> impl Iterator<Item = &'a str> {
+        let release = self.signal_code() == Some(SignalCode::Release);
+
+        self.options
+            .into_iter()
+            .filter(move |option| release && u16::from(option.number) == ALTERNATIVE_ADDRESS)
+            .filter_map(|option| option.as_str().ok())
+    }
+
+    /// Returns how long the sender of a Release message asks the peer to wait before
+    /// reconnecting.
+    ///
+    /// Source: [RFC 8323 5.5.2](https://datatracker.ietf.org/doc/html/rfc8323#section-5.5.2)
+    pub fn hold_off(&self) -> Option<Duration> {
+        self.signal_option(SignalCode::Release, HOLD_OFF)
+            .filter(|option| option.value.len() <= 3)
+            .and_then(|option| option.as_uint())
+            .map(Duration::from_secs)
+    }
+
+    /// Returns the number of the CSM option that caused an Abort message.
+    ///
+    /// Source: [RFC 8323 5.6.1](https://datatracker.ietf.org/doc/html/rfc8323#section-5.6.1)
+    pub fn bad_csm_option(&self) -> Option<u16> {
+        self.signal_option(SignalCode::Abort, BAD_CSM_OPTION)
+            .filter(|option| option.value.len() <= 2)
+            .and_then(|option| option.as_uint())
+            .map(|value| value as u16)
+    }
+
+    /// Find the first option with the given number, if this message has the given signaling code.
+    fn signal_option(&self, code: SignalCode, number: u16) -> Option<CoapOption<'a>> {
+        if self.signal_code() != Some(code) {
+            return None;
+        }
+
+        self.options
+            .into_iter()
+            .find(|option| u16::from(option.number) == number)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MessageBuilder;
+
+    #[test]
+    fn csm_roundtrip() {
+        let mut buffer = [0; 32];
+
+        let packet = MessageBuilder::new_tcp(&mut buffer)
+            .unwrap()
+            .signal(SignalCode::Csm)
+            .no_token()
+            .unwrap()
+            .max_message_size(4096)
+            .unwrap()
+            .block_wise_transfer()
+            .unwrap()
+            .no_payload()
+            .build();
+
+        // Length 4, code 7.01, Max-Message-Size (delta 2, length 2), Block-Wise-Transfer (delta 2)
+        assert_eq!(packet, &[0x40, 0xE1, 0x22, 0x10, 0x00, 0x20]);
+
+        let message = ReliableMessage::parse(packet).unwrap();
+        assert!(message.is_signal());
+        assert_eq!(message.signal_code(), Some(SignalCode::Csm));
+        assert_eq!(message.max_message_size(), Some(4096));
+        assert!(message.block_wise_transfer());
+        assert!(!message.custody());
+    }
+
+    #[test]
+    fn options_depend_on_signal_code() {
+        let mut buffer = [0; 32];
+
+        let packet = MessageBuilder::new_tcp(&mut buffer)
+            .unwrap()
+            .signal(SignalCode::Ping)
+            .token(&[0x42])
+            .unwrap()
+            .custody()
+            .unwrap()
+            .no_payload()
+            .build();
+
+        let message = ReliableMessage::parse(packet).unwrap();
+        assert_eq!(message.signal_code(), Some(SignalCode::Ping));
+        assert!(message.custody());
+        // Option 2 of a Ping is Custody, not Max-Message-Size or Bad-CSM-Option.
+        assert_eq!(message.max_message_size(), None);
+        assert_eq!(message.bad_csm_option(), None);
+    }
+
+    #[test]
+    fn release_and_abort() {
+        let mut buffer = [0; 64];
+
+        let packet = MessageBuilder::new_websocket(&mut buffer)
+            .unwrap()
+            .signal(SignalCode::Release)
+            .no_token()
+            .unwrap()
+            .alternative_address("coap.example.org")
+            .unwrap()
+            .alternative_address("[2001:db8::1]:5683")
+            .unwrap()
+            .hold_off(Duration::from_secs(30))
+            .unwrap()
+            .no_payload()
+            .build();
+
+        let message = ReliableMessage::parse_websocket(packet).unwrap();
+        let mut addresses = message.alternative_addresses();
+        assert_eq!(addresses.next(), Some("coap.example.org"));
+        assert_eq!(addresses.next(), Some("[2001:db8::1]:5683"));
+        assert_eq!(addresses.next(), None);
+        assert_eq!(message.hold_off(), Some(Duration::from_secs(30)));
+
+        let mut buffer = [0; 64];
+        let packet = MessageBuilder::new_tcp(&mut buffer)
+            .unwrap()
+            .signal(SignalCode::Abort)
+            .no_token()
+            .unwrap()
+            .bad_csm_option(MAX_MESSAGE_SIZE)
+            .unwrap()
+            .payload(b"unsupported")
+            .unwrap()
+            .build();
+
+        let message = ReliableMessage::parse(packet).unwrap();
+        assert_eq!(message.signal_code(), Some(SignalCode::Abort));
+        assert_eq!(message.bad_csm_option(), Some(MAX_MESSAGE_SIZE));
+        assert_eq!(message.payload, Some(&b"unsupported"[..]));
+    }
+
+    #[test]
+    fn non_signal_codes() {
+        assert_eq!(SignalCode::try_from(coap_code!(7, 6)).ok(), None);
+        assert_eq!(u8::from(SignalCode::Pong), 0xE3);
+    }
+}
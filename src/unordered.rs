@@ -0,0 +1,199 @@
+use crate::builder::uint_to_minimal_bytes;
+use crate::error::CoapBuildError;
+use crate::{Complete, MessageBuilder, NeedsPayload};
+
+type BuilderResult<'buf, T> = core::result::Result<MessageBuilder<'buf, T>, CoapBuildError>;
+
+/// Value of a staged option.
+#[derive(Debug, Clone, Copy)]
+enum StagedValue<'v> {
+    Borrowed(&'v [u8]),
+    /// A minimally encoded unsigned integer, stored as the bytes from the given index.
+    Uint([u8; 8], usize),
+}
+
+impl StagedValue<'_> {
+    fn as_bytes(&self) -> &[u8] {
+        match self {
+            StagedValue::Borrowed(value) => value,
+            StagedValue::Uint(bytes, start) => &bytes[*start..],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct StagedOption<'v> {
+    number: u16,
+    value: StagedValue<'v>,
+}
+
+/// A builder that accepts up to `N` options in any order, and encodes them sorted by option number
+/// when the payload is added. Repeated options keep the order in which they were added.
+///
+/// Created with [`MessageBuilder::unordered`].
+///
+/// Source: [RFC 7252 3.1](https://datatracker.ietf.org/doc/html/rfc7252#section-3.1)
+pub struct UnorderedBuilder<'buf, 'v, const N: usize> {
+    builder: MessageBuilder<'buf, NeedsPayload>,
+    options: [Option<StagedOption<'v>>; N],
+    len: usize,
+}
+
+impl<'buf> MessageBuilder<'buf, NeedsPayload> {
+    /// Stage up to `N` further options in any order. Staged options must not be numbered below
+    /// options already added to this builder.
+    pub fn unordered<'v, const N: usize>(self) -> UnorderedBuilder<'buf, 'v, N> {
+        UnorderedBuilder {
+            builder: self,
+            options: [const { None }; N],
+            len: 0,
+        }
+    }
+}
+
+impl<'buf, 'v, const N: usize> UnorderedBuilder<'buf, 'v, N> {
+    fn stage(mut self, number: u16, value: StagedValue<'v>) -> Result<Self, CoapBuildError> {
+        let slot = self
+            .options
+            .get_mut(self.len)
+            .ok_or(CoapBuildError::TooManyOptions(N))?;
+        *slot = Some(StagedOption { number, value });
+        self.len += 1;
+
+        Ok(self)
+    }
+
+    /// Stage an option.
+    pub fn option(
+        self,
+        option_number: impl Into<u16>,
+        value: &'v [u8],
+    ) -> Result<Self, CoapBuildError> {
+        self.stage(option_number.into(), StagedValue::Borrowed(value))
+    }
+
+    /// Stage an option with a UTF8 string value.
+    pub fn option_string(
+        self,
+        option_number: impl Into<u16>,
+        value: &'v str,
+    ) -> Result<Self, CoapBuildError> {
+        self.option(option_number, value.as_bytes())
+    }
+
+    /// Stage an option with an unsigned integer value, encoded with minimal bytes.
+    pub fn option_uint(
+        self,
+        option_number: impl Into<u16>,
+        value: impl Into<u64>,
+    ) -> Result<Self, CoapBuildError> {
+        let (bytes, start) = uint_to_minimal_bytes(value.into());
+        self.stage(option_number.into(), StagedValue::Uint(bytes, start))
+    }
+
+    /// Sort the staged options and encode them, returning to the regular builder so that further
+    /// options numbered at or above the last staged option can be added.
+    pub fn finish(mut self) -> BuilderResult<'buf, NeedsPayload> {
+        let options = &mut self.options[..self.len];
+
+        // Insertion sort, which is stable and needs no allocation.
+        for i in 1..options.len() {
+            let mut j = i;
+            while j > 0 && number_of(&options[j - 1]) > number_of(&options[j]) {
+                options.swap(j - 1, j);
+                j -= 1;
+            }
+        }
+
+        let mut builder = self.builder;
+        for option in options.iter().flatten() {
+            builder = builder.option(option.number, option.value.as_bytes())?;
+        }
+
+        Ok(builder)
+    }
+
+    /// Encode the staged options and add a payload.
+    pub fn payload(self, payload: &[u8]) -> BuilderResult<'buf, Complete> {
+        self.finish()?.payload(payload)
+    }
+
+    /// Encode the staged options without adding a payload.
+    pub fn no_payload(self) -> BuilderResult<'buf, Complete> {
+        Ok(self.finish()?.no_payload())
+    }
+}
+
+fn number_of(option: &Option<StagedOption<'_>>) -> u16 {
+    option.map_or(u16::MAX, |option| option.number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Message, MessageType, OptionNumber, RequestCode};
+
+    #[test]
+    fn sorts_options_stably() -> Result<(), CoapBuildError> {
+        let mut buffer = [0; 64];
+        let packet = MessageBuilder::new(&mut buffer)?
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(0x1234)
+            .no_token()?
+            .unordered::<6>()
+            .option_uint(OptionNumber::Accept, 50u16)?
+            .option_string(OptionNumber::UriPath, "b")?
+            .option_string(OptionNumber::UriQuery, "x=1")?
+            .option_string(OptionNumber::UriPath, "a")?
+            .option_string(OptionNumber::UriHost, "example.com")?
+            .option_string(OptionNumber::UriPath, "c")?
+            .payload(b"hi")?
+            .build();
+
+        let message = Message::parse(packet).unwrap();
+        let mut options = message.options.into_iter();
+        let mut next = || {
+            let option = options.next().unwrap();
+            (option.number, option.value)
+        };
+        assert_eq!(next(), (OptionNumber::UriHost, &b"example.com"[..]));
+        assert_eq!(next(), (OptionNumber::UriPath, &b"b"[..]));
+        assert_eq!(next(), (OptionNumber::UriPath, &b"a"[..]));
+        assert_eq!(next(), (OptionNumber::UriPath, &b"c"[..]));
+        assert_eq!(next(), (OptionNumber::UriQuery, &b"x=1"[..]));
+        assert_eq!(next(), (OptionNumber::Accept, &[50][..]));
+        assert_eq!(message.payload, Some(&b"hi"[..]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn limits_staged_options() -> Result<(), CoapBuildError> {
+        let mut buffer = [0; 64];
+        let result = MessageBuilder::new(&mut buffer)?
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(0x1234)
+            .no_token()?
+            .unordered::<1>()
+            .option_uint(OptionNumber::Accept, 50u16)?
+            .option_uint(OptionNumber::ContentFormat, 0u16);
+        assert!(matches!(result, Err(CoapBuildError::TooManyOptions(1))));
+
+        // Staged options still follow options added beforehand.
+        let mut buffer = [0; 64];
+        let result = MessageBuilder::new(&mut buffer)?
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(0x1234)
+            .no_token()?
+            .option_uint(OptionNumber::Accept, 50u16)?
+            .unordered::<2>()
+            .option_uint(OptionNumber::ContentFormat, 0u16)?
+            .no_payload();
+        assert!(matches!(
+            result,
+            Err(CoapBuildError::OptionNumberOutOfOrder)
+        ));
+
+        Ok(())
+    }
+}
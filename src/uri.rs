@@ -0,0 +1,632 @@
+use crate::error::CoapUriError;
+use crate::link_format::format_uint;
+use crate::{Message, MessageBuilder, NeedsPayload, OptionNumber};
+
+type UriResult<T> = core::result::Result<T, CoapUriError>;
+
+/// URI scheme of a CoAP resource.
+///
+/// Source: [RFC 7252 6](https://datatracker.ietf.org/doc/html/rfc7252#section-6)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum UriScheme {
+    /// `coap`, for CoAP over UDP.
+    ///
+    /// Source: [RFC 7252 6.1](https://datatracker.ietf.org/doc/html/rfc7252#section-6.1)
+    Coap,
+    /// `coaps`, for CoAP over DTLS.
+    ///
+    /// Source: [RFC 7252 6.2](https://datatracker.ietf.org/doc/html/rfc7252#section-6.2)
+    Coaps,
+}
+
+impl UriScheme {
+    /// Returns the scheme name as used in URIs.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            UriScheme::Coap => "coap",
+            UriScheme::Coaps => "coaps",
+        }
+    }
+
+    /// Returns the port used when a URI does not specify one.
+    pub fn default_port(self) -> u16 {
+        match self {
+            UriScheme::Coap => 5683,
+            UriScheme::Coaps => 5684,
+        }
+    }
+
+    /// Returns the scheme with the given name, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        [UriScheme::Coap, UriScheme::Coaps]
+            .into_iter()
+            .find(|scheme| scheme.as_str().eq_ignore_ascii_case(name))
+    }
+}
+
+fn hex_value(byte: u8) -> Option<u8> {
+    match byte {
+        b'0'..=b'9' => Some(byte - b'0'),
+        b'a'..=b'f' => Some(byte - b'a' + 10),
+        b'A'..=b'F' => Some(byte - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Returns the length of `s` after percent-decoding, checking that every `%` is followed by two
+/// hexadecimal digits.
+fn decoded_len(s: &str) -> UriResult<usize> {
+    let bytes = s.as_bytes();
+    let mut len = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let valid = bytes.get(i + 1).copied().and_then(hex_value).is_some()
+                && bytes.get(i + 2).copied().and_then(hex_value).is_some();
+            if !valid {
+                return Err(CoapUriError::InvalidPercentEncoding);
+            }
+            i += 3;
+        } else {
+            i += 1;
+        }
+        len += 1;
+    }
+
+    Ok(len)
+}
+
+/// Percent-decode `s` into `dst`, which must be exactly [`decoded_len`] bytes long.
+fn decode_into(s: &str, dst: &mut [u8]) {
+    let bytes = s.as_bytes();
+    let mut i = 0;
+
+    for byte in dst.iter_mut() {
+        if bytes[i] == b'%' {
+            let high = hex_value(bytes[i + 1]).unwrap_or(0);
+            let low = hex_value(bytes[i + 2]).unwrap_or(0);
+            *byte = (high << 4) | low;
+            i += 3;
+        } else {
+            *byte = bytes[i];
+            i += 1;
+        }
+    }
+}
+
+fn is_unreserved(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~')
+}
+
+fn is_sub_delim(byte: u8) -> bool {
+    matches!(
+        byte,
+        b'!' | b'$' | b'&' | b'\'' | b'(' | b')' | b'*' | b'+' | b',' | b';' | b'='
+    )
+}
+
+/// Characters left as they are in a path segment.
+fn is_segment_char(byte: u8) -> bool {
+    is_unreserved(byte) || is_sub_delim(byte) || matches!(byte, b':' | b'@')
+}
+
+/// Characters left as they are in a query argument. `&` separates arguments, so it is encoded.
+fn is_query_char(byte: u8) -> bool {
+    (is_segment_char(byte) || matches!(byte, b'/' | b'?')) && byte != b'&'
+}
+
+/// Characters left as they are in a host, including the brackets of IP literals.
+fn is_host_char(byte: u8) -> bool {
+    is_unreserved(byte) || is_sub_delim(byte) || matches!(byte, b'[' | b']' | b':')
+}
+
+/// A `coap` or `coaps` URI split into its components. Components are kept percent-encoded.
+///
+/// Source: [RFC 7252 6](https://datatracker.ietf.org/doc/html/rfc7252#section-6)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CoapUri<'a> {
+    /// Scheme of the URI
+    pub scheme: UriScheme,
+    /// Host of the URI. IPv6 literals keep their enclosing brackets.
+    pub host: &'a str,
+    /// Port of the URI, if given explicitly
+    pub port: Option<u16>,
+    /// Path of the URI, which is either empty or starts with `/`
+    pub path: &'a str,
+    /// Query of the URI, without the leading `?`
+    pub query: Option<&'a str>,
+}
+
+impl<'a> CoapUri<'a> {
+    /// Split a `coap` or `coaps` URI into its components.
+    ///
+    /// Source: [RFC 7252 6.4](https://datatracker.ietf.org/doc/html/rfc7252#section-6.4)
+    pub fn parse(uri: &'a str) -> UriResult<Self> {
+        let (scheme, rest) = uri.split_once("://").ok_or(CoapUriError::NotAbsolute)?;
+        let scheme = UriScheme::from_name(scheme).ok_or(CoapUriError::UnsupportedScheme)?;
+
+        if rest.contains('#') {
+            return Err(CoapUriError::Fragment);
+        }
+
+        let authority_end = rest.find(['/', '?']).unwrap_or(rest.len());
+        let (authority, rest) = rest.split_at(authority_end);
+
+        // CoAP URIs have no user information.
+        if authority.contains('@') {
+            return Err(CoapUriError::InvalidHost);
+        }
+
+        let host_end = if authority.starts_with('[') {
+            authority.find(']').ok_or(CoapUriError::InvalidHost)? + 1
+        } else {
+            authority.find(':').unwrap_or(authority.len())
+        };
+        let (host, port) = authority.split_at(host_end);
+
+        if host.is_empty() {
+            return Err(CoapUriError::InvalidHost);
+        }
+        decoded_len(host)?;
+
+        let port = match port {
+            "" | ":" => None,
+            port => {
+                let digits = port.strip_prefix(':').ok_or(CoapUriError::InvalidHost)?;
+                Some(digits.parse().map_err(|_| CoapUriError::InvalidPort)?)
+            }
+        };
+
+        let (path, query) = match rest.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (rest, None),
+        };
+        decoded_len(path)?;
+        if let Some(query) = query {
+            decoded_len(query)?;
+        }
+
+        Ok(CoapUri {
+            scheme,
+            host,
+            port,
+            path,
+            query,
+        })
+    }
+
+    /// Returns the port of the URI, or the default port of its scheme.
+    pub fn port_or_default(&self) -> u16 {
+        self.port.unwrap_or(self.scheme.default_port())
+    }
+
+    /// Check if the host is an IP literal or IPv4 address rather than a name.
+    pub fn is_ip_literal(&self) -> bool {
+        if self.host.starts_with('[') {
+            return true;
+        }
+
+        let mut octets = 0;
+        let valid = self.host.split('.').all(|octet| {
+            octets += 1;
+            (1..=3).contains(&octet.len())
+                && octet.bytes().all(|b| b.is_ascii_digit())
+                && octet.parse::<u8>().is_ok()
+        });
+
+        valid && octets == 4
+    }
+
+    /// Returns the percent-encoded segments of the path. An empty path or `/` has no segments.
+    pub fn path_segments(&self) -> impl Iterator<Item = &'a str> {
+        let path = self.path.strip_prefix('/').unwrap_or(self.path);
+        path.split('/').filter(move |_| !path.is_empty())
+    }
+
+    /// Returns the percent-encoded `&`-separated arguments of the query.
+    pub fn query_arguments(&self) -> impl Iterator<Item = &'a str> {
+        let query = self.query.unwrap_or("");
+        query.split('&').filter(move |_| !query.is_empty())
+    }
+
+    /// Add the Uri-Host, Uri-Port, Uri-Path and Uri-Query options describing this URI to a
+    /// request, percent-decoding their values.
+    ///
+    /// Uri-Host is added when the host is a name rather than an IP literal, and Uri-Port when the
+    /// URI names a port other than the default of its scheme. Only options numbered below 3
+    /// (Uri-Host) may be added to the builder beforehand.
+    ///
+    /// Source: [RFC 7252 6.4](https://datatracker.ietf.org/doc/html/rfc7252#section-6.4)
+    pub fn write_options<'buf>(
+        &self,
+        builder: MessageBuilder<'buf, NeedsPayload>,
+    ) -> UriResult<MessageBuilder<'buf, NeedsPayload>> {
+        let mut builder = builder;
+        for number in [
+            OptionNumber::UriHost,
+            OptionNumber::UriPort,
+            OptionNumber::UriPath,
+            OptionNumber::UriQuery,
+        ] {
+            builder = self.write_option(number, builder)?;
+        }
+
+        Ok(builder)
+    }
+
+    /// Add the options of this URI numbered `number`, which must be one of Uri-Host, Uri-Port,
+    /// Uri-Path or Uri-Query.
+    pub(crate) fn write_option<'buf>(
+        &self,
+        number: OptionNumber,
+        builder: MessageBuilder<'buf, NeedsPayload>,
+    ) -> UriResult<MessageBuilder<'buf, NeedsPayload>> {
+        let mut builder = builder;
+
+        match number {
+            OptionNumber::UriHost if !self.is_ip_literal() => {
+                let host = self.host;
+                builder = builder.option_with(number, decoded_len(host)?, |dst| {
+                    decode_into(host, dst);
+                    dst.make_ascii_lowercase();
+                })?;
+            }
+            OptionNumber::UriPort => {
+                if let Some(port) = self.port.filter(|&port| port != self.scheme.default_port()) {
+                    builder = builder.option_uint(number, port)?;
+                }
+            }
+            OptionNumber::UriPath => {
+                for segment in self.path_segments() {
+                    builder = builder.option_with(number, decoded_len(segment)?, |dst| {
+                        decode_into(segment, dst)
+                    })?;
+                }
+            }
+            OptionNumber::UriQuery => {
+                for argument in self.query_arguments() {
+                    builder = builder.option_with(number, decoded_len(argument)?, |dst| {
+                        decode_into(argument, dst)
+                    })?;
+                }
+            }
+            _ => {}
+        }
+
+        Ok(builder)
+    }
+}
+
+/// Writes a URI into a caller-provided buffer.
+struct UriBuffer<'b> {
+    buffer: &'b mut [u8],
+    len: usize,
+}
+
+impl<'b> UriBuffer<'b> {
+    fn push(&mut self, bytes: &[u8]) -> UriResult<()> {
+        let end = self.len + bytes.len();
+        if end > self.buffer.len() {
+            return Err(CoapUriError::BufferTooSmall);
+        }
+
+        self.buffer[self.len..end].copy_from_slice(bytes);
+        self.len = end;
+
+        Ok(())
+    }
+
+    /// Push `bytes`, percent-encoding every byte for which `allowed` returns `false`.
+    fn push_encoded(&mut self, bytes: &[u8], allowed: fn(u8) -> bool) -> UriResult<()> {
+        const HEX: &[u8; 16] = b"0123456789ABCDEF";
+
+        for &byte in bytes {
+            if allowed(byte) {
+                self.push(&[byte])?;
+            } else {
+                self.push(&[b'%', HEX[(byte >> 4) as usize], HEX[(byte & 0x0F) as usize]])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finish(self) -> UriResult<&'b str> {
+        core::str::from_utf8(&self.buffer[..self.len]).map_err(|_| CoapUriError::InvalidUtf8)
+    }
+}
+
+impl<'a> Message<'a> {
+    /// Reconstruct the URI of a request from its options into `buffer`.
+    ///
+    /// A Proxy-Uri option is returned as it is. Otherwise the scheme is taken from the
+    /// Proxy-Scheme option or `scheme`, and the host from the Uri-Host option or `destination`,
+    /// which should be the IP literal of the address the request was received on, such as
+    /// `192.0.2.1` or `[2001:db8::1]`. A Uri-Port option that does not fit in 16 bits is rejected
+    /// with [`CoapUriError::InvalidPort`].
+    ///
+    /// Source: [RFC 7252 6.5](https://datatracker.ietf.org/doc/html/rfc7252#section-6.5)
+    pub fn write_uri<'b>(
+        &self,
+        scheme: UriScheme,
+        destination: &str,
+        buffer: &'b mut [u8],
+    ) -> UriResult<&'b str> {
+        let mut uri = UriBuffer { buffer, len: 0 };
+
+        if let Some(proxy_uri) = self.find_option(OptionNumber::ProxyUri) {
+            uri.push(proxy_uri)?;
+            return uri.finish();
+        }
+
+        let scheme_name = match self.find_option(OptionNumber::ProxyScheme) {
+            Some(name) => core::str::from_utf8(name).map_err(|_| CoapUriError::InvalidUtf8)?,
+            None => scheme.as_str(),
+        };
+        let default_port = UriScheme::from_name(scheme_name).map(UriScheme::default_port);
+
+        uri.push(scheme_name.as_bytes())?;
+        uri.push(b"://")?;
+
+        match self.find_option(OptionNumber::UriHost) {
+            Some(host) => uri.push_encoded(host, is_host_char)?,
+            None => uri.push(destination.as_bytes())?,
+        }
+
+        let port = self
+            .options
+            .into_iter()
+            .find(|opt| opt.number == OptionNumber::UriPort)
+            .map(|opt| {
+                opt.as_uint()
+                    .and_then(|port| u16::try_from(port).ok())
+                    .ok_or(CoapUriError::InvalidPort)
+            })
+            .transpose()?;
+        if let Some(port) = port.filter(|&port| Some(port) != default_port) {
+            let mut digits = [0; 10];
+            uri.push(b":")?;
+            uri.push(format_uint(port.into(), &mut digits))?;
+        }
+
+        let mut has_path = false;
+        for segment in self.options_with(OptionNumber::UriPath) {
+            uri.push(b"/")?;
+            uri.push_encoded(segment, is_segment_char)?;
+            has_path = true;
+        }
+        if !has_path {
+            uri.push(b"/")?;
+        }
+
+        for (i, argument) in self.options_with(OptionNumber::UriQuery).enumerate() {
+            uri.push(if i == 0 { b"?" } else { b"&" })?;
+            uri.push_encoded(argument, is_query_char)?;
+        }
+
+        uri.finish()
+    }
+
+    fn find_option(&self, number: OptionNumber) -> Option<&'a [u8]> {
+        self.options_with(number).next()
+    }
+
+    fn options_with(&self, number: OptionNumber) -> impl Iterator<Item = &'a [u8]> {
+        self.options
+            .into_iter()
+            .filter(move |opt| opt.number == number)
+            .map(|opt| opt.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MessageType, RequestCode};
+
+    #[test]
+    fn parse_components() {
+        let uri = CoapUri::parse("coaps://[2001:db8::1]:61616/a/b?x=1&y").unwrap();
+        assert_eq!(uri.scheme, UriScheme::Coaps);
+        assert_eq!(uri.host, "[2001:db8::1]");
+        assert_eq!(uri.port, Some(61616));
+        assert!(uri.is_ip_literal());
+        let mut segments = uri.path_segments();
+        assert_eq!(segments.next(), Some("a"));
+        assert_eq!(segments.next(), Some("b"));
+        assert_eq!(segments.next(), None);
+        assert_eq!(uri.query_arguments().count(), 2);
+
+        let uri = CoapUri::parse("COAP://example.com").unwrap();
+        assert_eq!(uri.scheme, UriScheme::Coap);
+        assert_eq!(uri.port_or_default(), 5683);
+        assert!(!uri.is_ip_literal());
+        assert_eq!(uri.path_segments().count(), 0);
+        assert!(CoapUri::parse("coap://192.0.2.1/").unwrap().is_ip_literal());
+        assert!(
+            !CoapUri::parse("coap://192.0.2.256/")
+                .unwrap()
+                .is_ip_literal()
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(CoapUri::parse("/a/b"), Err(CoapUriError::NotAbsolute));
+        assert_eq!(
+            CoapUri::parse("http://example.com/"),
+            Err(CoapUriError::UnsupportedScheme)
+        );
+        assert_eq!(
+            CoapUri::parse("coap://example.com/#top"),
+            Err(CoapUriError::Fragment)
+        );
+        assert_eq!(
+            CoapUri::parse("coap://user@example.com/"),
+            Err(CoapUriError::InvalidHost)
+        );
+        assert_eq!(
+            CoapUri::parse("coap://example.com:99999/"),
+            Err(CoapUriError::InvalidPort)
+        );
+        assert_eq!(
+            CoapUri::parse("coap://example.com/%7"),
+            Err(CoapUriError::InvalidPercentEncoding)
+        );
+    }
+
+    #[test]
+    fn equivalent_uris_produce_the_same_options() {
+        // Example from RFC 7252 6.3.
+        let first = CoapUri::parse("coap://example.com:5683/~sensors/temp.xml").unwrap();
+        let second = CoapUri::parse("coap://EXAMPLE.com/%7Esensors/temp.xml").unwrap();
+
+        let mut a = [0; 64];
+        let builder = MessageBuilder::new(&mut a)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(1)
+            .no_token()
+            .unwrap();
+        let first = first.write_options(builder).unwrap().no_payload().build();
+
+        let mut b = [0; 64];
+        let builder = MessageBuilder::new(&mut b)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(1)
+            .no_token()
+            .unwrap();
+        let second = second.write_options(builder).unwrap().no_payload().build();
+        assert_eq!(first, second);
+
+        let first = Message::parse(first).unwrap();
+        let mut options = first.options.into_iter();
+        let host = options.next().unwrap();
+        assert_eq!(host.number, OptionNumber::UriHost);
+        assert_eq!(host.as_str(), Ok("example.com"));
+        assert_eq!(options.next().unwrap().as_str(), Ok("~sensors"));
+        assert_eq!(options.next().unwrap().as_str(), Ok("temp.xml"));
+        assert!(options.next().is_none());
+    }
+
+    #[test]
+    fn decompose_and_recompose() {
+        let mut buffer = [0; 64];
+        let builder = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(1)
+            .no_token()
+            .unwrap();
+        let packet = CoapUri::parse("coap://example.com:8080/a%20b/c?q=%26&r")
+            .unwrap()
+            .write_options(builder)
+            .unwrap()
+            .no_payload()
+            .build();
+        let message = Message::parse(packet).unwrap();
+
+        let mut uri = [0; 64];
+        assert_eq!(
+            message.write_uri(UriScheme::Coap, "192.0.2.1", &mut uri),
+            Ok("coap://example.com:8080/a%20b/c?q=%26&r")
+        );
+
+        let mut buffer = [0; 64];
+        let builder = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(1)
+            .no_token()
+            .unwrap();
+        let packet = CoapUri::parse("coaps://[2001:db8::1]")
+            .unwrap()
+            .write_options(builder)
+            .unwrap()
+            .no_payload()
+            .build();
+        let message = Message::parse(packet).unwrap();
+        let mut uri = [0; 64];
+        assert_eq!(
+            message.write_uri(UriScheme::Coaps, "[2001:db8::1]", &mut uri),
+            Ok("coaps://[2001:db8::1]/")
+        );
+
+        let mut uri = [0; 8];
+        assert_eq!(
+            message.write_uri(UriScheme::Coaps, "[2001:db8::1]", &mut uri),
+            Err(CoapUriError::BufferTooSmall)
+        );
+
+        // 0x011633 must not be truncated to the default port 5683 (0x1633).
+        let mut buffer = [0; 64];
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(1)
+            .no_token()
+            .unwrap()
+            .option_uint(OptionNumber::UriPort, 0x01_1633u32)
+            .unwrap()
+            .no_payload()
+            .build();
+        let mut uri = [0; 64];
+        assert_eq!(
+            Message::parse(packet)
+                .unwrap()
+                .write_uri(UriScheme::Coap, "192.0.2.1", &mut uri),
+            Err(CoapUriError::InvalidPort)
+        );
+    }
+
+    #[test]
+    fn recompose_proxy_requests() {
+        let mut buffer = [0; 64];
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(1)
+            .no_token()
+            .unwrap()
+            .option_string(OptionNumber::ProxyUri, "http://example.org/x")
+            .unwrap()
+            .no_payload()
+            .build();
+        let mut uri = [0; 64];
+        assert_eq!(
+            Message::parse(packet)
+                .unwrap()
+                .write_uri(UriScheme::Coap, "192.0.2.1", &mut uri),
+            Ok("http://example.org/x")
+        );
+
+        let mut buffer = [0; 64];
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(1)
+            .no_token()
+            .unwrap()
+            .option_string(OptionNumber::UriHost, "example.org")
+            .unwrap()
+            .option_uint(OptionNumber::UriPort, 5683u16)
+            .unwrap()
+            .option_string(OptionNumber::UriPath, "x")
+            .unwrap()
+            .option_string(OptionNumber::ProxyScheme, "http")
+            .unwrap()
+            .no_payload()
+            .build();
+        let mut uri = [0; 64];
+        assert_eq!(
+            Message::parse(packet)
+                .unwrap()
+                .write_uri(UriScheme::Coap, "192.0.2.1", &mut uri),
+            Ok("http://example.org:5683/x")
+        );
+    }
+}
@@ -0,0 +1,350 @@
+use crate::error::CoapValidationError;
+use crate::{Message, OptionNumber};
+
+/// Format of an option value.
+///
+/// Source: [RFC 7252 3.2](https://datatracker.ietf.org/doc/html/rfc7252#section-3.2)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum OptionFormat {
+    /// A zero-length value.
+    Empty,
+    /// An opaque sequence of bytes.
+    Opaque,
+    /// A non-negative integer in network byte order, using as few bytes as possible.
+    Uint,
+    /// A UTF-8 string.
+    String,
+}
+
+/// Definition of an option in the registry used by [`Message::validate`].
+///
+/// Source: [RFC 7252 5.10](https://datatracker.ietf.org/doc/html/rfc7252#section-5.10)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct OptionDefinition {
+    /// Format of the option value
+    pub format: OptionFormat,
+    /// Minimum length of the option value in bytes
+    pub min_length: u16,
+    /// Maximum length of the option value in bytes
+    pub max_length: u16,
+    /// Whether the option may occur more than once in a message
+    pub repeatable: bool,
+}
+
+impl OptionDefinition {
+    const fn new(format: OptionFormat, min_length: u16, max_length: u16, repeatable: bool) -> Self {
+        Self {
+            format,
+            min_length,
+            max_length,
+            repeatable,
+        }
+    }
+}
+
+impl OptionNumber {
+    /// Returns the definition of the option from the built-in registry, or `None` for an
+    /// unrecognized option.
+    ///
+    /// Source: [RFC 7252 5.10](https://datatracker.ietf.org/doc/html/rfc7252#section-5.10)
+    pub fn definition(&self) -> Option<OptionDefinition> {
+        use OptionFormat::*;
+
+        let definition = match self {
+            OptionNumber::IfMatch => OptionDefinition::new(Opaque, 0, 8, true),
+            OptionNumber::UriHost => OptionDefinition::new(String, 1, 255, false),
+            OptionNumber::Etag => OptionDefinition::new(Opaque, 1, 8, true),
+            OptionNumber::IfNoneMatch => OptionDefinition::new(Empty, 0, 0, false),
+            OptionNumber::Observe => OptionDefinition::new(Uint, 0, 3, false),
+            OptionNumber::UriPort => OptionDefinition::new(Uint, 0, 2, false),
+            OptionNumber::LocationPath => OptionDefinition::new(String, 0, 255, true),
+            OptionNumber::Oscore => OptionDefinition::new(Opaque, 0, 255, false),
+            OptionNumber::UriPath => OptionDefinition::new(String, 0, 255, true),
+            OptionNumber::ContentFormat => OptionDefinition::new(Uint, 0, 2, false),
+            OptionNumber::MaxAge => OptionDefinition::new(Uint, 0, 4, false),
+            OptionNumber::UriQuery => OptionDefinition::new(String, 0, 255, true),
+            OptionNumber::HopLimit => OptionDefinition::new(Uint, 1, 1, false),
+            OptionNumber::Accept => OptionDefinition::new(Uint, 0, 2, false),
+            OptionNumber::QBlock1 => OptionDefinition::new(Uint, 0, 3, false),
+            OptionNumber::LocationQuery => OptionDefinition::new(String, 0, 255, true),
+            OptionNumber::Block2 | OptionNumber::Block1 => OptionDefinition::new(Uint, 0, 3, false),
+            OptionNumber::Size2 | OptionNumber::Size1 => OptionDefinition::new(Uint, 0, 4, false),
+            OptionNumber::QBlock2 => OptionDefinition::new(Uint, 0, 3, true),
+            OptionNumber::ProxyUri => OptionDefinition::new(String, 1, 1034, false),
+            OptionNumber::ProxyScheme => OptionDefinition::new(String, 1, 255, false),
+            OptionNumber::Echo => OptionDefinition::new(Opaque, 1, 40, false),
+            OptionNumber::NoResponse => OptionDefinition::new(Uint, 0, 1, false),
+            OptionNumber::RequestTag => OptionDefinition::new(Opaque, 0, 8, true),
+            OptionNumber::UnknownOption(_) => return None,
+        };
+
+        Some(definition)
+    }
+}
+
+impl<'a> Message<'a> {
+    /// Parse a CoAP message and [validate](Message::validate) its options.
+    pub fn parse_strict(buffer: &'a [u8]) -> Result<Self, CoapValidationError> {
+        let message = Message::parse(buffer)?;
+        message.validate()?;
+
+        Ok(message)
+    }
+
+    /// Check every recognized option against its [definition](OptionNumber::definition): the
+    /// value length must be within bounds, uint values must be minimally encoded, string values
+    /// must be UTF-8, and non-repeatable options must occur at most once. Unrecognized options are
+    /// not checked.
+    ///
+    /// Source: [RFC 7252 5.4](https://datatracker.ietf.org/doc/html/rfc7252#section-5.4),
+    /// [RFC 7252 5.10](https://datatracker.ietf.org/doc/html/rfc7252#section-5.10)
+    pub fn validate(&self) -> Result<(), CoapValidationError> {
+        let mut previous = None;
+
+        for option in self.options {
+            let number = u16::from(option.number);
+            let repeated = previous == Some(number);
+            previous = Some(number);
+
+            let Some(definition) = option.number.definition() else {
+                continue;
+            };
+
+            if repeated && !definition.repeatable {
+                return Err(CoapValidationError::NotRepeatable(number));
+            }
+
+            let length = option.value.len();
+            if length < definition.min_length as usize || length > definition.max_length as usize {
+                return Err(CoapValidationError::InvalidLength {
+                    option: number,
+                    length,
+                });
+            }
+
+            match definition.format {
+                OptionFormat::Uint if option.value.first() == Some(&0) => {
+                    return Err(CoapValidationError::NonMinimalUint(number));
+                }
+                OptionFormat::String if option.as_str().is_err() => {
+                    return Err(CoapValidationError::InvalidString(number));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the first critical option that is not in `understood`. A Confirmable request
+    /// carrying such an option must be rejected; see [`MessageBuilder::bad_option_reply`].
+    ///
+    /// Source: [RFC 7252 5.4.1](https://datatracker.ietf.org/doc/html/rfc7252#section-5.4.1)
+    ///
+    /// [`MessageBuilder::bad_option_reply`]: crate::MessageBuilder::bad_option_reply
+    pub fn unrecognized_critical_option(
+        &self,
+        understood: &[OptionNumber],
+    ) -> Option<OptionNumber> {
+        self.first_unrecognized_critical(|number| understood.contains(&number))
+    }
+
+    pub(crate) fn first_unrecognized_critical(
+        &self,
+        understood: impl Fn(OptionNumber) -> bool,
+    ) -> Option<OptionNumber> {
+        self.options
+            .into_iter()
+            .find(|option| option.is_critical() && !understood(option.number))
+            .map(|option| option.number)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MessageBuilder, MessageType, RequestCode};
+
+    #[test]
+    fn accepts_valid_options() {
+        let mut buffer = [0; 64];
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(1)
+            .no_token()
+            .unwrap()
+            .option(OptionNumber::Etag, &[1, 2, 3])
+            .unwrap()
+            .option(OptionNumber::Etag, &[4])
+            .unwrap()
+            .option(OptionNumber::UriPort, &[0x16, 0x33])
+            .unwrap()
+            .option(OptionNumber::UriPath, b"a")
+            .unwrap()
+            .option(OptionNumber::UriPath, b"")
+            .unwrap()
+            .option(OptionNumber::ContentFormat, &[])
+            .unwrap()
+            .option(OptionNumber::UnknownOption(65000), &[0; 20])
+            .unwrap()
+            .no_payload()
+            .build();
+
+        assert!(Message::parse_strict(packet).is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_lengths() {
+        let mut buffer = [0; 64];
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(1)
+            .no_token()
+            .unwrap()
+            .option(OptionNumber::Etag, &[0xAB; 9])
+            .unwrap()
+            .no_payload()
+            .build();
+        assert_eq!(
+            Message::parse_strict(packet),
+            Err(CoapValidationError::InvalidLength {
+                option: 4,
+                length: 9
+            })
+        );
+
+        let mut buffer = [0; 64];
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(1)
+            .no_token()
+            .unwrap()
+            .option(OptionNumber::UriPort, &[1, 2, 3])
+            .unwrap()
+            .no_payload()
+            .build();
+        assert_eq!(
+            Message::parse_strict(packet).unwrap_err().option_number(),
+            Some(7)
+        );
+
+        let mut buffer = [0; 64];
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(1)
+            .no_token()
+            .unwrap()
+            .option(OptionNumber::IfNoneMatch, &[1])
+            .unwrap()
+            .no_payload()
+            .build();
+        assert!(Message::parse_strict(packet).is_err());
+        // The lenient parser still accepts the message.
+        assert!(Message::parse(packet).is_ok());
+    }
+
+    #[test]
+    fn rejects_repeated_options() {
+        let mut buffer = [0; 64];
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(1)
+            .no_token()
+            .unwrap()
+            .option(OptionNumber::ContentFormat, &[40])
+            .unwrap()
+            .option(OptionNumber::ContentFormat, &[50])
+            .unwrap()
+            .no_payload()
+            .build();
+        assert_eq!(
+            Message::parse_strict(packet),
+            Err(CoapValidationError::NotRepeatable(12))
+        );
+    }
+
+    #[test]
+    fn rejects_bad_values() {
+        let mut buffer = [0; 64];
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(1)
+            .no_token()
+            .unwrap()
+            .option(OptionNumber::MaxAge, &[0, 60])
+            .unwrap()
+            .no_payload()
+            .build();
+        assert_eq!(
+            Message::parse_strict(packet),
+            Err(CoapValidationError::NonMinimalUint(14))
+        );
+
+        let mut buffer = [0; 64];
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(1)
+            .no_token()
+            .unwrap()
+            .option(OptionNumber::UriPath, &[0xFF])
+            .unwrap()
+            .no_payload()
+            .build();
+        assert_eq!(
+            Message::parse_strict(packet),
+            Err(CoapValidationError::InvalidString(11))
+        );
+
+        assert_eq!(
+            Message::parse_strict(&[0x40]),
+            Err(CoapValidationError::Parse(
+                crate::CoapParseError::MessageTooShort
+            ))
+        );
+    }
+
+    #[test]
+    fn finds_unrecognized_critical_options() {
+        let mut buffer = [0; 64];
+        let packet = MessageBuilder::new(&mut buffer)
+            .unwrap()
+            .request(MessageType::Confirmable, RequestCode::Get)
+            .message_id(1)
+            .no_token()
+            .unwrap()
+            .option(OptionNumber::UriPath, b"a")
+            .unwrap()
+            .option(OptionNumber::UnknownOption(64), b"elective")
+            .unwrap()
+            .option(OptionNumber::UnknownOption(65), b"critical")
+            .unwrap()
+            .no_payload()
+            .build();
+        let message = Message::parse(packet).unwrap();
+
+        assert_eq!(
+            message.unrecognized_critical_option(&[OptionNumber::UriPath]),
+            Some(OptionNumber::UnknownOption(65))
+        );
+        assert_eq!(
+            message.unrecognized_critical_option(&[]),
+            Some(OptionNumber::UriPath)
+        );
+        assert_eq!(
+            message.unrecognized_critical_option(&[
+                OptionNumber::UriPath,
+                OptionNumber::UnknownOption(65)
+            ]),
+            None
+        );
+    }
+}